@@ -5,8 +5,11 @@ use std::time::Duration;
 
 use async_trait::async_trait;
 use futures_core::Stream;
-use input_event::PointerEvent;
-use tokio::time::{self, Instant, Interval};
+use input_event::{PointerEvent, scancode};
+use tokio::{
+    sync::mpsc::UnboundedSender,
+    time::{self, Instant, Interval},
+};
 
 use super::{Capture, CaptureError, CaptureEvent, Position};
 
@@ -42,10 +45,26 @@ impl Capture for DummyInputCapture {
         Ok(())
     }
 
+    async fn capture(&mut self, _pos: Position) -> Result<(), CaptureError> {
+        Ok(())
+    }
+
     async fn release(&mut self) -> Result<(), CaptureError> {
         Ok(())
     }
 
+    async fn center_cursor(&mut self) -> Result<(), CaptureError> {
+        Ok(())
+    }
+
+    async fn register_hotkeys(
+        &mut self,
+        _chords: Vec<Vec<scancode::Linux>>,
+        _tx: UnboundedSender<usize>,
+    ) -> Result<(), CaptureError> {
+        Err(CaptureError::HotkeysUnsupported)
+    }
+
     async fn terminate(&mut self) -> Result<(), CaptureError> {
         Ok(())
     }
@@ -62,7 +81,7 @@ impl Stream for DummyInputCapture {
         let event = match self.start {
             None => {
                 self.start.replace(current);
-                CaptureEvent::Begin
+                CaptureEvent::Begin(None)
             }
             Some(start) => {
                 let elapsed = start.elapsed();
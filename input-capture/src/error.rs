@@ -29,6 +29,12 @@ pub enum CaptureError {
     ActivationClosed,
     #[error("libei stream was closed")]
     EndOfStream,
+    #[error("capture backend does not support grabbing input on request")]
+    CaptureUnsupported,
+    #[error("capture backend can not move the cursor")]
+    WarpUnsupported,
+    #[error("capture backend does not support key bindings while input is not captured")]
+    HotkeysUnsupported,
     #[error("io error: `{0}`")]
     Io(#[from] std::io::Error),
     #[cfg(all(unix, feature = "libei", not(target_os = "macos")))]
@@ -0,0 +1,63 @@
+use std::collections::HashSet;
+
+use input_event::scancode;
+use tokio::sync::mpsc::UnboundedSender;
+
+/// key chords reported while input is not captured
+#[derive(Debug, Default)]
+pub(crate) struct Hotkeys {
+    /// registered key chords
+    chords: Vec<Vec<scancode::Linux>>,
+    /// channel triggered chords are reported on (by index)
+    tx: Option<UnboundedSender<usize>>,
+    /// keys currently pressed
+    pressed: HashSet<u32>,
+    /// keys that triggered a chord and are not passed on
+    swallowed: HashSet<u32>,
+}
+
+impl Hotkeys {
+    pub(crate) fn register(
+        &mut self,
+        chords: Vec<Vec<scancode::Linux>>,
+        tx: UnboundedSender<usize>,
+    ) {
+        self.chords = chords;
+        self.tx = Some(tx);
+        self.clear();
+    }
+
+    /// update the pressed keys with a key event and report the chord
+    /// it triggers, if any. Returns whether the event must be swallowed.
+    pub(crate) fn key(&mut self, key: u32, state: u8) -> bool {
+        if state == 0 {
+            self.pressed.remove(&key);
+            return self.swallowed.remove(&key);
+        }
+        // key repeat
+        if !self.pressed.insert(key) {
+            return self.swallowed.contains(&key);
+        }
+        let triggered = self
+            .chords
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| c.iter().any(|&k| k as u32 == key))
+            .filter(|(_, c)| c.iter().all(|&k| self.pressed.contains(&(k as u32))))
+            .max_by_key(|(_, c)| c.len())
+            .map(|(i, _)| i);
+        let (Some(index), Some(tx)) = (triggered, &self.tx) else {
+            return false;
+        };
+        // the receiving end is gone, when the input capture is dropped
+        let _ = tx.send(index);
+        self.swallowed.insert(key);
+        true
+    }
+
+    /// forget the pressed keys, e.g. because input was captured
+    pub(crate) fn clear(&mut self) {
+        self.pressed.clear();
+        self.swallowed.clear();
+    }
+}
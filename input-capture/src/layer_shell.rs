@@ -9,7 +9,7 @@ use std::{
     pin::Pin,
     task::{Context, Poll, ready},
 };
-use tokio::io::unix::AsyncFd;
+use tokio::{io::unix::AsyncFd, sync::mpsc::UnboundedSender};
 
 use std::{
     fs::File,
@@ -61,9 +61,9 @@ use wayland_client::{
     },
 };
 
-use input_event::{Event, KeyboardEvent, PointerEvent};
+use input_event::{Event, KeyboardEvent, PointerEvent, scancode};
 
-use crate::{CaptureError, CaptureEvent};
+use crate::{CaptureError, CaptureEvent, EdgePosition};
 
 use super::{
     Capture, Position,
@@ -149,6 +149,8 @@ struct Window {
     surface: WlSurface,
     layer_surface: ZwlrLayerSurfaceV1,
     pos: Position,
+    /// length of the output edge covered by the window
+    length: u32,
 }
 
 impl Window {
@@ -208,6 +210,7 @@ impl Window {
             buffer,
             surface,
             layer_surface,
+            length: width.max(height),
         }
     }
 }
@@ -628,6 +631,11 @@ impl Capture for LayerShellInputCapture {
         Ok(inner.flush_events()?)
     }
 
+    async fn capture(&mut self, _pos: Position) -> Result<(), CaptureError> {
+        // the pointer can only be locked once it entered a layer surface
+        Err(CaptureError::CaptureUnsupported)
+    }
+
     async fn release(&mut self) -> Result<(), CaptureError> {
         log::debug!("releasing pointer");
         let inner = self.0.get_mut();
@@ -635,6 +643,18 @@ impl Capture for LayerShellInputCapture {
         Ok(inner.flush_events()?)
     }
 
+    async fn center_cursor(&mut self) -> Result<(), CaptureError> {
+        Err(CaptureError::WarpUnsupported)
+    }
+
+    async fn register_hotkeys(
+        &mut self,
+        _chords: Vec<Vec<scancode::Linux>>,
+        _tx: UnboundedSender<usize>,
+    ) -> Result<(), CaptureError> {
+        Err(CaptureError::HotkeysUnsupported)
+    }
+
     async fn terminate(&mut self) -> Result<(), CaptureError> {
         Ok(())
     }
@@ -735,8 +755,8 @@ impl Dispatch<WlPointer, ()> for State {
             wl_pointer::Event::Enter {
                 serial,
                 surface,
-                surface_x: _,
-                surface_y: _,
+                surface_x,
+                surface_y,
             } => {
                 // get client corresponding to the focused surface
                 {
@@ -747,13 +767,22 @@ impl Dispatch<WlPointer, ()> for State {
                         return;
                     }
                 }
-                let pos = app
+                let window = app
                     .active_windows
                     .iter()
                     .find(|w| w.surface == surface)
-                    .map(|w| w.pos)
                     .unwrap();
-                app.pending_events.push_back((pos, CaptureEvent::Begin));
+                let offset = match window.pos {
+                    Position::Left | Position::Right => surface_y,
+                    Position::Top | Position::Bottom => surface_x,
+                };
+                let edge = EdgePosition {
+                    offset,
+                    length: window.length as f64,
+                };
+                let pos = window.pos;
+                app.pending_events
+                    .push_back((pos, CaptureEvent::Begin(Some(edge))));
             }
             wl_pointer::Event::Leave { .. } => {
                 /* There are rare cases, where when a window is opened in
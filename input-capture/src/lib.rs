@@ -8,6 +8,7 @@ use std::{
 use async_trait::async_trait;
 use futures::StreamExt;
 use futures_core::Stream;
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender, unbounded_channel};
 
 use input_event::{Event, KeyboardEvent, scancode};
 
@@ -33,20 +34,40 @@ mod x11;
 /// fallback input capture (does not produce events)
 mod dummy;
 
+#[cfg(any(windows, target_os = "macos"))]
+mod hotkey;
+
 pub type CaptureHandle = u64;
 
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum CaptureEvent {
-    /// capture on this capture handle is now active
-    Begin,
+    /// capture on this capture handle is now active,
+    /// the edge position is known if a barrier was crossed
+    Begin(Option<EdgePosition>),
     /// input event coming from capture handle
     Input(Event),
 }
 
+/// position on a screen edge, where a barrier was crossed
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct EdgePosition {
+    /// distance from the left / top end of the edge in pixels
+    pub offset: f64,
+    /// length of the edge in pixels
+    pub length: f64,
+}
+
+impl EdgePosition {
+    /// distance to the closest end of the edge in pixels
+    pub fn corner_distance(&self) -> f64 {
+        self.offset.min(self.length - self.offset).max(0.)
+    }
+}
+
 impl Display for CaptureEvent {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            CaptureEvent::Begin => write!(f, "begin capture"),
+            CaptureEvent::Begin(_) => write!(f, "begin capture"),
             CaptureEvent::Input(e) => write!(f, "{e}"),
         }
     }
@@ -165,12 +186,35 @@ impl InputCapture {
         Ok(())
     }
 
+    /// grab input and start capturing for the client with the given id
+    pub async fn capture(&mut self, id: CaptureHandle) -> Result<(), CaptureError> {
+        let pos = *self.id_map.get(&id).expect("no position for this handle");
+        log::debug!("capturing {id} @ {pos}");
+        self.capture.capture(pos).await
+    }
+
     /// release mouse
     pub async fn release(&mut self) -> Result<(), CaptureError> {
         self.pressed_keys.clear();
         self.capture.release().await
     }
 
+    /// move the (released) cursor to the center of the screen
+    pub async fn center_cursor(&mut self) -> Result<(), CaptureError> {
+        self.capture.center_cursor().await
+    }
+
+    /// report the given key chords while input is not captured.
+    /// A triggered chord is received as its index in `chords`.
+    pub async fn register_hotkeys(
+        &mut self,
+        chords: Vec<Vec<scancode::Linux>>,
+    ) -> Result<UnboundedReceiver<usize>, CaptureError> {
+        let (tx, rx) = unbounded_channel();
+        self.capture.register_hotkeys(chords, tx).await?;
+        Ok(rx)
+    }
+
     /// destroy the input capture
     pub async fn terminate(&mut self) -> Result<(), CaptureError> {
         self.capture.terminate().await
@@ -193,6 +237,11 @@ impl InputCapture {
         keys.iter().all(|k| self.pressed_keys.contains(k))
     }
 
+    /// keys currently pressed
+    pub fn pressed_keys(&self) -> impl Iterator<Item = scancode::Linux> + '_ {
+        self.pressed_keys.iter().copied()
+    }
+
     fn update_pressed_keys(&mut self, key: u32, state: u8) {
         if let Ok(scancode) = scancode::Linux::try_from(key) {
             log::debug!("key: {key}, state: {state}, scancode: {scancode:?}");
@@ -271,9 +320,22 @@ trait Capture: Stream<Item = Result<(Position, CaptureEvent), CaptureError>> + U
     /// destroy the client with the given id, if it exists
     async fn destroy(&mut self, pos: Position) -> Result<(), CaptureError>;
 
+    /// grab input as if the barrier at the given position was crossed
+    async fn capture(&mut self, pos: Position) -> Result<(), CaptureError>;
+
     /// release mouse
     async fn release(&mut self) -> Result<(), CaptureError>;
 
+    /// move the (released) cursor to the center of the screen
+    async fn center_cursor(&mut self) -> Result<(), CaptureError>;
+
+    /// report the given key chords on `tx` while input is not captured
+    async fn register_hotkeys(
+        &mut self,
+        chords: Vec<Vec<scancode::Linux>>,
+        tx: UnboundedSender<usize>,
+    ) -> Result<(), CaptureError>;
+
     /// destroy the input capture
     async fn terminate(&mut self) -> Result<(), CaptureError>;
 }
@@ -29,7 +29,7 @@ use std::{
 use tokio::{
     sync::{
         Notify,
-        mpsc::{self, Receiver, Sender},
+        mpsc::{self, Receiver, Sender, UnboundedSender},
     },
     task::JoinHandle,
 };
@@ -37,9 +37,9 @@ use tokio_util::sync::CancellationToken;
 
 use futures_core::Stream;
 
-use input_event::Event;
+use input_event::{Event, scancode};
 
-use crate::CaptureEvent;
+use crate::{CaptureEvent, EdgePosition};
 
 use super::{
     Capture as LanMouseInputCapture, Position,
@@ -407,7 +407,8 @@ async fn do_capture_session(
                     current_pos.replace(Some(pos));
 
                     // client entered => send event
-                    event_tx.send((pos, CaptureEvent::Begin)).await.expect("no channel");
+                    let edge = activated.cursor_position().and_then(|p| edge_position(&barriers, barrier_id, p));
+                    event_tx.send((pos, CaptureEvent::Begin(edge))).await.expect("no channel");
 
                     tokio::select! {
                         _ = notify_release.notified() => { /* capture release */
@@ -490,6 +491,26 @@ async fn release_capture<'a>(
     Ok(())
 }
 
+/// position of the cursor on the given barrier
+fn edge_position(barriers: &[ICBarrier], id: BarrierID, pos: (f32, f32)) -> Option<EdgePosition> {
+    let barrier = barriers.iter().find(|b| b.barrier_id == id)?;
+    let (x1, y1, x2, y2) = barrier.position;
+    let (x, y) = (pos.0 as f64, pos.1 as f64);
+    let edge = match x1 == x2 {
+        // vertical barrier
+        true => EdgePosition {
+            offset: y - y1 as f64,
+            length: (y2 - y1 + 1) as f64,
+        },
+        // horizontal barrier
+        false => EdgePosition {
+            offset: x - x1 as f64,
+            length: (x2 - x1 + 1) as f64,
+        },
+    };
+    Some(edge)
+}
+
 fn find_corresponding_client(barriers: &[ICBarrier], pos: (f32, f32)) -> BarrierID {
     barriers
         .iter()
@@ -578,11 +599,28 @@ impl LanMouseInputCapture for LibeiInputCapture<'_> {
         Ok(())
     }
 
+    async fn capture(&mut self, _pos: Position) -> Result<(), CaptureError> {
+        // the portal only activates capture when a pointer barrier is hit
+        Err(CaptureError::CaptureUnsupported)
+    }
+
     async fn release(&mut self) -> Result<(), CaptureError> {
         self.notify_release.notify_waiters();
         Ok(())
     }
 
+    async fn center_cursor(&mut self) -> Result<(), CaptureError> {
+        Err(CaptureError::WarpUnsupported)
+    }
+
+    async fn register_hotkeys(
+        &mut self,
+        _chords: Vec<Vec<scancode::Linux>>,
+        _tx: UnboundedSender<usize>,
+    ) -> Result<(), CaptureError> {
+        Err(CaptureError::HotkeysUnsupported)
+    }
+
     async fn terminate(&mut self) -> Result<(), CaptureError> {
         self.cancellation_token.cancel();
         let task = &mut self.capture_task;
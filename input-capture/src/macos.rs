@@ -1,4 +1,7 @@
-use super::{Capture, CaptureError, CaptureEvent, Position, error::MacosCaptureCreationError};
+use super::{
+    Capture, CaptureError, CaptureEvent, EdgePosition, Position, error::MacosCaptureCreationError,
+    hotkey::Hotkeys,
+};
 use async_trait::async_trait;
 use bitflags::bitflags;
 use core_foundation::{
@@ -9,7 +12,7 @@ use core_foundation::{
     string::{CFStringCreateWithCString, CFStringRef, kCFStringEncodingUTF8},
 };
 use core_graphics::{
-    base::{CGError, kCGErrorSuccess},
+    base::{CGError, kCGErrorFailure, kCGErrorSuccess},
     display::{CGDisplay, CGPoint},
     event::{
         CGEvent, CGEventFlags, CGEventTap, CGEventTapLocation, CGEventTapOptions,
@@ -18,7 +21,7 @@ use core_graphics::{
     event_source::{CGEventSource, CGEventSourceStateID},
 };
 use futures_core::Stream;
-use input_event::{BTN_LEFT, BTN_MIDDLE, BTN_RIGHT, Event, KeyboardEvent, PointerEvent};
+use input_event::{BTN_LEFT, BTN_MIDDLE, BTN_RIGHT, Event, KeyboardEvent, PointerEvent, scancode};
 use keycode::{KeyMap, KeyMapping};
 use libc::c_void;
 use once_cell::unsync::Lazy;
@@ -32,7 +35,7 @@ use std::{
 };
 use tokio::sync::{
     Mutex,
-    mpsc::{self, Receiver, Sender},
+    mpsc::{self, Receiver, Sender, UnboundedSender},
     oneshot,
 };
 
@@ -56,6 +59,8 @@ struct InputCaptureState {
     bounds: Bounds,
     /// current state of modifier keys
     modifier_state: XMods,
+    /// key chords reported while input is not captured
+    hotkeys: Hotkeys,
 }
 
 #[derive(Debug)]
@@ -64,6 +69,9 @@ enum ProducerEvent {
     Create(Position),
     Destroy(Position),
     Grab(Position),
+    Capture(Position),
+    CenterCursor,
+    Hotkeys(Vec<Vec<scancode::Linux>>, UnboundedSender<usize>),
     EventTapDisabled,
 }
 
@@ -75,6 +83,7 @@ impl InputCaptureState {
             enter_position: None,
             bounds: Bounds::default(),
             modifier_state: Default::default(),
+            hotkeys: Default::default(),
         };
         res.update_bounds()?;
         Ok(res)
@@ -98,6 +107,22 @@ impl InputCaptureState {
         None
     }
 
+    /// position on the edge of the display bounds, where the barrier was crossed
+    fn edge_position(&self, event: &CGEvent, position: Position) -> EdgePosition {
+        let location = event.location();
+        let (offset, length) = match position {
+            Position::Left | Position::Right => (
+                location.y - self.bounds.ymin,
+                self.bounds.ymax - self.bounds.ymin,
+            ),
+            Position::Top | Position::Bottom => (
+                location.x - self.bounds.xmin,
+                self.bounds.xmax - self.bounds.xmin,
+            ),
+        };
+        EdgePosition { offset, length }
+    }
+
     // Get the max bounds of all displays
     fn update_bounds(&mut self) -> Result<(), MacosCaptureCreationError> {
         let active_ids =
@@ -129,6 +154,16 @@ impl InputCaptureState {
         self.reset_cursor()
     }
 
+    /// start the input capture at the current cursor position
+    fn start_capture_at_cursor(&mut self) -> Result<(), CaptureError> {
+        let source = CGEventSource::new(CGEventSourceStateID::CombinedSessionState)
+            .map_err(|_| CaptureError::CoreGraphics(kCGErrorFailure))?;
+        let event =
+            CGEvent::new(source).map_err(|_| CaptureError::CoreGraphics(kCGErrorFailure))?;
+        self.enter_position = Some(event.location());
+        self.reset_cursor()
+    }
+
     /// resets the cursor to the position, where the capture started
     fn reset_cursor(&mut self) -> Result<(), CaptureError> {
         let pos = self.enter_position.expect("capture active");
@@ -147,6 +182,7 @@ impl InputCaptureState {
     async fn handle_producer_event(
         &mut self,
         producer_event: ProducerEvent,
+        event_tx: &Sender<(Position, CaptureEvent)>,
     ) -> Result<(), CaptureError> {
         log::debug!("handling event: {producer_event:?}");
         match producer_event {
@@ -162,6 +198,22 @@ impl InputCaptureState {
                     self.current_pos = Some(pos);
                 }
             }
+            ProducerEvent::Capture(pos) => {
+                if self.current_pos.is_none() && self.active_clients.contains(&pos) {
+                    self.start_capture_at_cursor()?;
+                    self.hide_cursor()?;
+                    self.current_pos = Some(pos);
+                    let _ = event_tx.send((pos, CaptureEvent::Begin(None))).await;
+                }
+            }
+            ProducerEvent::CenterCursor => {
+                let bounds = CGDisplay::main().bounds();
+                let center = CGPoint::new(
+                    bounds.origin.x + bounds.size.width / 2.,
+                    bounds.origin.y + bounds.size.height / 2.,
+                );
+                CGDisplay::warp_mouse_cursor_position(center).map_err(CaptureError::WarpCursor)?;
+            }
             ProducerEvent::Create(p) => {
                 self.active_clients.insert(p);
             }
@@ -174,6 +226,7 @@ impl InputCaptureState {
                 }
                 self.active_clients.remove(&p);
             }
+            ProducerEvent::Hotkeys(chords, tx) => self.hotkeys.register(chords, tx),
             ProducerEvent::EventTapDisabled => return Err(CaptureError::EventTapDisabled),
         };
         Ok(())
@@ -410,6 +463,7 @@ fn create_event_tap<'a>(
             // Are we in a client?
             if let Some(current_pos) = state.current_pos {
                 capture_position = Some(current_pos);
+                state.hotkeys.clear();
                 get_events(
                     &event_type,
                     cg_ev,
@@ -437,11 +491,40 @@ fn create_event_tap<'a>(
                     state
                         .start_capture(cg_ev, new_pos)
                         .unwrap_or_else(|e| log::warn!("{e}"));
-                    res_events.push(CaptureEvent::Begin);
+                    let edge = state.edge_position(cg_ev, new_pos);
+                    res_events.push(CaptureEvent::Begin(Some(edge)));
                     notify_tx
                         .blocking_send(ProducerEvent::Grab(new_pos))
                         .expect("Failed to send notification");
                 }
+            } else if matches!(
+                event_type,
+                CGEventType::KeyDown | CGEventType::KeyUp | CGEventType::FlagsChanged
+            ) {
+                // not capturing => only check for hotkeys
+                let mut key_events = vec![];
+                let state = &mut *state;
+                get_events(
+                    &event_type,
+                    cg_ev,
+                    &mut key_events,
+                    &mut state.modifier_state,
+                )
+                .unwrap_or_else(|e| log::warn!("{e}"));
+                let mut hotkey = false;
+                for event in key_events {
+                    if let CaptureEvent::Input(Event::Keyboard(KeyboardEvent::Key {
+                        key,
+                        state: key_state,
+                        ..
+                    })) = event
+                    {
+                        hotkey |= state.hotkeys.key(key, key_state);
+                    }
+                }
+                if hotkey {
+                    cg_ev.set_type(CGEventType::Null);
+                }
             }
 
             if let Some(pos) = capture_position {
@@ -524,6 +607,7 @@ impl MacOSInputCapture {
         log::info!("Enabling CGEvent tap");
         let event_tap_thread_state = state.clone();
         let event_tap_notify = notify_tx.clone();
+        let producer_event_tx = event_tx.clone();
         thread::spawn(move || {
             event_tap_thread(
                 event_tap_thread_state,
@@ -545,7 +629,7 @@ impl MacOSInputCapture {
                             break;
                         };
                         let mut state = state.lock().await;
-                        state.handle_producer_event(producer_event).await.unwrap_or_else(|e| {
+                        state.handle_producer_event(producer_event, &producer_event_tx).await.unwrap_or_else(|e| {
                             log::error!("Failed to handle producer event: {e}");
                         })
                     }
@@ -592,6 +676,15 @@ impl Capture for MacOSInputCapture {
         Ok(())
     }
 
+    async fn capture(&mut self, pos: Position) -> Result<(), CaptureError> {
+        let notify_tx = self.notify_tx.clone();
+        tokio::task::spawn_local(async move {
+            log::debug!("notifying Capture {pos}");
+            let _ = notify_tx.send(ProducerEvent::Capture(pos)).await;
+        });
+        Ok(())
+    }
+
     async fn release(&mut self) -> Result<(), CaptureError> {
         let notify_tx = self.notify_tx.clone();
         tokio::task::spawn_local(async move {
@@ -601,6 +694,27 @@ impl Capture for MacOSInputCapture {
         Ok(())
     }
 
+    async fn center_cursor(&mut self) -> Result<(), CaptureError> {
+        let notify_tx = self.notify_tx.clone();
+        tokio::task::spawn_local(async move {
+            log::debug!("notifying CenterCursor");
+            let _ = notify_tx.send(ProducerEvent::CenterCursor).await;
+        });
+        Ok(())
+    }
+
+    async fn register_hotkeys(
+        &mut self,
+        chords: Vec<Vec<scancode::Linux>>,
+        tx: UnboundedSender<usize>,
+    ) -> Result<(), CaptureError> {
+        let _ = self
+            .notify_tx
+            .send(ProducerEvent::Hotkeys(chords, tx))
+            .await;
+        Ok(())
+    }
+
     async fn terminate(&mut self) -> Result<(), CaptureError> {
         Ok(())
     }
@@ -4,8 +4,9 @@ use event_thread::EventThread;
 use futures::Stream;
 use std::pin::Pin;
 
+use input_event::scancode;
 use std::task::ready;
-use tokio::sync::mpsc::{Receiver, channel};
+use tokio::sync::mpsc::{Receiver, UnboundedSender, channel};
 
 use super::{Capture, CaptureError, CaptureEvent, Position};
 
@@ -29,11 +30,30 @@ impl Capture for WindowsInputCapture {
         Ok(())
     }
 
+    async fn capture(&mut self, pos: Position) -> Result<(), CaptureError> {
+        self.event_thread.capture(pos);
+        Ok(())
+    }
+
     async fn release(&mut self) -> Result<(), CaptureError> {
         self.event_thread.release_capture();
         Ok(())
     }
 
+    async fn center_cursor(&mut self) -> Result<(), CaptureError> {
+        self.event_thread.center_cursor();
+        Ok(())
+    }
+
+    async fn register_hotkeys(
+        &mut self,
+        chords: Vec<Vec<scancode::Linux>>,
+        tx: UnboundedSender<usize>,
+    ) -> Result<(), CaptureError> {
+        self.event_thread.register_hotkeys(chords, tx);
+        Ok(())
+    }
+
     async fn terminate(&mut self) -> Result<(), CaptureError> {
         Ok(())
     }
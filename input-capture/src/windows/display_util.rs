@@ -1,6 +1,6 @@
 use windows::Win32::Foundation::RECT;
 
-use crate::Position;
+use crate::{EdgePosition, Position};
 
 fn is_within_dp_region(point: (i32, i32), display: &RECT) -> bool {
     [
@@ -97,3 +97,25 @@ pub(crate) fn clamp_to_display_bounds(
     let (min_y, max_y) = (display.top, display.bottom - 1);
     (x.clamp(min_x, max_x), y.clamp(min_y, max_y))
 }
+
+/// position of the entry point on the edge of the display the cursor came from
+pub(crate) fn edge_position(
+    display_regions: &[RECT],
+    prev_point: (i32, i32),
+    entry_point: (i32, i32),
+    pos: Position,
+) -> EdgePosition {
+    let display = display_regions
+        .iter()
+        .find(|&d| is_within_dp_region(prev_point, d))
+        .unwrap();
+    let (x, y) = entry_point;
+    let (offset, length) = match pos {
+        Position::Left | Position::Right => (y - display.top, display.bottom - display.top),
+        Position::Top | Position::Bottom => (x - display.left, display.right - display.left),
+    };
+    EdgePosition {
+        offset: offset as f64,
+        length: length as f64,
+    }
+}
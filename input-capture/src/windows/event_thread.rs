@@ -6,9 +6,9 @@ use std::default::Default;
 use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
 use std::sync::{Arc, Condvar, Mutex};
 use std::thread;
-use tokio::sync::mpsc::Sender;
 use tokio::sync::mpsc::error::TrySendError;
-use windows::Win32::Foundation::{FALSE, HWND, LPARAM, LRESULT, RECT, WPARAM};
+use tokio::sync::mpsc::{Sender, UnboundedSender};
+use windows::Win32::Foundation::{FALSE, HWND, LPARAM, LRESULT, POINT, RECT, WPARAM};
 use windows::Win32::Graphics::Gdi::{
     DEVMODEW, DISPLAY_DEVICE_ATTACHED_TO_DESKTOP, DISPLAY_DEVICEW, ENUM_CURRENT_SETTINGS,
     EnumDisplayDevicesW, EnumDisplaySettingsW,
@@ -18,12 +18,13 @@ use windows::Win32::System::Threading::GetCurrentThreadId;
 use windows::core::{PCWSTR, w};
 
 use windows::Win32::UI::WindowsAndMessaging::{
-    CallNextHookEx, CreateWindowExW, DispatchMessageW, EDD_GET_DEVICE_INTERFACE_NAME, GetMessageW,
-    HOOKPROC, KBDLLHOOKSTRUCT, LLKHF_EXTENDED, MSG, MSLLHOOKSTRUCT, PostThreadMessageW,
-    RegisterClassW, SetWindowsHookExW, TranslateMessage, WH_KEYBOARD_LL, WH_MOUSE_LL, WINDOW_STYLE,
-    WM_DISPLAYCHANGE, WM_KEYDOWN, WM_KEYUP, WM_LBUTTONDOWN, WM_LBUTTONUP, WM_MBUTTONDOWN,
-    WM_MBUTTONUP, WM_MOUSEHWHEEL, WM_MOUSEMOVE, WM_MOUSEWHEEL, WM_RBUTTONDOWN, WM_RBUTTONUP,
-    WM_SYSKEYDOWN, WM_SYSKEYUP, WM_USER, WM_XBUTTONDOWN, WM_XBUTTONUP, WNDCLASSW, WNDPROC,
+    CallNextHookEx, CreateWindowExW, DispatchMessageW, EDD_GET_DEVICE_INTERFACE_NAME, GetCursorPos,
+    GetMessageW, GetSystemMetrics, HOOKPROC, KBDLLHOOKSTRUCT, LLKHF_EXTENDED, MSG, MSLLHOOKSTRUCT,
+    PostThreadMessageW, RegisterClassW, SM_CXSCREEN, SM_CYSCREEN, SetCursorPos, SetWindowsHookExW,
+    TranslateMessage, WH_KEYBOARD_LL, WH_MOUSE_LL, WINDOW_STYLE, WM_DISPLAYCHANGE, WM_KEYDOWN,
+    WM_KEYUP, WM_LBUTTONDOWN, WM_LBUTTONUP, WM_MBUTTONDOWN, WM_MBUTTONUP, WM_MOUSEHWHEEL,
+    WM_MOUSEMOVE, WM_MOUSEWHEEL, WM_RBUTTONDOWN, WM_RBUTTONUP, WM_SYSKEYDOWN, WM_SYSKEYUP, WM_USER,
+    WM_XBUTTONDOWN, WM_XBUTTONUP, WNDCLASSW, WNDPROC,
 };
 
 use input_event::{
@@ -32,6 +33,7 @@ use input_event::{
 };
 
 use super::{CaptureEvent, Position, display_util};
+use crate::hotkey::Hotkeys;
 
 pub(crate) struct EventThread {
     request_buffer: Arc<Mutex<Vec<ClientUpdate>>>,
@@ -62,6 +64,18 @@ impl EventThread {
         self.client_update(ClientUpdate::Destroy(pos));
     }
 
+    pub(crate) fn capture(&self, pos: Position) {
+        self.client_update(ClientUpdate::Capture(pos));
+    }
+
+    pub(crate) fn center_cursor(&self) {
+        self.client_update(ClientUpdate::CenterCursor);
+    }
+
+    pub(crate) fn register_hotkeys(&self, chords: Vec<Vec<Linux>>, tx: UnboundedSender<usize>) {
+        self.client_update(ClientUpdate::Hotkeys(chords, tx));
+    }
+
     fn exit(&self) {
         self.signal(RequestType::Exit);
     }
@@ -96,6 +110,9 @@ enum RequestType {
 enum ClientUpdate {
     Create(Position),
     Destroy(Position),
+    Capture(Position),
+    CenterCursor,
+    Hotkeys(Vec<Vec<Linux>>, UnboundedSender<usize>),
 }
 
 fn blocking_send_event(pos: Position, event: CaptureEvent) {
@@ -122,6 +139,8 @@ thread_local! {
     static PREV_POS: Cell<Option<(i32, i32)>> = const { Cell::new(None) };
     /// displays and generation counter
     static DISPLAYS: RefCell<(Vec<RECT>, i32)> = const { RefCell::new((Vec::new(), 0)) };
+    /// key chords reported while input is not captured
+    static HOTKEYS: RefCell<Hotkeys> = RefCell::new(Hotkeys::default());
 }
 
 fn get_msg() -> Option<MSG> {
@@ -294,15 +313,17 @@ fn check_client_activation(wparam: WPARAM, lparam: LPARAM) -> bool {
 
     /* update active client and entry point */
     ACTIVE_CLIENT.replace(Some(pos));
-    let entry_point = DISPLAYS.with_borrow(|(displays, _)| {
-        display_util::clamp_to_display_bounds(displays, prev_pos, curr_pos)
+    let (entry_point, edge) = DISPLAYS.with_borrow(|(displays, _)| {
+        let entry_point = display_util::clamp_to_display_bounds(displays, prev_pos, curr_pos);
+        let edge = display_util::edge_position(displays, prev_pos, entry_point, pos);
+        (entry_point, edge)
     });
     ENTRY_POINT.replace(entry_point);
 
     /* notify main thread */
     log::debug!("ENTERED @ {prev_pos:?} -> {curr_pos:?}");
     let active = ACTIVE_CLIENT.get().expect("active client");
-    blocking_send_event(active, CaptureEvent::Begin);
+    blocking_send_event(active, CaptureEvent::Begin(Some(edge)));
 
     ret
 }
@@ -337,8 +358,19 @@ unsafe extern "system" fn mouse_proc(ncode: i32, wparam: WPARAM, lparam: LPARAM)
 unsafe extern "system" fn kybrd_proc(ncode: i32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
     /* get active client if any */
     let Some(client) = ACTIVE_CLIENT.get() else {
-        return CallNextHookEx(None, ncode, wparam, lparam);
+        /* not capturing => only check for hotkeys */
+        let hotkey = match to_key_event(wparam, lparam) {
+            Some(KeyboardEvent::Key { key, state, .. }) => {
+                HOTKEYS.with_borrow_mut(|hotkeys| hotkeys.key(key, state))
+            }
+            _ => false,
+        };
+        return match hotkey {
+            true => LRESULT(1),
+            false => CallNextHookEx(None, ncode, wparam, lparam),
+        };
     };
+    HOTKEYS.with_borrow_mut(|hotkeys| hotkeys.clear());
 
     /* convert to key event */
     let Some(key_event) = to_key_event(wparam, lparam) else {
@@ -434,6 +466,36 @@ fn update_clients(request: ClientUpdate) {
             }
             CLIENTS.with_borrow_mut(|clients| clients.remove(&pos));
         }
+        ClientUpdate::Capture(pos) => {
+            if ACTIVE_CLIENT.get().is_some()
+                || !CLIENTS.with_borrow(|clients| clients.contains(&pos))
+            {
+                return;
+            }
+            /* capture at the current cursor position */
+            let mut point = POINT::default();
+            if let Err(e) = unsafe { GetCursorPos(&mut point) } {
+                log::warn!("could not get cursor position: {e}");
+                return;
+            }
+            ENTRY_POINT.replace((point.x, point.y));
+            PREV_POS.replace(Some((point.x, point.y)));
+            ACTIVE_CLIENT.replace(Some(pos));
+            blocking_send_event(pos, CaptureEvent::Begin(None));
+        }
+        ClientUpdate::CenterCursor => {
+            /* center of the primary display */
+            let (x, y) = unsafe { (GetSystemMetrics(SM_CXSCREEN), GetSystemMetrics(SM_CYSCREEN)) };
+            let center = (x / 2, y / 2);
+            if let Err(e) = unsafe { SetCursorPos(center.0, center.1) } {
+                log::warn!("could not set cursor position: {e}");
+                return;
+            }
+            PREV_POS.replace(Some(center));
+        }
+        ClientUpdate::Hotkeys(chords, tx) => {
+            HOTKEYS.with_borrow_mut(|hotkeys| hotkeys.register(chords, tx));
+        }
     }
 }
 
@@ -2,6 +2,8 @@ use std::task::Poll;
 
 use async_trait::async_trait;
 use futures_core::Stream;
+use input_event::scancode;
+use tokio::sync::mpsc::UnboundedSender;
 
 use super::{Capture, CaptureError, CaptureEvent, Position, error::X11InputCaptureCreationError};
 
@@ -23,10 +25,26 @@ impl Capture for X11InputCapture {
         Ok(())
     }
 
+    async fn capture(&mut self, _pos: Position) -> Result<(), CaptureError> {
+        Ok(())
+    }
+
     async fn release(&mut self) -> Result<(), CaptureError> {
         Ok(())
     }
 
+    async fn center_cursor(&mut self) -> Result<(), CaptureError> {
+        Ok(())
+    }
+
+    async fn register_hotkeys(
+        &mut self,
+        _chords: Vec<Vec<scancode::Linux>>,
+        _tx: UnboundedSender<usize>,
+    ) -> Result<(), CaptureError> {
+        Err(CaptureError::HotkeysUnsupported)
+    }
+
     async fn terminate(&mut self) -> Result<(), CaptureError> {
         Ok(())
     }
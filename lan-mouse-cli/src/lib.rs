@@ -1,12 +1,18 @@
 use clap::{Args, Parser, Subcommand};
 use futures::StreamExt;
 
-use std::{net::IpAddr, time::Duration};
+use std::{
+    collections::HashMap,
+    io,
+    net::{IpAddr, SocketAddr},
+    time::{Duration, SystemTime},
+};
 use thiserror::Error;
+use tokio::io::{AsyncBufReadExt, BufReader};
 
 use lan_mouse_ipc::{
-    ClientHandle, ConnectionError, FrontendEvent, FrontendRequest, IpcError, Position,
-    connect_async,
+    AuditQuery, ClientHandle, ConnectionError, DEFAULT_PORT, FrontendEvent, FrontendRequest,
+    IpcError, Position, connect_async,
 };
 
 #[derive(Debug, Error)]
@@ -16,6 +22,11 @@ pub enum CliError {
     ServiceNotRunning(#[from] ConnectionError),
     #[error("error communicating with service: {0}")]
     Ipc(#[from] IpcError),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    /// the service reported an error
+    #[error("{0}")]
+    Service(String),
 }
 
 #[derive(Parser, Clone, Debug, PartialEq, Eq)]
@@ -64,13 +75,58 @@ enum CliSubcommand {
     EnableCapture,
     /// re-enable emulation
     EnableEmulation,
+    /// capture input and send it to a client
+    Enter { id: ClientHandle },
+    /// release captured input
+    Release,
+    /// pin input to the currently entered client or unpin it
+    ToggleLock,
+    /// send a Wake-on-LAN packet to a client
+    Wake { id: ClientHandle },
+    /// list lan-mouse devices found on the local network
+    Discover,
+    /// add a discovered device as a client
+    AddDiscovered { sha256_fingerprint: String },
+    /// pair with another device by comparing a short code:
+    /// run without an ip on one device and with its ip on the other
+    Pair {
+        ip: Option<IpAddr>,
+        #[arg(long)]
+        port: Option<u16>,
+    },
     /// authorize a public key
     AuthorizeKey {
         description: String,
         sha256_fingerprint: String,
+        /// revoke the authorization after the given number of minutes
+        #[arg(long, conflicts_with = "session")]
+        minutes: Option<u64>,
+        /// revoke the authorization once the device disconnects
+        #[arg(long)]
+        session: bool,
     },
     /// deauthorize a public key
     RemoveAuthorizedKey { sha256_fingerprint: String },
+    /// trust a new certificate of a client, e.g. after it was regenerated
+    AcceptKey {
+        id: ClientHandle,
+        sha256_fingerprint: String,
+    },
+    /// show the audit log of connections and authorization changes
+    Audit {
+        /// only entries involving this fingerprint
+        #[arg(long)]
+        fingerprint: Option<String>,
+        /// only entries involving this ip
+        #[arg(long)]
+        ip: Option<IpAddr>,
+        /// only entries of the last N hours
+        #[arg(long)]
+        hours: Option<u64>,
+        /// number of (most recent) entries to show
+        #[arg(long, default_value_t = 100)]
+        limit: usize,
+    },
 }
 
 pub async fn run(args: CliArgs) -> Result<(), CliError> {
@@ -125,8 +181,14 @@ async fn execute(cmd: CliSubcommand) -> Result<(), CliError> {
                         let pos = config.pos;
                         let active = state.active;
                         let ips = state.ips;
+                        let fingerprint = config.fingerprint.unwrap_or("none".to_owned());
+                        let connection = state.connection;
+                        let incoming = state
+                            .incoming
+                            .map(|a| a.to_string())
+                            .unwrap_or("none".to_owned());
                         println!(
-                            "id {handle}: {host}:{port} ({pos}) active: {active}, ips: {ips:?}"
+                            "id {handle}: {host}:{port} ({pos}) active: {active}, {connection}, incoming: {incoming}, ips: {ips:?}, fingerprint: {fingerprint}"
                         );
                     }
                     break;
@@ -148,20 +210,141 @@ async fn execute(cmd: CliSubcommand) -> Result<(), CliError> {
         }
         CliSubcommand::EnableCapture => tx.request(FrontendRequest::EnableCapture).await?,
         CliSubcommand::EnableEmulation => tx.request(FrontendRequest::EnableEmulation).await?,
+        CliSubcommand::Enter { id } => {
+            tx.request(FrontendRequest::Enter(id)).await?;
+            // failures are reported right away, success is not
+            while let Ok(Some(e)) =
+                tokio::time::timeout(Duration::from_millis(500), rx.next()).await
+            {
+                if let FrontendEvent::Error(e) = e? {
+                    return Err(CliError::Service(e));
+                }
+            }
+        }
+        CliSubcommand::Release => tx.request(FrontendRequest::Release).await?,
+        CliSubcommand::ToggleLock => tx.request(FrontendRequest::ToggleLock).await?,
+        CliSubcommand::Wake { id } => tx.request(FrontendRequest::Wake(id)).await?,
+        CliSubcommand::Discover => {
+            tx.request(FrontendRequest::EnumerateDiscovered).await?;
+            // peers are reported one by one (and once more on connecting),
+            // stop once no more arrive
+            let mut peers = HashMap::new();
+            while let Ok(Some(e)) =
+                tokio::time::timeout(Duration::from_millis(500), rx.next()).await
+            {
+                if let FrontendEvent::PeerDiscovered(peer) = e? {
+                    peers.insert(peer.fingerprint.clone(), peer);
+                }
+            }
+            for (fp, peer) in peers {
+                let name = peer.name;
+                let ip = peer.ip;
+                let port = peer.port;
+                let pos = peer.pos;
+                println!("{name}: {ip}:{port} (suggested position: {pos}), fingerprint: {fp}");
+            }
+        }
+        CliSubcommand::AddDiscovered { sha256_fingerprint } => {
+            tx.request(FrontendRequest::AddDiscovered(sha256_fingerprint))
+                .await?
+        }
+        CliSubcommand::Pair { ip, port } => {
+            match ip {
+                Some(ip) => {
+                    let addr = SocketAddr::new(ip, port.unwrap_or(DEFAULT_PORT));
+                    tx.request(FrontendRequest::Pair(addr)).await?
+                }
+                None => {
+                    tx.request(FrontendRequest::StartPairing).await?;
+                    println!("waiting for the other device ...");
+                }
+            }
+            while let Some(e) = rx.next().await {
+                match e? {
+                    FrontendEvent::PairingCode { fingerprint, code } => {
+                        println!("pairing code: {code}");
+                        println!("do the codes on both devices match? [y/N]");
+                        let mut answer = String::new();
+                        BufReader::new(tokio::io::stdin())
+                            .read_line(&mut answer)
+                            .await?;
+                        if answer.trim().eq_ignore_ascii_case("y") {
+                            tx.request(FrontendRequest::ConfirmPairing(fingerprint))
+                                .await?;
+                            println!("paired");
+                        } else {
+                            println!("pairing cancelled");
+                        }
+                        break;
+                    }
+                    FrontendEvent::Error(e) => {
+                        eprintln!("{e}");
+                        break;
+                    }
+                    _ => {}
+                }
+            }
+        }
         CliSubcommand::AuthorizeKey {
             description,
             sha256_fingerprint,
+            minutes,
+            session,
         } => {
-            tx.request(FrontendRequest::AuthorizeKey(
-                description,
-                sha256_fingerprint,
-            ))
-            .await?
+            let request = match (minutes, session) {
+                (Some(minutes), _) => FrontendRequest::AuthorizeKeyTemporarily(
+                    description,
+                    sha256_fingerprint,
+                    Duration::from_secs(minutes * 60),
+                ),
+                (None, true) => {
+                    FrontendRequest::AuthorizeKeyForSession(description, sha256_fingerprint)
+                }
+                (None, false) => FrontendRequest::AuthorizeKey(description, sha256_fingerprint),
+            };
+            tx.request(request).await?
         }
         CliSubcommand::RemoveAuthorizedKey { sha256_fingerprint } => {
             tx.request(FrontendRequest::RemoveAuthorizedKey(sha256_fingerprint))
                 .await?
         }
+        CliSubcommand::AcceptKey {
+            id,
+            sha256_fingerprint,
+        } => {
+            tx.request(FrontendRequest::AcceptFingerprint(id, sha256_fingerprint))
+                .await?
+        }
+        CliSubcommand::Audit {
+            fingerprint,
+            ip,
+            hours,
+            limit,
+        } => {
+            let since = hours.map(|h| SystemTime::now() - Duration::from_secs(h * 60 * 60));
+            let query = AuditQuery {
+                fingerprint,
+                ip,
+                since,
+                limit: Some(limit),
+            };
+            tx.request(FrontendRequest::QueryAudit(query)).await?;
+            while let Some(e) = rx.next().await {
+                match e? {
+                    FrontendEvent::AuditLog(entries) => {
+                        for entry in entries {
+                            println!("{} {}", entry.time, entry.event);
+                        }
+                        break;
+                    }
+                    FrontendEvent::Error(e) => {
+                        eprintln!("{e}");
+                        break;
+                    }
+                    _ => {}
+                }
+            }
+        }
     }
     Ok(())
 }
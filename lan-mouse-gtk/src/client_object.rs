@@ -3,7 +3,7 @@ mod imp;
 use adw::subclass::prelude::*;
 use gtk::glib::{self, Object};
 
-use lan_mouse_ipc::{ClientConfig, ClientHandle, ClientState};
+use lan_mouse_ipc::{Acceleration, ClientConfig, ClientHandle, ClientState};
 
 glib::wrapper! {
     pub struct ClientObject(ObjectSubclass<imp::ClientObject>);
@@ -11,7 +11,7 @@ glib::wrapper! {
 
 impl ClientObject {
     pub fn new(handle: ClientHandle, client: ClientConfig, state: ClientState) -> Self {
-        Object::builder()
+        let obj: Self = Object::builder()
             .property("handle", handle)
             .property("hostname", client.hostname)
             .property("port", client.port as u32)
@@ -26,7 +26,34 @@ impl ClientObject {
                     .collect::<Vec<_>>(),
             )
             .property("resolving", state.resolving)
-            .build()
+            .property("connection", state.connection.to_string())
+            .property(
+                "incoming",
+                state.incoming.map(|a| a.to_string()).unwrap_or_default(),
+            )
+            .property("sensitivity", client.pointer.sensitivity)
+            .property("acceleration", client.pointer.acceleration.is_some())
+            .property("scroll-speed", client.pointer.scroll_speed)
+            .property("natural-scrolling", client.pointer.natural_scrolling)
+            .build();
+        obj.set_acceleration_curve(client.pointer.acceleration);
+        obj
+    }
+
+    /// the acceleration curve to use when acceleration is enabled
+    pub fn acceleration_curve(&self) -> Acceleration {
+        self.imp()
+            .data
+            .borrow()
+            .acceleration_curve
+            .unwrap_or_default()
+    }
+
+    /// remember the given acceleration curve, if any
+    pub fn set_acceleration_curve(&self, curve: Option<Acceleration>) {
+        if let Some(curve) = curve {
+            self.imp().data.borrow_mut().acceleration_curve = Some(curve);
+        }
     }
 
     pub fn get_data(&self) -> ClientData {
@@ -42,5 +69,15 @@ pub struct ClientData {
     pub active: bool,
     pub position: String,
     pub resolving: bool,
+    /// state of the outgoing connection
+    pub connection: String,
+    /// address of the incoming connection of the same device, if any
+    pub incoming: String,
     pub ips: Vec<String>,
+    pub sensitivity: f64,
+    pub acceleration: bool,
+    pub scroll_speed: f64,
+    pub natural_scrolling: bool,
+    /// last known acceleration curve (kept while acceleration is disabled)
+    pub acceleration_curve: Option<Acceleration>,
 }
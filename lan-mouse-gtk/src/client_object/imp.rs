@@ -18,7 +18,13 @@ pub struct ClientObject {
     #[property(name = "active", get, set, type = bool, member = active)]
     #[property(name = "position", get, set, type = String, member = position)]
     #[property(name = "resolving", get, set, type = bool, member = resolving)]
+    #[property(name = "connection", get, set, type = String, member = connection)]
+    #[property(name = "incoming", get, set, type = String, member = incoming)]
     #[property(name = "ips", get, set, type = Vec<String>, member = ips)]
+    #[property(name = "sensitivity", get, set, type = f64, member = sensitivity)]
+    #[property(name = "acceleration", get, set, type = bool, member = acceleration)]
+    #[property(name = "scroll-speed", get, set, type = f64, member = scroll_speed)]
+    #[property(name = "natural-scrolling", get, set, type = bool, member = natural_scrolling)]
     pub data: RefCell<ClientData>,
 }
 
@@ -4,7 +4,7 @@ use adw::prelude::*;
 use adw::subclass::prelude::*;
 use gtk::glib::{self, Object};
 
-use lan_mouse_ipc::{DEFAULT_PORT, Position};
+use lan_mouse_ipc::{DEFAULT_PORT, PointerConfig, Position};
 
 use super::ClientObject;
 
@@ -130,6 +130,61 @@ impl ClientRow {
             .sync_create()
             .build();
 
+        // bind connection state to label, the reason of a failure to its tooltip
+        let connection_binding = client_object
+            .bind_property("connection", &self.imp().connection_label.get(), "label")
+            .transform_to(|_, connection: String| connection.split(": ").next().map(str::to_owned))
+            .sync_create()
+            .build();
+        let connection_tooltip_binding = client_object
+            .bind_property(
+                "connection",
+                &self.imp().connection_label.get(),
+                "tooltip-text",
+            )
+            .sync_create()
+            .build();
+
+        // the same device connected to this device => show it in this row
+        let incoming_binding = client_object
+            .bind_property("incoming", &self.imp().incoming_indicator.get(), "visible")
+            .transform_to(|_, incoming: String| Some(!incoming.is_empty()))
+            .sync_create()
+            .build();
+        let incoming_tooltip_binding = client_object
+            .bind_property(
+                "incoming",
+                &self.imp().incoming_indicator.get(),
+                "tooltip-text",
+            )
+            .transform_to(|_, incoming: String| {
+                Some(format!("also connected to this computer from {incoming}"))
+            })
+            .sync_create()
+            .build();
+
+        // bind pointer settings to their respective widgets
+        let sensitivity_binding = client_object
+            .bind_property("sensitivity", &self.imp().sensitivity(), "value")
+            .sync_create()
+            .build();
+        let acceleration_binding = client_object
+            .bind_property("acceleration", &self.imp().acceleration(), "active")
+            .sync_create()
+            .build();
+        let scroll_speed_binding = client_object
+            .bind_property("scroll-speed", &self.imp().scroll_speed(), "value")
+            .sync_create()
+            .build();
+        let natural_scrolling_binding = client_object
+            .bind_property(
+                "natural-scrolling",
+                &self.imp().natural_scrolling(),
+                "active",
+            )
+            .sync_create()
+            .build();
+
         bindings.push(active_binding);
         bindings.push(switch_position_binding);
         bindings.push(hostname_binding);
@@ -139,6 +194,14 @@ impl ClientRow {
         bindings.push(position_binding);
         bindings.push(resolve_binding);
         bindings.push(ip_binding);
+        bindings.push(connection_binding);
+        bindings.push(connection_tooltip_binding);
+        bindings.push(incoming_binding);
+        bindings.push(incoming_tooltip_binding);
+        bindings.push(sensitivity_binding);
+        bindings.push(acceleration_binding);
+        bindings.push(scroll_speed_binding);
+        bindings.push(natural_scrolling_binding);
     }
 
     pub fn unbind(&self) {
@@ -163,6 +226,10 @@ impl ClientRow {
         self.imp().set_pos(pos);
     }
 
+    pub fn set_pointer_config(&self, pointer: PointerConfig) {
+        self.imp().set_pointer_config(pointer);
+    }
+
     pub fn set_dns_state(&self, resolved: bool) {
         self.imp().set_dns_state(resolved);
     }
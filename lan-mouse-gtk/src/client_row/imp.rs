@@ -5,8 +5,8 @@ use adw::{ActionRow, ComboRow, prelude::*};
 use glib::{Binding, subclass::InitializingObject};
 use gtk::glib::subclass::Signal;
 use gtk::glib::{SignalHandlerId, clone};
-use gtk::{Button, CompositeTemplate, Entry, Switch, glib};
-use lan_mouse_ipc::Position;
+use gtk::{Button, CompositeTemplate, Entry, SpinButton, Switch, glib};
+use lan_mouse_ipc::{PointerConfig, Position};
 use std::sync::OnceLock;
 
 use crate::client_object::ClientObject;
@@ -17,6 +17,10 @@ pub struct ClientRow {
     #[template_child]
     pub enable_switch: TemplateChild<gtk::Switch>,
     #[template_child]
+    pub connection_label: TemplateChild<gtk::Label>,
+    #[template_child]
+    pub incoming_indicator: TemplateChild<gtk::Image>,
+    #[template_child]
     pub dns_button: TemplateChild<gtk::Button>,
     #[template_child]
     pub dns_loading_indicator: TemplateChild<gtk::Spinner>,
@@ -24,11 +28,16 @@ pub struct ClientRow {
     pub port: RefCell<Option<gtk::Entry>>,
     pub position: RefCell<Option<ComboRow>>,
     pub delete_button: RefCell<Option<gtk::Button>>,
+    pub sensitivity: RefCell<Option<SpinButton>>,
+    pub acceleration: RefCell<Option<Switch>>,
+    pub scroll_speed: RefCell<Option<SpinButton>>,
+    pub natural_scrolling: RefCell<Option<Switch>>,
     pub bindings: RefCell<Vec<Binding>>,
     hostname_change_handler: RefCell<Option<SignalHandlerId>>,
     port_change_handler: RefCell<Option<SignalHandlerId>>,
     position_change_handler: RefCell<Option<SignalHandlerId>>,
     set_state_handler: RefCell<Option<SignalHandlerId>>,
+    pointer_change_handlers: RefCell<Vec<SignalHandlerId>>,
     pub client_object: RefCell<Option<ClientObject>>,
 }
 
@@ -83,6 +92,41 @@ impl ObjectImpl for ClientRow {
             .build();
         self.obj().add_row(&position);
 
+        let sensitivity_row = ActionRow::builder().title("pointer sensitivity").build();
+        let sensitivity = SpinButton::with_range(
+            *PointerConfig::SPEED_RANGE.start(),
+            *PointerConfig::SPEED_RANGE.end(),
+            0.1,
+        );
+        sensitivity.set_digits(1);
+        sensitivity.set_valign(gtk::Align::Center);
+        sensitivity_row.add_suffix(&sensitivity);
+        self.obj().add_row(&sensitivity_row);
+
+        let acceleration_row = ActionRow::builder().title("pointer acceleration").build();
+        let acceleration = Switch::builder().valign(gtk::Align::Center).build();
+        acceleration_row.add_suffix(&acceleration);
+        self.obj().add_row(&acceleration_row);
+
+        let scroll_speed_row = ActionRow::builder().title("scroll speed").build();
+        let scroll_speed = SpinButton::with_range(
+            *PointerConfig::SPEED_RANGE.start(),
+            *PointerConfig::SPEED_RANGE.end(),
+            0.1,
+        );
+        scroll_speed.set_digits(1);
+        scroll_speed.set_valign(gtk::Align::Center);
+        scroll_speed_row.add_suffix(&scroll_speed);
+        self.obj().add_row(&scroll_speed_row);
+
+        let natural_scrolling_row = ActionRow::builder()
+            .title("natural scrolling")
+            .subtitle("invert the scroll direction on this client")
+            .build();
+        let natural_scrolling = Switch::builder().valign(gtk::Align::Center).build();
+        natural_scrolling_row.add_suffix(&natural_scrolling);
+        self.obj().add_row(&natural_scrolling_row);
+
         let delete_row = ActionRow::builder().title("delete this client").build();
         let delete_button = Button::builder()
             .icon_name("user-trash-symbolic")
@@ -98,6 +142,11 @@ impl ObjectImpl for ClientRow {
         self.port.replace(Some(port.clone()));
         self.position.replace(Some(position.clone()));
         self.delete_button.replace(Some(delete_button.clone()));
+        self.sensitivity.replace(Some(sensitivity.clone()));
+        self.acceleration.replace(Some(acceleration.clone()));
+        self.scroll_speed.replace(Some(scroll_speed.clone()));
+        self.natural_scrolling
+            .replace(Some(natural_scrolling.clone()));
 
         delete_button.connect_clicked(clone!(
             #[weak(rename_to = row)]
@@ -141,6 +190,27 @@ impl ObjectImpl for ClientRow {
             }
         ));
         self.set_state_handler.replace(Some(handler));
+        let mut pointer_change_handlers = self.pointer_change_handlers.borrow_mut();
+        for spin_button in [&sensitivity, &scroll_speed] {
+            let handler = spin_button.connect_value_changed(clone!(
+                #[weak(rename_to = row)]
+                self,
+                move |_| {
+                    row.handle_pointer_changed();
+                }
+            ));
+            pointer_change_handlers.push(handler);
+        }
+        for switch in [&acceleration, &natural_scrolling] {
+            let handler = switch.connect_active_notify(clone!(
+                #[weak(rename_to = row)]
+                self,
+                move |_| {
+                    row.handle_pointer_changed();
+                }
+            ));
+            pointer_change_handlers.push(handler);
+        }
     }
 
     fn signals() -> &'static [glib::subclass::Signal] {
@@ -161,6 +231,14 @@ impl ObjectImpl for ClientRow {
                 Signal::builder("request-position-change")
                     .param_types([u32::static_type()])
                     .build(),
+                Signal::builder("request-pointer-change")
+                    .param_types([
+                        f64::static_type(),
+                        bool::static_type(),
+                        f64::static_type(),
+                        bool::static_type(),
+                    ])
+                    .build(),
             ]
         })
     }
@@ -186,6 +264,38 @@ impl ClientRow {
             .expect("position row")
             .clone()
     }
+
+    pub(crate) fn sensitivity(&self) -> SpinButton {
+        self.sensitivity
+            .borrow()
+            .as_ref()
+            .expect("sensitivity button")
+            .clone()
+    }
+
+    pub(crate) fn acceleration(&self) -> Switch {
+        self.acceleration
+            .borrow()
+            .as_ref()
+            .expect("acceleration switch")
+            .clone()
+    }
+
+    pub(crate) fn scroll_speed(&self) -> SpinButton {
+        self.scroll_speed
+            .borrow()
+            .as_ref()
+            .expect("scroll speed button")
+            .clone()
+    }
+
+    pub(crate) fn natural_scrolling(&self) -> Switch {
+        self.natural_scrolling
+            .borrow()
+            .as_ref()
+            .expect("natural scrolling switch")
+            .clone()
+    }
 }
 
 #[gtk::template_callbacks]
@@ -223,6 +333,18 @@ impl ClientRow {
             .emit_by_name("request-position-change", &[&position.selected()])
     }
 
+    fn handle_pointer_changed(&self) {
+        self.obj().emit_by_name::<()>(
+            "request-pointer-change",
+            &[
+                &self.sensitivity().value(),
+                &self.acceleration().is_active(),
+                &self.scroll_speed().value(),
+                &self.natural_scrolling().is_active(),
+            ],
+        );
+    }
+
     pub(super) fn set_hostname(&self, hostname: Option<String>) {
         let entry = self.hostname();
         let position = entry.position();
@@ -266,6 +388,30 @@ impl ClientRow {
         position.unblock_signal(handler);
     }
 
+    pub(super) fn set_pointer_config(&self, pointer: PointerConfig) {
+        // same order as the handlers are registered in `constructed`
+        let widgets: [gtk::Widget; 4] = [
+            self.sensitivity().upcast(),
+            self.scroll_speed().upcast(),
+            self.acceleration().upcast(),
+            self.natural_scrolling().upcast(),
+        ];
+        let handlers = self.pointer_change_handlers.borrow();
+        for (widget, handler) in widgets.iter().zip(handlers.iter()) {
+            widget.block_signal(handler);
+        }
+        let client_object = self.client_object.borrow();
+        let client_object = client_object.as_ref().expect("client object");
+        client_object.set_acceleration_curve(pointer.acceleration);
+        client_object.set_sensitivity(pointer.sensitivity);
+        client_object.set_acceleration(pointer.acceleration.is_some());
+        client_object.set_scroll_speed(pointer.scroll_speed);
+        client_object.set_natural_scrolling(pointer.natural_scrolling);
+        for (widget, handler) in widgets.iter().zip(handlers.iter()) {
+            widget.unblock_signal(handler);
+        }
+    }
+
     pub(super) fn set_active(&self, active: bool) {
         let handler = self.set_state_handler.borrow();
         let handler = handler.as_ref().expect("signal handler");
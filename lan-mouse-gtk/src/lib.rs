@@ -14,7 +14,7 @@ use std::{env, process, str};
 
 use window::Window;
 
-use lan_mouse_ipc::FrontendEvent;
+use lan_mouse_ipc::{FrontendEvent, ReleaseReason};
 
 use adw::Application;
 use gtk::{IconTheme, gdk::Display, glib::clone, prelude::*};
@@ -211,6 +211,26 @@ fn build_ui(app: &Application) {
                     FrontendEvent::IncomingDisconnected(addr) => {
                         window.show_toast(format!("{addr} disconnected").as_str());
                     }
+                    FrontendEvent::CaptureReleased(_, ReleaseReason::IdleTimeout) => {
+                        window.show_toast("input returned after inactivity");
+                    }
+                    FrontendEvent::CaptureReleased(..) => {}
+                    FrontendEvent::PeerDiscovered(peer) => window.offer_discovered_peer(peer),
+                    FrontendEvent::PairingCode { fingerprint, code } => {
+                        window.confirm_pairing(fingerprint, &code)
+                    }
+                    FrontendEvent::CertificateExpiring { days_left } => {
+                        window.warn_certificate_expiry(days_left)
+                    }
+                    FrontendEvent::FingerprintMismatch {
+                        handle,
+                        expected: _,
+                        received,
+                    } => window.confirm_fingerprint_change(handle, received),
+                    FrontendEvent::AuditLog(_) => {}
+                    FrontendEvent::LimitExceeded { ip, limit } => {
+                        window.show_toast(&format!("{ip}: {limit}"))
+                    }
                 }
             }
         }
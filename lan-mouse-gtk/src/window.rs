@@ -1,6 +1,6 @@
 mod imp;
 
-use std::collections::HashMap;
+use std::{collections::HashMap, time::Duration};
 
 use adw::prelude::*;
 use adw::subclass::prelude::*;
@@ -11,8 +11,8 @@ use gtk::{
 };
 
 use lan_mouse_ipc::{
-    ClientConfig, ClientHandle, ClientState, DEFAULT_PORT, FrontendRequest, FrontendRequestWriter,
-    Position,
+    ClientConfig, ClientHandle, ClientState, DEFAULT_PORT, DiscoveredPeer, FrontendRequest,
+    FrontendRequestWriter, PointerConfig, Position,
 };
 
 use crate::{
@@ -22,6 +22,11 @@ use crate::{
 
 use super::{client_object::ClientObject, client_row::ClientRow};
 
+/// choices of the validity row in the authorization dialog
+const AUTHORIZE_PERMANENTLY: u32 = 0;
+const AUTHORIZE_FOR_ONE_HOUR: u32 = 1;
+const AUTHORIZE_FOR_SESSION: u32 = 2;
+
 glib::wrapper! {
     pub struct Window(ObjectSubclass<imp::Window>)
         @extends adw::ApplicationWindow, gtk::Window, gtk::Widget,
@@ -227,6 +232,33 @@ impl Window {
                             }
                         ),
                     );
+                    row.connect_closure(
+                        "request-pointer-change",
+                        false,
+                        closure_local!(
+                            #[strong]
+                            window,
+                            move |row: ClientRow,
+                                  sensitivity: f64,
+                                  acceleration: bool,
+                                  scroll_speed: f64,
+                                  natural_scrolling: bool| {
+                                if let Some(client) = window.client_by_idx(row.index() as u32) {
+                                    let acceleration =
+                                        acceleration.then(|| client.acceleration_curve());
+                                    window.request(FrontendRequest::UpdatePointerConfig(
+                                        client.handle(),
+                                        PointerConfig {
+                                            sensitivity,
+                                            acceleration,
+                                            scroll_speed,
+                                            natural_scrolling,
+                                        },
+                                    ));
+                                }
+                            }
+                        ),
+                    );
                     row.upcast()
                 }
             ),
@@ -356,6 +388,7 @@ impl Window {
         row.set_hostname(client.hostname);
         row.set_port(client.port);
         row.set_position(client.pos);
+        row.set_pointer_config(client.pointer);
     }
 
     pub(super) fn update_client_state(&self, handle: ClientHandle, state: ClientState) {
@@ -373,6 +406,8 @@ impl Window {
 
         /* dns state */
         client_object.set_resolving(state.resolving);
+        client_object.set_connection(state.connection.to_string());
+        client_object.set_incoming(state.incoming.map(|a| a.to_string()).unwrap_or_default());
 
         self.update_dns_state(handle, !state.ips.is_empty());
         let ips = state
@@ -439,7 +474,7 @@ impl Window {
         self.request(FrontendRequest::Create);
     }
 
-    fn open_fingerprint_dialog(&self, fp: Option<String>) {
+    fn open_fingerprint_dialog(&self, fp: Option<String>, validity: u32) {
         let window = FingerprintWindow::new(fp);
         window.set_transient_for(Some(self));
         window.connect_closure(
@@ -449,7 +484,7 @@ impl Window {
                 #[strong(rename_to = parent)]
                 self,
                 move |w: FingerprintWindow, desc: String, fp: String| {
-                    parent.request_fingerprint_add(desc, fp);
+                    parent.request_fingerprint_add(desc, fp, validity);
                     w.close();
                 }
             ),
@@ -457,8 +492,15 @@ impl Window {
         window.present();
     }
 
-    fn request_fingerprint_add(&self, desc: String, fp: String) {
-        self.request(FrontendRequest::AuthorizeKey(desc, fp));
+    fn request_fingerprint_add(&self, desc: String, fp: String, validity: u32) {
+        let request = match validity {
+            AUTHORIZE_FOR_ONE_HOUR => {
+                FrontendRequest::AuthorizeKeyTemporarily(desc, fp, Duration::from_secs(3600))
+            }
+            AUTHORIZE_FOR_SESSION => FrontendRequest::AuthorizeKeyForSession(desc, fp),
+            _ => FrontendRequest::AuthorizeKey(desc, fp),
+        };
+        self.request(request);
     }
 
     fn request_fingerprint_remove(&self, fp: String) {
@@ -479,6 +521,58 @@ impl Window {
         toast_overlay.add_toast(toast);
     }
 
+    pub(super) fn offer_discovered_peer(&self, peer: DiscoveredPeer) {
+        let toast = adw::Toast::new(&format!("found {} ({})", peer.name, peer.ip));
+        toast.set_button_label(Some("Add"));
+        toast.set_timeout(0);
+        toast.connect_button_clicked(clone!(
+            #[weak(rename_to = window)]
+            self,
+            move |_| window.request(FrontendRequest::AddDiscovered(peer.fingerprint.clone()))
+        ));
+        self.imp().toast_overlay.add_toast(toast);
+    }
+
+    pub(super) fn confirm_pairing(&self, fingerprint: String, code: &str) {
+        let toast = adw::Toast::new(&format!("pairing code: {code} - same on both devices?"));
+        toast.set_button_label(Some("Confirm"));
+        toast.set_timeout(0);
+        toast.connect_button_clicked(clone!(
+            #[weak(rename_to = window)]
+            self,
+            move |_| window.request(FrontendRequest::ConfirmPairing(fingerprint.clone()))
+        ));
+        self.imp().toast_overlay.add_toast(toast);
+    }
+
+    pub(super) fn confirm_fingerprint_change(&self, handle: ClientHandle, received: String) {
+        let host = self
+            .client_object_for_handle(handle)
+            .and_then(|c| c.hostname())
+            .unwrap_or_else(|| format!("client {handle}"));
+        let toast = adw::Toast::new(&format!(
+            "certificate of {host} changed! Trust only if the key was regenerated: {received}"
+        ));
+        toast.set_button_label(Some("Trust"));
+        toast.set_timeout(0);
+        toast.connect_button_clicked(clone!(
+            #[weak(rename_to = window)]
+            self,
+            move |_| window.request(FrontendRequest::AcceptFingerprint(handle, received.clone()))
+        ));
+        self.imp().toast_overlay.add_toast(toast);
+    }
+
+    pub(super) fn warn_certificate_expiry(&self, days_left: i64) {
+        let msg = match days_left {
+            d if d < 0 => "the certificate of this device has expired!".to_owned(),
+            d => format!("the certificate of this device expires in {d} days"),
+        };
+        let toast = adw::Toast::new(&format!("{msg} - replace it with `lan-mouse cert rotate`"));
+        toast.set_timeout(0);
+        self.imp().toast_overlay.add_toast(toast);
+    }
+
     pub(super) fn set_capture(&self, active: bool) {
         self.imp().capture_active.replace(active);
         self.update_capture_emulation_status();
@@ -527,9 +621,9 @@ impl Window {
             closure_local!(
                 #[strong(rename_to = parent)]
                 self,
-                move |w: AuthorizationWindow, fp: String| {
+                move |w: AuthorizationWindow, fp: String, validity: u32| {
                     w.close();
-                    parent.open_fingerprint_dialog(Some(fp));
+                    parent.open_fingerprint_dialog(Some(fp), validity);
                 }
             ),
         );
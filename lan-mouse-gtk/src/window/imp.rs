@@ -152,7 +152,8 @@ impl Window {
 
     #[template_callback]
     fn handle_add_cert_fingerprint(&self, _button: &Button) {
-        self.obj().open_fingerprint_dialog(None);
+        self.obj()
+            .open_fingerprint_dialog(None, super::AUTHORIZE_PERMANENTLY);
     }
 
     pub fn set_port(&self, port: u16) {
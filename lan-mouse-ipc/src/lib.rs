@@ -4,7 +4,9 @@ use std::{
     fmt::Display,
     io,
     net::{IpAddr, SocketAddr},
+    ops::RangeInclusive,
     str::FromStr,
+    time::{Duration, SystemTime},
 };
 use thiserror::Error;
 
@@ -128,7 +130,188 @@ impl TryFrom<&str> for Position {
     }
 }
 
-#[derive(Debug, Eq, PartialEq, Clone, Serialize, Deserialize)]
+/// pointer acceleration curve:
+/// motion is scaled by `1 + factor * (speed - threshold)` for speeds above
+/// `threshold`, where speed is the length of the relative motion vector of
+/// a single motion event. The resulting gain is capped at `max_gain`.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub struct Acceleration {
+    /// speed below which no acceleration is applied
+    pub threshold: f64,
+    /// gain added per unit of speed above `threshold`
+    pub factor: f64,
+    /// maximum gain
+    pub max_gain: f64,
+}
+
+impl Default for Acceleration {
+    fn default() -> Self {
+        Self {
+            threshold: 2.0,
+            factor: 0.1,
+            max_gain: 3.0,
+        }
+    }
+}
+
+impl Acceleration {
+    /// the curve with values that would break the pointer math
+    /// (negative, infinite or NaN) replaced
+    pub fn sanitized(self) -> Self {
+        let default = Self::default();
+        let valid =
+            |v: f64, min: f64, default: f64| if v.is_finite() { v.max(min) } else { default };
+        Self {
+            threshold: valid(self.threshold, 0.0, default.threshold),
+            factor: valid(self.factor, 0.0, default.factor),
+            max_gain: valid(self.max_gain, 1.0, default.max_gain),
+        }
+    }
+
+    /// gain for a motion event of the given speed
+    pub fn gain(&self, speed: f64) -> f64 {
+        if speed <= self.threshold {
+            1.0
+        } else {
+            (1.0 + self.factor * (speed - self.threshold)).min(self.max_gain)
+        }
+    }
+}
+
+/// per client pointer and scroll settings
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub struct PointerConfig {
+    /// multiplier applied to relative pointer motion
+    pub sensitivity: f64,
+    /// optional pointer acceleration
+    pub acceleration: Option<Acceleration>,
+    /// multiplier applied to scroll events
+    pub scroll_speed: f64,
+    /// invert the scroll direction ("natural" scrolling)
+    pub natural_scrolling: bool,
+}
+
+impl PointerConfig {
+    /// valid range of `sensitivity` and `scroll_speed`
+    pub const SPEED_RANGE: RangeInclusive<f64> = 0.1..=10.0;
+
+    /// the settings with `sensitivity` and `scroll_speed` clamped to
+    /// [`Self::SPEED_RANGE`], invalid values (NaN, infinite) are reset
+    pub fn sanitized(self) -> Self {
+        let default = Self::default();
+        let clamp = |v: f64, default: f64| {
+            if v.is_finite() {
+                v.clamp(*Self::SPEED_RANGE.start(), *Self::SPEED_RANGE.end())
+            } else {
+                default
+            }
+        };
+        Self {
+            sensitivity: clamp(self.sensitivity, default.sensitivity),
+            acceleration: self.acceleration.map(Acceleration::sanitized),
+            scroll_speed: clamp(self.scroll_speed, default.scroll_speed),
+            natural_scrolling: self.natural_scrolling,
+        }
+    }
+}
+
+impl Default for PointerConfig {
+    fn default() -> Self {
+        Self {
+            sensitivity: 1.0,
+            acceleration: None,
+            scroll_speed: 1.0,
+            natural_scrolling: false,
+        }
+    }
+}
+
+/// per client safeguards against accidentally crossing the screen edge.
+/// Input is only sent to the client once all enabled conditions are met.
+#[derive(Debug, Default, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub struct EdgePolicy {
+    /// time in milliseconds the pointer must be pushed against the edge
+    pub dwell_time: u64,
+    /// distance in pixels the pointer must be pushed through the edge
+    pub push_distance: f64,
+    /// minimum distance in pixels a single motion event must push through the edge
+    pub push_velocity: f64,
+    /// modifier key (linux scancode) that must be held while crossing
+    pub modifier: Option<u32>,
+    /// the edge must be hit twice in short succession
+    pub double_tap: bool,
+    /// length in pixels of the regions at both ends of the edge,
+    /// in which crossing the edge is not possible
+    pub corner_dead_zone: f64,
+}
+
+/// hardware address of a client, used for Wake-on-LAN
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct MacAddress(pub [u8; 6]);
+
+#[derive(Debug, Error)]
+#[error("not a valid mac address: {addr}")]
+pub struct MacAddressParseError {
+    addr: String,
+}
+
+impl FromStr for MacAddress {
+    type Err = MacAddressParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || MacAddressParseError { addr: s.into() };
+        let mut mac = [0u8; 6];
+        let mut octets = s.split([':', '-']);
+        for b in mac.iter_mut() {
+            let octet = octets.next().ok_or_else(err)?;
+            if octet.len() != 2 {
+                return Err(err());
+            }
+            *b = u8::from_str_radix(octet, 16).map_err(|_| err())?;
+        }
+        match octets.next() {
+            Some(_) => Err(err()),
+            None => Ok(Self(mac)),
+        }
+    }
+}
+
+impl TryFrom<String> for MacAddress {
+    type Error = MacAddressParseError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<MacAddress> for String {
+    fn from(mac: MacAddress) -> Self {
+        mac.to_string()
+    }
+}
+
+impl Display for MacAddress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let [a, b, c, d, e, g] = self.0;
+        write!(f, "{a:02x}:{b:02x}:{c:02x}:{d:02x}:{e:02x}:{g:02x}")
+    }
+}
+
+/// transport used to connect to a client
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Transport {
+    /// DTLS, falling back to TLS over TCP if the client can not be reached
+    #[default]
+    Auto,
+    /// DTLS only
+    Udp,
+    /// TLS over TCP only
+    Tcp,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct ClientConfig {
     /// hostname of this client
     pub hostname: Option<String>,
@@ -140,6 +323,20 @@ pub struct ClientConfig {
     pub pos: Position,
     /// enter hook
     pub cmd: Option<String>,
+    /// pointer and scroll settings
+    pub pointer: PointerConfig,
+    /// key remap table (linux scancodes)
+    pub key_remap: HashMap<u32, u32>,
+    /// edge crossing safeguards
+    pub edge: EdgePolicy,
+    /// hardware address for Wake-on-LAN
+    pub mac_address: Option<MacAddress>,
+    /// pinned certificate fingerprint of the client (trusted on first use)
+    pub fingerprint: Option<String>,
+    /// transport used to connect to the client
+    pub transport: Transport,
+    /// relay used to reach the client (`host:port`)
+    pub relay: Option<String>,
 }
 
 impl Default for ClientConfig {
@@ -150,12 +347,41 @@ impl Default for ClientConfig {
             fix_ips: Default::default(),
             pos: Default::default(),
             cmd: None,
+            pointer: Default::default(),
+            key_remap: Default::default(),
+            edge: Default::default(),
+            mac_address: None,
+            fingerprint: None,
+            transport: Default::default(),
+            relay: None,
         }
     }
 }
 
 pub type ClientHandle = u64;
 
+/// state of the outgoing connection to a client
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub enum ConnectionState {
+    #[default]
+    Disconnected,
+    Connecting,
+    Connected,
+    /// the last attempt failed, connecting is retried with a backoff
+    Failed(String),
+}
+
+impl Display for ConnectionState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConnectionState::Disconnected => write!(f, "disconnected"),
+            ConnectionState::Connecting => write!(f, "connecting"),
+            ConnectionState::Connected => write!(f, "connected"),
+            ConnectionState::Failed(reason) => write!(f, "failed: {reason}"),
+        }
+    }
+}
+
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct ClientState {
     /// events should be sent to and received from the client
@@ -168,6 +394,9 @@ pub struct ClientState {
     pub alive: bool,
     /// ips from dns
     pub dns_ips: Vec<IpAddr>,
+    /// ips the device was last seen at (incoming connections, mDNS),
+    /// recognized by its certificate fingerprint
+    pub seen_ips: Vec<IpAddr>,
     /// all ip addresses associated with a particular client
     /// e.g. Laptops usually have at least an ethernet and a wifi port
     /// which have different ip addresses
@@ -176,6 +405,11 @@ pub struct ClientState {
     pub has_pressed_keys: bool,
     /// dns resolving in progress
     pub resolving: bool,
+    /// state of the outgoing connection
+    pub connection: ConnectionState,
+    /// address of the incoming connection of the same device,
+    /// recognized by its certificate fingerprint
+    pub incoming: Option<SocketAddr>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -217,9 +451,270 @@ pub enum FrontendEvent {
     IncomingDisconnected(SocketAddr),
     /// failed connection attempt (approval for fingerprint required)
     ConnectionAttempt { fingerprint: String },
+    /// input capture for the client ended
+    CaptureReleased(ClientHandle, ReleaseReason),
+    /// another lan-mouse instance was found on the local network
+    PeerDiscovered(DiscoveredPeer),
+    /// pairing finished: the code must match the one shown on the other device
+    /// before the pairing is confirmed
+    PairingCode { fingerprint: String, code: String },
+    /// the certificate of this device expires soon (negative: has expired)
+    CertificateExpiring { days_left: i64 },
+    /// the client presented a certificate that does not match the pinned one.
+    /// No input is sent to the client until the new fingerprint is accepted.
+    FingerprintMismatch {
+        handle: ClientHandle,
+        expected: String,
+        received: String,
+    },
+    /// entries of the audit log matching a [`FrontendRequest::QueryAudit`]
+    AuditLog(Vec<AuditEntry>),
+    /// a device exceeded a limit protecting this device and was throttled or disconnected
+    LimitExceeded { ip: IpAddr, limit: InboundLimit },
+}
+
+/// a lan-mouse instance found via mDNS / DNS-SD
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct DiscoveredPeer {
+    /// device name advertised by the peer
+    pub name: String,
+    /// certificate fingerprint advertised by the peer
+    pub fingerprint: String,
+    /// address the peer was discovered at
+    pub ip: IpAddr,
+    /// port the peer is listening on
+    pub port: u16,
+    /// suggested position for the peer (first unused position)
+    pub pos: Position,
+}
+
+/// an entry of the audit log, one JSON object per line
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// time of the event (RFC 3339, UTC)
+    pub time: String,
+    #[serde(flatten)]
+    pub event: AuditEvent,
+}
+
+/// security relevant event recorded in the audit log
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum AuditEvent {
+    /// a device connected to this device
+    Accepted {
+        addr: SocketAddr,
+        fingerprint: String,
+        /// the device is not authorized and was only accepted for pairing
+        pairing: bool,
+    },
+    /// the connection of an unauthorized device was rejected
+    Rejected { fingerprint: String },
+    /// a connected device entered this device for the first time
+    Entered {
+        addr: SocketAddr,
+        fingerprint: String,
+    },
+    /// a connected device disconnected or stopped responding
+    Disconnected { addr: SocketAddr },
+    /// a fingerprint was authorized
+    Authorized {
+        fingerprint: String,
+        description: String,
+        /// the authorization is revoked after this many seconds
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        expires_after: Option<u64>,
+        /// the authorization is revoked when the device disconnects
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        session: bool,
+    },
+    /// the authorization of a fingerprint was removed or expired
+    Deauthorized { fingerprint: String },
+    /// an authorized device rotated its key
+    KeyTransition {
+        previous: String,
+        fingerprint: String,
+    },
+    /// this device connected to a client
+    Connected {
+        handle: ClientHandle,
+        addr: SocketAddr,
+        fingerprint: String,
+    },
+    /// a client presented a certificate not matching its pinned fingerprint
+    FingerprintMismatch {
+        handle: ClientHandle,
+        expected: String,
+        received: String,
+    },
+    /// a device exceeded a limit protecting this device
+    LimitExceeded { ip: IpAddr, limit: InboundLimit },
 }
 
-#[derive(Debug, Eq, PartialEq, Clone, Serialize, Deserialize)]
+impl AuditEvent {
+    /// fingerprints involved in the event
+    pub fn fingerprints(&self) -> Vec<&str> {
+        match self {
+            AuditEvent::Accepted { fingerprint, .. }
+            | AuditEvent::Rejected { fingerprint }
+            | AuditEvent::Entered { fingerprint, .. }
+            | AuditEvent::Authorized { fingerprint, .. }
+            | AuditEvent::Deauthorized { fingerprint }
+            | AuditEvent::Connected { fingerprint, .. } => vec![fingerprint],
+            AuditEvent::KeyTransition {
+                previous,
+                fingerprint,
+            } => vec![previous, fingerprint],
+            AuditEvent::FingerprintMismatch {
+                expected, received, ..
+            } => vec![expected, received],
+            AuditEvent::Disconnected { .. } | AuditEvent::LimitExceeded { .. } => vec![],
+        }
+    }
+
+    /// ip address of the device, if known
+    pub fn ip(&self) -> Option<IpAddr> {
+        match self {
+            AuditEvent::Accepted { addr, .. }
+            | AuditEvent::Entered { addr, .. }
+            | AuditEvent::Disconnected { addr }
+            | AuditEvent::Connected { addr, .. } => Some(addr.ip()),
+            AuditEvent::LimitExceeded { ip, .. } => Some(*ip),
+            _ => None,
+        }
+    }
+}
+
+impl Display for AuditEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AuditEvent::Accepted {
+                addr,
+                fingerprint,
+                pairing: false,
+            } => write!(f, "accepted {addr} ({fingerprint})"),
+            AuditEvent::Accepted {
+                addr,
+                fingerprint,
+                pairing: true,
+            } => write!(f, "accepted {addr} ({fingerprint}) for pairing"),
+            AuditEvent::Rejected { fingerprint } => write!(f, "rejected {fingerprint}"),
+            AuditEvent::Entered { addr, fingerprint } => {
+                write!(f, "{addr} ({fingerprint}) entered")
+            }
+            AuditEvent::Disconnected { addr } => write!(f, "{addr} disconnected"),
+            AuditEvent::Authorized {
+                fingerprint,
+                description,
+                expires_after,
+                session,
+            } => {
+                write!(f, "authorized {fingerprint} ({description})")?;
+                if let Some(secs) = expires_after {
+                    write!(f, " for {secs}s")?;
+                }
+                if *session {
+                    write!(f, " for one session")?;
+                }
+                Ok(())
+            }
+            AuditEvent::Deauthorized { fingerprint } => write!(f, "deauthorized {fingerprint}"),
+            AuditEvent::KeyTransition {
+                previous,
+                fingerprint,
+            } => write!(f, "{previous} rotated its key to {fingerprint}"),
+            AuditEvent::Connected {
+                handle,
+                addr,
+                fingerprint,
+            } => write!(f, "connected to client {handle} @ {addr} ({fingerprint})"),
+            AuditEvent::FingerprintMismatch {
+                handle,
+                expected,
+                received,
+            } => write!(
+                f,
+                "client {handle} presented {received} instead of {expected}"
+            ),
+            AuditEvent::LimitExceeded { ip, limit } => write!(f, "{ip}: {limit}"),
+        }
+    }
+}
+
+/// limits protecting this device against floods of inbound connections and events
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum InboundLimit {
+    /// too many handshakes within a minute, further handshakes are dropped
+    HandshakeRate,
+    /// repeatedly rejected, handshakes are dropped for a while
+    Banned,
+    /// too many events per second, the connection was closed
+    EventRate,
+    /// the maximum number of incoming connections is reached
+    MaxConnections,
+}
+
+impl Display for InboundLimit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let limit = match self {
+            InboundLimit::HandshakeRate => "too many connection attempts, throttled",
+            InboundLimit::Banned => "repeatedly rejected, temporarily banned",
+            InboundLimit::EventRate => "too many events per second, disconnected",
+            InboundLimit::MaxConnections => "too many incoming connections, refused",
+        };
+        write!(f, "{limit}")
+    }
+}
+
+/// filter for entries of the audit log
+#[derive(Debug, Default, PartialEq, Clone, Serialize, Deserialize)]
+pub struct AuditQuery {
+    /// only entries involving this fingerprint
+    pub fingerprint: Option<String>,
+    /// only entries involving this address
+    pub ip: Option<IpAddr>,
+    /// only entries recorded after this time
+    pub since: Option<SystemTime>,
+    /// at most this many (of the most recent) entries
+    pub limit: Option<usize>,
+}
+
+/// reason why captured input was returned to this device
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum ReleaseReason {
+    /// the release bind was pressed
+    ReleaseBind,
+    /// the pointer left the device region of the remote client
+    Left,
+    /// release requested by a frontend or a key binding
+    Requested,
+    /// another device entered this device
+    DeviceEntered,
+    /// no input was sent for the configured idle timeout
+    IdleTimeout,
+    /// input capture was disabled
+    Disabled,
+    /// the client was deactivated or removed
+    Deactivated,
+}
+
+impl Display for ReleaseReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let reason = match self {
+            ReleaseReason::ReleaseBind => "release bind pressed",
+            ReleaseReason::Left => "left the remote device",
+            ReleaseReason::Requested => "release requested",
+            ReleaseReason::DeviceEntered => "another device entered",
+            ReleaseReason::IdleTimeout => "idle timeout",
+            ReleaseReason::Disabled => "capture disabled",
+            ReleaseReason::Deactivated => "client deactivated",
+        };
+        write!(f, "{reason}")
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub enum FrontendRequest {
     /// activate/deactivate client
     Activate(ClientHandle, bool),
@@ -249,10 +744,38 @@ pub enum FrontendRequest {
     Sync,
     /// authorize fingerprint (description, fingerprint)
     AuthorizeKey(String, String),
+    /// authorize fingerprint for the given time (description, fingerprint, duration)
+    AuthorizeKeyTemporarily(String, String, Duration),
+    /// authorize fingerprint until the device disconnects (description, fingerprint)
+    AuthorizeKeyForSession(String, String),
     /// remove fingerprint (fingerprint)
     RemoveAuthorizedKey(String),
     /// change the hook command
     UpdateEnterHook(u64, Option<String>),
+    /// update pointer and scroll settings
+    UpdatePointerConfig(ClientHandle, PointerConfig),
+    /// grab input and start sending it to the client
+    Enter(ClientHandle),
+    /// release captured input
+    Release,
+    /// pin input to the currently entered client or unpin it
+    ToggleLock,
+    /// send a Wake-on-LAN packet to the client
+    Wake(ClientHandle),
+    /// request all peers discovered on the local network
+    EnumerateDiscovered,
+    /// add a discovered peer (fingerprint) as a client
+    AddDiscovered(String),
+    /// accept pairing requests from other devices for a while
+    StartPairing,
+    /// pair with the device listening at the given address
+    Pair(SocketAddr),
+    /// the pairing code matched: authorize the fingerprint of the paired device
+    ConfirmPairing(String),
+    /// pin a new certificate fingerprint for the client (e.g. after it changed)
+    AcceptFingerprint(ClientHandle, String),
+    /// request entries of the audit log
+    QueryAudit(AuditQuery),
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
@@ -297,3 +820,55 @@ pub fn default_socket_path() -> Result<PathBuf, SocketPathError> {
         .join("Caches")
         .join(LAN_MOUSE_SOCKET_NAME))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pointer_config_is_clamped() {
+        let pointer = PointerConfig {
+            sensitivity: -1.0,
+            acceleration: None,
+            scroll_speed: 1000.0,
+            natural_scrolling: true,
+        }
+        .sanitized();
+        assert_eq!(pointer.sensitivity, 0.1);
+        assert_eq!(pointer.scroll_speed, 10.0);
+        assert!(pointer.natural_scrolling);
+    }
+
+    #[test]
+    fn invalid_pointer_config_is_reset() {
+        let pointer = PointerConfig {
+            sensitivity: f64::NAN,
+            acceleration: Some(Acceleration {
+                threshold: f64::NAN,
+                factor: -1.0,
+                max_gain: 0.0,
+            }),
+            scroll_speed: f64::INFINITY,
+            natural_scrolling: false,
+        }
+        .sanitized();
+        let default = PointerConfig::default();
+        assert_eq!(pointer.sensitivity, default.sensitivity);
+        assert_eq!(pointer.scroll_speed, default.scroll_speed);
+        let acceleration = pointer.acceleration.expect("acceleration");
+        assert_eq!(acceleration.threshold, Acceleration::default().threshold);
+        assert_eq!(acceleration.factor, 0.0);
+        assert_eq!(acceleration.max_gain, 1.0);
+    }
+
+    #[test]
+    fn valid_pointer_config_is_kept() {
+        let pointer = PointerConfig {
+            sensitivity: 2.5,
+            acceleration: Some(Acceleration::default()),
+            scroll_speed: 0.5,
+            natural_scrolling: false,
+        };
+        assert_eq!(pointer.sanitized(), pointer);
+    }
+}
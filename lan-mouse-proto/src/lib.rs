@@ -63,6 +63,14 @@ pub enum ProtoEvent {
     Ping,
     /// Response to [`ProtoEvent::Ping`], true if emulation is enabled / available
     Pong(bool),
+    /// notify a client that the screen of this device was locked (true) or unlocked (false)
+    ScreenLock(bool),
+    /// start pairing: commitment to the nonce of the initiator
+    /// (see [`ProtoEvent::PairNonce`])
+    PairCommit(u128),
+    /// pairing nonce, sent by the responder after receiving [`ProtoEvent::PairCommit`]
+    /// and revealed by the initiator afterwards
+    PairNonce(u128),
 }
 
 impl Display for ProtoEvent {
@@ -80,6 +88,11 @@ impl Display for ProtoEvent {
                     if *alive { "alive" } else { "not available" }
                 )
             }
+            ProtoEvent::ScreenLock(locked) => {
+                write!(f, "screen {}", if *locked { "locked" } else { "unlocked" })
+            }
+            ProtoEvent::PairCommit(_) => write!(f, "pair-commit"),
+            ProtoEvent::PairNonce(_) => write!(f, "pair-nonce"),
         }
     }
 }
@@ -98,6 +111,9 @@ pub enum EventType {
     Enter,
     Leave,
     Ack,
+    ScreenLock,
+    PairCommit,
+    PairNonce,
 }
 
 impl ProtoEvent {
@@ -120,6 +136,9 @@ impl ProtoEvent {
             ProtoEvent::Enter(_) => EventType::Enter,
             ProtoEvent::Leave(_) => EventType::Leave,
             ProtoEvent::Ack(_) => EventType::Ack,
+            ProtoEvent::ScreenLock(_) => EventType::ScreenLock,
+            ProtoEvent::PairCommit(_) => EventType::PairCommit,
+            ProtoEvent::PairNonce(_) => EventType::PairNonce,
         }
     }
 }
@@ -174,6 +193,9 @@ impl TryFrom<[u8; MAX_EVENT_SIZE]> for ProtoEvent {
             EventType::Enter => Ok(Self::Enter(decode_u8(&mut buf)?.try_into()?)),
             EventType::Leave => Ok(Self::Leave(decode_u32(&mut buf)?)),
             EventType::Ack => Ok(Self::Ack(decode_u32(&mut buf)?)),
+            EventType::ScreenLock => Ok(Self::ScreenLock(decode_u8(&mut buf)? != 0)),
+            EventType::PairCommit => Ok(Self::PairCommit(decode_u128(&mut buf)?)),
+            EventType::PairNonce => Ok(Self::PairNonce(decode_u128(&mut buf)?)),
         }
     }
 }
@@ -238,6 +260,9 @@ impl From<ProtoEvent> for ([u8; MAX_EVENT_SIZE], usize) {
                 ProtoEvent::Enter(pos) => encode_u8(buf, len, pos as u8),
                 ProtoEvent::Leave(serial) => encode_u32(buf, len, serial),
                 ProtoEvent::Ack(serial) => encode_u32(buf, len, serial),
+                ProtoEvent::ScreenLock(locked) => encode_u8(buf, len, locked as u8),
+                ProtoEvent::PairCommit(commit) => encode_u128(buf, len, commit),
+                ProtoEvent::PairNonce(nonce) => encode_u128(buf, len, nonce),
             }
         }
         (buf, len)
@@ -260,6 +285,7 @@ decode_impl!(u8);
 decode_impl!(u32);
 decode_impl!(i32);
 decode_impl!(f64);
+decode_impl!(u128);
 
 macro_rules! encode_impl {
     ($t:ty) => {
@@ -280,3 +306,4 @@ encode_impl!(u8);
 encode_impl!(u32);
 encode_impl!(i32);
 encode_impl!(f64);
+encode_impl!(u128);
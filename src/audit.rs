@@ -0,0 +1,292 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    ffi::OsString,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    iter,
+    path::PathBuf,
+    time::{Duration, Instant, SystemTime},
+};
+
+use lan_mouse_ipc::{AuditEntry, AuditEvent, AuditQuery};
+use time::{OffsetDateTime, format_description::well_known::Rfc3339};
+
+use crate::config::AuditConfig;
+
+/// rejected connection attempts of a device are recorded at most once in this interval
+const REJECTED_INTERVAL: Duration = Duration::from_secs(60);
+
+/// append-only log of connections and authorization changes.
+///
+/// Each event is written as one JSON object per line.
+/// Once the log exceeds its maximum size, it is renamed to `<path>.1`,
+/// older logs are shifted to `<path>.2` ... `<path>.<keep>` and the oldest is dropped.
+pub(crate) struct AuditLog {
+    path: PathBuf,
+    max_size: u64,
+    keep: usize,
+    /// last time a rejected attempt was recorded, by fingerprint
+    rejected: HashMap<String, Instant>,
+}
+
+impl AuditLog {
+    pub(crate) fn new(config: AuditConfig) -> Self {
+        log::info!("audit log: {}", config.path.display());
+        Self {
+            path: config.path,
+            max_size: config.max_size,
+            keep: config.keep,
+            rejected: Default::default(),
+        }
+    }
+
+    pub(crate) fn record(&mut self, event: AuditEvent) {
+        // unauthorized devices retry connecting continuously
+        if let AuditEvent::Rejected { fingerprint } = &event {
+            let now = Instant::now();
+            self.rejected
+                .retain(|_, t| now.duration_since(*t) < REJECTED_INTERVAL);
+            if self.rejected.contains_key(fingerprint) {
+                return;
+            }
+            self.rejected.insert(fingerprint.clone(), now);
+        }
+        if let Err(e) = self.append(event) {
+            log::warn!("{}: failed to write audit log: {e}", self.path.display());
+        }
+    }
+
+    fn append(&self, event: AuditEvent) -> io::Result<()> {
+        let entry = AuditEntry {
+            time: OffsetDateTime::now_utc()
+                .format(&Rfc3339)
+                .map_err(io::Error::other)?,
+            event,
+        };
+        let mut line = serde_json::to_string(&entry)?;
+        line.push('\n');
+        if fs::metadata(&self.path).is_ok_and(|m| m.len() + line.len() as u64 > self.max_size) {
+            self.rotate()?;
+        }
+        let mut options = OpenOptions::new();
+        options.create(true).append(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        options.open(&self.path)?.write_all(line.as_bytes())
+    }
+
+    fn rotate(&self) -> io::Result<()> {
+        if self.keep == 0 {
+            return fs::remove_file(&self.path);
+        }
+        for i in (1..self.keep).rev() {
+            match fs::rename(self.rotated(i), self.rotated(i + 1)) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+        }
+        fs::rename(&self.path, self.rotated(1))
+    }
+
+    /// path of the `i`-th most recent rotated log
+    fn rotated(&self, i: usize) -> PathBuf {
+        let mut path = OsString::from(&self.path);
+        path.push(format!(".{i}"));
+        path.into()
+    }
+
+    /// the most recent entries matching the query, oldest first
+    pub(crate) fn query(&self, query: &AuditQuery) -> Vec<AuditEntry> {
+        let limit = query.limit.unwrap_or(usize::MAX);
+        let files = (1..=self.keep)
+            .rev()
+            .map(|i| self.rotated(i))
+            .chain(iter::once(self.path.clone()));
+        let mut entries = VecDeque::new();
+        for file in files {
+            let Ok(content) = fs::read_to_string(&file) else {
+                continue;
+            };
+            for line in content.lines() {
+                let entry = match serde_json::from_str::<AuditEntry>(line) {
+                    Ok(entry) => entry,
+                    Err(e) => {
+                        log::debug!("{}: invalid audit entry: {e}", file.display());
+                        continue;
+                    }
+                };
+                if matches(query, &entry) {
+                    entries.push_back(entry);
+                    if entries.len() > limit {
+                        entries.pop_front();
+                    }
+                }
+            }
+        }
+        entries.into()
+    }
+}
+
+fn matches(query: &AuditQuery, entry: &AuditEntry) -> bool {
+    if let Some(fingerprint) = &query.fingerprint {
+        if !entry.event.fingerprints().contains(&fingerprint.as_str()) {
+            return false;
+        }
+    }
+    if let Some(ip) = query.ip {
+        if entry.event.ip() != Some(ip) {
+            return false;
+        }
+    }
+    if let Some(since) = query.since {
+        let time = OffsetDateTime::parse(&entry.time, &Rfc3339).map(SystemTime::from);
+        if time.is_ok_and(|t| t < since) {
+            return false;
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::SocketAddr, path::Path};
+
+    use super::*;
+
+    /// directory removed after the test
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir =
+                std::env::temp_dir().join(format!("lan-mouse-audit-{name}-{}", std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).expect("create dir");
+            Self(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn audit_log(dir: &Path, max_size: u64, keep: usize) -> AuditLog {
+        AuditLog::new(AuditConfig {
+            path: dir.join("audit.jsonl"),
+            max_size,
+            keep,
+        })
+    }
+
+    fn disconnected(port: u16) -> AuditEvent {
+        AuditEvent::Disconnected {
+            addr: SocketAddr::from(([192, 168, 1, 2], port)),
+        }
+    }
+
+    fn ports(entries: Vec<AuditEntry>) -> Vec<u16> {
+        entries
+            .into_iter()
+            .map(|e| match e.event {
+                AuditEvent::Disconnected { addr } => addr.port(),
+                e => panic!("unexpected {e:?}"),
+            })
+            .collect()
+    }
+
+    fn line_count(path: &Path) -> usize {
+        fs::read_to_string(path).map_or(0, |c| c.lines().count())
+    }
+
+    #[test]
+    fn rotates_and_drops_oldest() {
+        let dir = TempDir::new("rotate");
+        // each entry is rotated into its own file
+        let mut log = audit_log(&dir.0, 1, 2);
+        for port in 1..=4 {
+            log.record(disconnected(port));
+        }
+        assert_eq!(line_count(&log.path), 1);
+        assert_eq!(line_count(&log.rotated(1)), 1);
+        assert_eq!(line_count(&log.rotated(2)), 1);
+        assert!(!log.rotated(3).exists());
+        // entry 1 was dropped, the rest is returned oldest first
+        assert_eq!(ports(log.query(&Default::default())), vec![2, 3, 4]);
+    }
+
+    #[test]
+    fn rotates_by_size() {
+        let dir = TempDir::new("size");
+        let mut log = audit_log(&dir.0, 1024, 1);
+        log.record(disconnected(1));
+        let entry_size = fs::metadata(&log.path).expect("log").len();
+        // timestamps differ in length by a few bytes
+        log.max_size = entry_size * 3 + entry_size / 2;
+        log.record(disconnected(2));
+        log.record(disconnected(3));
+        assert_eq!(line_count(&log.path), 3);
+        log.record(disconnected(4));
+        assert_eq!(line_count(&log.path), 1);
+        assert_eq!(line_count(&log.rotated(1)), 3);
+    }
+
+    #[test]
+    fn keep_zero_removes_the_log() {
+        let dir = TempDir::new("keep0");
+        let mut log = audit_log(&dir.0, 1, 0);
+        log.record(disconnected(1));
+        log.record(disconnected(2));
+        assert!(!log.rotated(1).exists());
+        assert_eq!(ports(log.query(&Default::default())), vec![2]);
+    }
+
+    #[test]
+    fn query_filters_and_limits() {
+        let dir = TempDir::new("query");
+        let mut log = audit_log(&dir.0, 1 << 20, 1);
+        for port in 1..=5 {
+            log.record(disconnected(port));
+        }
+        log.record(AuditEvent::Deauthorized {
+            fingerprint: "fp".into(),
+        });
+        let limited = AuditQuery {
+            limit: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(log.query(&limited).len(), 2);
+        let by_fingerprint = AuditQuery {
+            fingerprint: Some("fp".into()),
+            ..Default::default()
+        };
+        assert_eq!(log.query(&by_fingerprint).len(), 1);
+        let by_ip = AuditQuery {
+            ip: Some([192, 168, 1, 2].into()),
+            limit: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(ports(log.query(&by_ip)), vec![4, 5]);
+        let future = AuditQuery {
+            since: Some(SystemTime::now() + Duration::from_secs(60)),
+            ..Default::default()
+        };
+        assert!(log.query(&future).is_empty());
+    }
+
+    #[test]
+    fn repeated_rejections_are_recorded_once() {
+        let dir = TempDir::new("rejected");
+        let mut log = audit_log(&dir.0, 1 << 20, 1);
+        for _ in 0..3 {
+            log.record(AuditEvent::Rejected {
+                fingerprint: "fp-a".into(),
+            });
+        }
+        log.record(AuditEvent::Rejected {
+            fingerprint: "fp-b".into(),
+        });
+        assert_eq!(line_count(&log.path), 2);
+    }
+}
@@ -0,0 +1,404 @@
+use std::{
+    fs, io,
+    net::IpAddr,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::SystemTime,
+};
+
+use rustls::pki_types::{
+    CertificateDer, CertificateRevocationListDer, TrustAnchor, UnixTime, pem::PemObject,
+};
+use thiserror::Error;
+use webpki::{
+    CertRevocationList, EndEntityCert, KeyUsage, OwnedCertRevocationList, RevocationOptionsBuilder,
+};
+use x509_parser::{
+    certificate::X509Certificate,
+    error::X509Error,
+    extensions::GeneralName,
+    objects::{oid_registry, oid2abbrev},
+    prelude::FromDer,
+};
+
+use crate::config::CertificateAuthorityConfig;
+
+#[derive(Debug, Error)]
+pub enum CaError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("invalid PEM file: {0}")]
+    Pem(#[from] rustls::pki_types::pem::Error),
+    #[error("no CA certificate found in `{0}`")]
+    NoCaCertificate(PathBuf),
+    #[error("invalid subject constraint `{0}`, expected `ATTRIBUTE=value`")]
+    InvalidSubjectConstraint(String),
+    #[error("certificate not trusted: {0}")]
+    Webpki(#[from] webpki::Error),
+    #[error("invalid certificate: {0}")]
+    X509(#[from] x509_parser::nom::Err<X509Error>),
+    #[error("no certificate presented")]
+    NoCertificate,
+    #[error("certificate is not issued for an allowed name")]
+    NameNotAllowed,
+    #[error("certificate subject does not contain `{0}`")]
+    SubjectMismatch(String),
+    #[error("certificate is not issued for `{0}`")]
+    HostnameMismatch(String),
+}
+
+/// an organizational certificate authority.
+///
+/// Certificates chaining to one of its CA certificates, which are not revoked
+/// and satisfy the name and subject constraints, are trusted without their
+/// fingerprint being authorized.
+///
+/// The revocation lists are reloaded whenever the CRL file is modified.
+pub(crate) struct CertificateAuthority {
+    anchors: Vec<TrustAnchor<'static>>,
+    crl_path: Option<PathBuf>,
+    crls: Mutex<Crls>,
+    allowed_names: Vec<String>,
+    required_subject: Vec<(String, String)>,
+}
+
+#[derive(Default)]
+struct Crls {
+    modified: Option<SystemTime>,
+    crls: Vec<CertRevocationList<'static>>,
+}
+
+impl CertificateAuthority {
+    pub(crate) fn load(config: &CertificateAuthorityConfig) -> Result<Self, CaError> {
+        let anchors = CertificateDer::pem_file_iter(&config.bundle)?
+            .map(|der| Ok(webpki::anchor_from_trusted_cert(&der?)?.to_owned()))
+            .collect::<Result<Vec<_>, CaError>>()?;
+        if anchors.is_empty() {
+            return Err(CaError::NoCaCertificate(config.bundle.clone()));
+        }
+        let required_subject = config
+            .required_subject
+            .iter()
+            .map(|c| match c.split_once('=') {
+                Some((attr, value)) => Ok((attr.trim().to_owned(), value.trim().to_owned())),
+                None => Err(CaError::InvalidSubjectConstraint(c.clone())),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let crls = match &config.crl {
+            Some(path) => Crls {
+                modified: fs::metadata(path)?.modified().ok(),
+                crls: load_crls(path)?,
+            },
+            None => Default::default(),
+        };
+        log::info!(
+            "trusting certificates issued by {} CA certificate(s) in {}",
+            anchors.len(),
+            config.bundle.display()
+        );
+        Ok(Self {
+            anchors,
+            crl_path: config.crl.clone(),
+            crls: Mutex::new(crls),
+            allowed_names: config
+                .allowed_names
+                .iter()
+                .map(|n| n.to_ascii_lowercase())
+                .collect(),
+            required_subject,
+        })
+    }
+
+    /// verify the certificate chain of a device connecting to this device
+    pub(crate) fn verify_client(&self, certs: &[Vec<u8>]) -> Result<(), CaError> {
+        self.verify(certs, KeyUsage::client_auth())
+    }
+
+    /// verify the certificate chain of a device this device connects to.
+    /// The certificate must name the configured `hostname` of the device
+    /// in its subject alternative names.
+    pub(crate) fn verify_server(&self, certs: &[Vec<u8>], hostname: &str) -> Result<(), CaError> {
+        self.verify(certs, KeyUsage::server_auth())?;
+        let end_entity = certs.first().ok_or(CaError::NoCertificate)?;
+        let (_, cert) = X509Certificate::from_der(end_entity)?;
+        let hostname = hostname.to_ascii_lowercase();
+        if !names_host(&cert, &hostname) {
+            return Err(CaError::HostnameMismatch(hostname));
+        }
+        Ok(())
+    }
+
+    fn verify(&self, certs: &[Vec<u8>], usage: KeyUsage) -> Result<(), CaError> {
+        let (end_entity, intermediates) = certs.split_first().ok_or(CaError::NoCertificate)?;
+        let end_entity_der = CertificateDer::from(end_entity.as_slice());
+        let intermediates = intermediates
+            .iter()
+            .map(|c| CertificateDer::from(c.as_slice()))
+            .collect::<Vec<_>>();
+        let cert = EndEntityCert::try_from(&end_entity_der)?;
+
+        self.reload_crls();
+        let crls = self.crls.lock().expect("lock");
+        let crls = crls.crls.iter().collect::<Vec<_>>();
+        let revocation = RevocationOptionsBuilder::new(&crls)
+            .ok()
+            .map(|builder| builder.build());
+        cert.verify_for_usage(
+            webpki::ALL_VERIFICATION_ALGS,
+            &self.anchors,
+            &intermediates,
+            UnixTime::now(),
+            usage,
+            revocation,
+            None,
+        )?;
+        self.check_constraints(end_entity)
+    }
+
+    fn check_constraints(&self, cert: &[u8]) -> Result<(), CaError> {
+        let (_, cert) = X509Certificate::from_der(cert)?;
+        if !self.allowed_names.is_empty() {
+            let allowed = dns_names(&cert).iter().any(|name| {
+                self.allowed_names
+                    .iter()
+                    .any(|pattern| name_matches(pattern, name))
+            });
+            if !allowed {
+                return Err(CaError::NameNotAllowed);
+            }
+        }
+        for (attr, value) in &self.required_subject {
+            let found = cert.subject().iter_attributes().any(|a| {
+                oid2abbrev(a.attr_type(), oid_registry())
+                    .is_ok_and(|a| a.eq_ignore_ascii_case(attr))
+                    && a.as_str().is_ok_and(|v| v == value)
+            });
+            if !found {
+                return Err(CaError::SubjectMismatch(format!("{attr}={value}")));
+            }
+        }
+        Ok(())
+    }
+
+    /// reload the revocation lists if the CRL file changed
+    fn reload_crls(&self) {
+        let Some(path) = &self.crl_path else {
+            return;
+        };
+        let modified = fs::metadata(path).and_then(|m| m.modified()).ok();
+        let mut crls = self.crls.lock().expect("lock");
+        if modified == crls.modified {
+            return;
+        }
+        match load_crls(path) {
+            Ok(new_crls) => {
+                log::info!(
+                    "reloaded certificate revocation lists from {}",
+                    path.display()
+                );
+                *crls = Crls {
+                    modified,
+                    crls: new_crls,
+                };
+            }
+            Err(e) => {
+                log::warn!("failed to reload {}: {e}", path.display());
+                // keep the previous lists until the file changes again
+                crls.modified = modified;
+            }
+        }
+    }
+}
+
+/// revocation lists in PEM format or a single DER encoded list
+fn load_crls(path: &Path) -> Result<Vec<CertRevocationList<'static>>, CaError> {
+    let bytes = fs::read(path)?;
+    let mut ders =
+        CertificateRevocationListDer::pem_slice_iter(&bytes).collect::<Result<Vec<_>, _>>()?;
+    if ders.is_empty() {
+        ders.push(CertificateRevocationListDer::from(bytes));
+    }
+    ders.iter()
+        .map(|der| Ok(OwnedCertRevocationList::from_der(der)?.into()))
+        .collect()
+}
+
+/// subject alternative names of the certificate
+fn alt_names<'a>(cert: &'a X509Certificate) -> impl Iterator<Item = &'a GeneralName<'a>> {
+    cert.subject_alternative_name()
+        .ok()
+        .flatten()
+        .into_iter()
+        .flat_map(|ext| ext.value.general_names.iter())
+}
+
+/// whether the subject alternative names of the certificate contain `hostname`,
+/// which is either a DNS name or an IP address
+fn names_host(cert: &X509Certificate, hostname: &str) -> bool {
+    let ip = hostname.parse::<IpAddr>().ok();
+    alt_names(cert).any(|name| match (name, ip) {
+        (GeneralName::DNSName(name), None) => name_matches(&name.to_ascii_lowercase(), hostname),
+        (GeneralName::IPAddress(bytes), Some(IpAddr::V4(ip))) => *bytes == ip.octets(),
+        (GeneralName::IPAddress(bytes), Some(IpAddr::V6(ip))) => *bytes == ip.octets(),
+        _ => false,
+    })
+}
+
+/// DNS subject alternative names and common names of the certificate
+fn dns_names(cert: &X509Certificate) -> Vec<String> {
+    let alt_names = alt_names(cert).filter_map(|name| match name {
+        GeneralName::DNSName(name) => Some(*name),
+        _ => None,
+    });
+    let common_names = cert
+        .subject()
+        .iter_common_name()
+        .filter_map(|cn| cn.as_str().ok());
+    alt_names
+        .chain(common_names)
+        .map(|name| name.to_ascii_lowercase())
+        .collect()
+}
+
+/// `*.example.com` matches any name within `example.com`
+fn name_matches(pattern: &str, name: &str) -> bool {
+    match pattern.strip_prefix("*.") {
+        Some(domain) => name
+            .strip_suffix(domain)
+            .is_some_and(|sub| sub.len() > 1 && sub.ends_with('.')),
+        None => pattern == name,
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use rcgen::{
+        BasicConstraints, CertificateParams, ExtendedKeyUsagePurpose, IsCa, KeyPair, SanType,
+    };
+
+    use super::*;
+
+    pub(crate) struct Issuer {
+        cert: rcgen::Certificate,
+        key: KeyPair,
+    }
+
+    impl Issuer {
+        pub(crate) fn new() -> Self {
+            let key = KeyPair::generate().expect("key");
+            let mut params = CertificateParams::new(vec![]).expect("params");
+            params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+            let cert = params.self_signed(&key).expect("ca certificate");
+            Self { cert, key }
+        }
+
+        /// server certificate chain with the given subject alternative names
+        pub(crate) fn issue(&self, names: Vec<SanType>) -> Vec<Vec<u8>> {
+            let key = KeyPair::generate().expect("key");
+            let mut params = CertificateParams::default();
+            params.subject_alt_names = names;
+            params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ServerAuth];
+            let cert = params
+                .signed_by(&key, &self.cert, &self.key)
+                .expect("certificate");
+            vec![cert.der().to_vec()]
+        }
+
+        pub(crate) fn authority(&self, allowed_names: Vec<String>) -> CertificateAuthority {
+            let bundle = std::env::temp_dir().join(format!(
+                "lan-mouse-ca-test-{}-{:?}.pem",
+                std::process::id(),
+                std::thread::current().id()
+            ));
+            fs::write(&bundle, self.cert.pem()).expect("write bundle");
+            let config = CertificateAuthorityConfig {
+                bundle: bundle.clone(),
+                crl: None,
+                allowed_names,
+                required_subject: vec![],
+                permissions: Default::default(),
+            };
+            let ca = CertificateAuthority::load(&config).expect("load ca");
+            fs::remove_file(bundle).expect("remove bundle");
+            ca
+        }
+    }
+
+    pub(crate) fn dns(name: &str) -> SanType {
+        SanType::DnsName(name.try_into().expect("dns name"))
+    }
+
+    #[test]
+    fn server_must_be_issued_for_hostname() {
+        let issuer = Issuer::new();
+        let ca = issuer.authority(vec![]);
+        let certs = issuer.issue(vec![dns("desktop.lan.example.com")]);
+        assert!(ca.verify_server(&certs, "desktop.lan.example.com").is_ok());
+        assert!(ca.verify_server(&certs, "Desktop.LAN.example.com").is_ok());
+        assert!(matches!(
+            ca.verify_server(&certs, "laptop.lan.example.com"),
+            Err(CaError::HostnameMismatch(_))
+        ));
+    }
+
+    #[test]
+    fn server_wildcard_and_ip_names() {
+        let issuer = Issuer::new();
+        let ca = issuer.authority(vec![]);
+        let certs = issuer.issue(vec![
+            dns("*.lan.example.com"),
+            SanType::IpAddress("192.168.1.10".parse().expect("ip")),
+        ]);
+        assert!(ca.verify_server(&certs, "laptop.lan.example.com").is_ok());
+        assert!(ca.verify_server(&certs, "lan.example.com").is_err());
+        assert!(ca.verify_server(&certs, "192.168.1.10").is_ok());
+        assert!(ca.verify_server(&certs, "192.168.1.11").is_err());
+    }
+
+    #[test]
+    fn common_name_does_not_name_the_host() {
+        let issuer = Issuer::new();
+        let ca = issuer.authority(vec![]);
+        let key = KeyPair::generate().expect("key");
+        let mut params = CertificateParams::default();
+        params
+            .distinguished_name
+            .push(rcgen::DnType::CommonName, "desktop.lan.example.com");
+        params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ServerAuth];
+        let cert = params
+            .signed_by(&key, &issuer.cert, &issuer.key)
+            .expect("certificate");
+        let certs = vec![cert.der().to_vec()];
+        assert!(ca.verify_server(&certs, "desktop.lan.example.com").is_err());
+    }
+
+    #[test]
+    fn other_issuer_is_not_trusted() {
+        let ca = Issuer::new().authority(vec![]);
+        let certs = Issuer::new().issue(vec![dns("desktop.lan.example.com")]);
+        assert!(matches!(
+            ca.verify_server(&certs, "desktop.lan.example.com"),
+            Err(CaError::Webpki(_))
+        ));
+    }
+
+    #[test]
+    fn allowed_names_apply_to_servers() {
+        let issuer = Issuer::new();
+        let ca = issuer.authority(vec!["*.lan.example.com".to_owned()]);
+        let certs = issuer.issue(vec![dns("desktop.example.org")]);
+        assert!(matches!(
+            ca.verify_server(&certs, "desktop.example.org"),
+            Err(CaError::NameNotAllowed)
+        ));
+    }
+
+    #[test]
+    fn wildcard_patterns() {
+        assert!(name_matches("*.example.com", "a.example.com"));
+        assert!(name_matches("*.example.com", "a.b.example.com"));
+        assert!(!name_matches("*.example.com", "example.com"));
+        assert!(!name_matches("*.example.com", "aexample.com"));
+        assert!(name_matches("example.com", "example.com"));
+    }
+}
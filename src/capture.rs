@@ -1,5 +1,6 @@
 use std::{
     cell::{Cell, RefCell},
+    collections::{HashMap, HashSet},
     rc::Rc,
     time::{Duration, Instant},
 };
@@ -8,13 +9,25 @@ use futures::StreamExt;
 use input_capture::{
     CaptureError, CaptureEvent, CaptureHandle, InputCapture, InputCaptureError, Position,
 };
-use input_event::scancode;
+use input_event::{Event, KeyboardEvent, scancode};
+use lan_mouse_ipc::ReleaseReason;
 use lan_mouse_proto::ProtoEvent;
 use local_channel::mpsc::{Receiver, Sender, channel};
-use tokio::task::{JoinHandle, spawn_local};
+use tokio::{
+    sync::mpsc::UnboundedReceiver,
+    task::{JoinHandle, spawn_local},
+    time,
+};
 use tokio_util::sync::CancellationToken;
 
-use crate::connect::LanMouseConnection;
+use crate::{
+    client::ClientManager,
+    config::{Action, Binding},
+    connect::LanMouseConnection,
+    edge::{EdgeGuard, Verdict},
+    keymap::{self, KeyRemapper},
+    pointer,
+};
 
 pub(crate) struct Capture {
     cancellation_token: CancellationToken,
@@ -37,6 +50,14 @@ pub(crate) enum ICaptureEvent {
     /// either the remote client leaving its device region,
     /// a new device entering the screen or the release bind.
     ClientEntered(u64),
+    /// capture of the given client ended
+    Released(CaptureHandle, ReleaseReason),
+    /// the client could not be entered on request
+    EnterFailed(CaptureHandle, String),
+    /// the toggle-emulation binding was triggered
+    ToggleEmulation,
+    /// a client locked (true) or unlocked (false) its screen
+    ScreenLock(bool),
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -52,20 +73,30 @@ pub(crate) enum CaptureType {
 #[derive(Clone, Copy, Debug)]
 enum CaptureRequest {
     /// capture must release the mouse
-    Release,
+    Release(ReleaseReason),
     /// add a capture client
     Create(CaptureHandle, Position, CaptureType),
     /// destory a capture client
     Destroy(CaptureHandle),
     /// reenable input capture
     Reenable,
+    /// grab input and enter the given client
+    Enter(CaptureHandle),
+    /// pin input to the active client or unpin it
+    ToggleLock,
+    /// notify active clients about a change of the screen lock state
+    ScreenLock(bool),
 }
 
 impl Capture {
     pub(crate) fn new(
         backend: Option<input_capture::Backend>,
         conn: LanMouseConnection,
+        client_manager: ClientManager,
         release_bind: Vec<scancode::Linux>,
+        bindings: Vec<Binding>,
+        idle_timeout: Option<Duration>,
+        idle_center_cursor: bool,
     ) -> Self {
         let (request_tx, request_rx) = channel();
         let (event_tx, event_rx) = channel();
@@ -73,13 +104,25 @@ impl Capture {
         let capture_task = CaptureTask {
             active_client: None,
             backend,
+            bindings,
+            broadcast: false,
             cancellation_token: cancellation_token.clone(),
             captures: Default::default(),
+            client_manager,
             conn,
+            edge_guard: Default::default(),
             event_tx,
+            idle_center_cursor,
+            idle_timeout,
+            key_remappers: Default::default(),
+            last_input: Instant::now(),
+            locked: false,
             request_rx,
+            requested_enter: None,
             release_bind: Rc::new(RefCell::new(release_bind)),
             state: Default::default(),
+            suspended: false,
+            swallowed_keys: Default::default(),
         };
         let task = spawn_local(capture_task.run());
         Self {
@@ -122,9 +165,27 @@ impl Capture {
             .expect("channel closed");
     }
 
-    pub(crate) fn release(&self) {
+    pub(crate) fn enter(&self, handle: CaptureHandle) {
+        self.request_tx
+            .send(CaptureRequest::Enter(handle))
+            .expect("channel closed");
+    }
+
+    pub(crate) fn toggle_lock(&self) {
+        self.request_tx
+            .send(CaptureRequest::ToggleLock)
+            .expect("channel closed");
+    }
+
+    pub(crate) fn send_screen_lock(&self, locked: bool) {
         self.request_tx
-            .send(CaptureRequest::Release)
+            .send(CaptureRequest::ScreenLock(locked))
+            .expect("channel closed");
+    }
+
+    pub(crate) fn release(&self, reason: ReleaseReason) {
+        self.request_tx
+            .send(CaptureRequest::Release(reason))
             .expect("channel closed");
     }
 
@@ -153,13 +214,34 @@ macro_rules! debounce {
 struct CaptureTask {
     active_client: Option<CaptureHandle>,
     backend: Option<input_capture::Backend>,
+    bindings: Vec<Binding>,
+    /// input is sent to all active clients
+    broadcast: bool,
     cancellation_token: CancellationToken,
     captures: Vec<(CaptureHandle, Position, CaptureType)>,
+    client_manager: ClientManager,
     conn: LanMouseConnection,
+    /// edge crossing safeguards
+    edge_guard: EdgeGuard,
     event_tx: Sender<ICaptureEvent>,
+    /// move the cursor to the center of the screen after the idle timeout
+    idle_center_cursor: bool,
+    /// time without input after which the capture is released
+    idle_timeout: Option<Duration>,
+    key_remappers: HashMap<CaptureHandle, KeyRemapper>,
+    /// time input was last sent to a client
+    last_input: Instant,
+    /// input is pinned to the active client
+    locked: bool,
     release_bind: Rc<RefCell<Vec<scancode::Linux>>>,
     request_rx: Receiver<CaptureRequest>,
+    /// client entered on request, which bypasses the edge safeguards
+    requested_enter: Option<CaptureHandle>,
     state: State,
+    /// capture disabled by a binding, while the backend keeps running
+    suspended: bool,
+    /// keys that triggered a binding and whose release is not forwarded
+    swallowed_keys: HashSet<u32>,
 }
 
 impl CaptureTask {
@@ -177,6 +259,12 @@ impl CaptureTask {
             .any(|&(_, p, t)| p == pos && t == CaptureType::Default)
     }
 
+    fn is_default_capture(&self, handle: CaptureHandle) -> bool {
+        self.captures
+            .iter()
+            .any(|&(h, _, t)| h == handle && t == CaptureType::Default)
+    }
+
     fn get_pos(&self, handle: CaptureHandle) -> Position {
         self.captures
             .iter()
@@ -204,8 +292,16 @@ impl CaptureTask {
                         CaptureRequest::Reenable => break,
                         CaptureRequest::Create(h, p, t) => self.add_capture(h, p, t),
                         CaptureRequest::Destroy(h) => self.remove_capture(h),
-                        CaptureRequest::Release => { /* nothing to do */ }
+                        CaptureRequest::Release(_) => { /* nothing to do */ }
+                        CaptureRequest::Enter(h) => self.enter_failed(h, "input capture is disabled"),
+                        CaptureRequest::ToggleLock => { /* nothing to lock */ }
+                        CaptureRequest::ScreenLock(locked) => self.send_screen_lock(locked).await,
                     },
+                    (_, event) = self.conn.recv() => {
+                        if let ProtoEvent::ScreenLock(locked) = event {
+                            self.event_tx.send(ICaptureEvent::ScreenLock(locked)).expect("channel closed");
+                        }
+                    }
                     _ = self.cancellation_token.cancelled() => return,
                 }
             }
@@ -219,6 +315,7 @@ impl CaptureTask {
             _ = self.cancellation_token.cancelled() => return Ok(()),
         };
 
+        self.suspended = false;
         let _capture_guard = DropGuard::new(
             self.event_tx.clone(),
             ICaptureEvent::CaptureEnabled,
@@ -251,17 +348,44 @@ impl CaptureTask {
         Ok(())
     }
 
+    /// bindings are evaluated while input is not captured,
+    /// if the backend supports it
+    async fn register_hotkeys(
+        &self,
+        capture: &mut InputCapture,
+    ) -> Result<Option<UnboundedReceiver<usize>>, CaptureError> {
+        if self.bindings.is_empty() {
+            return Ok(None);
+        }
+        let chords = self.bindings.iter().map(|b| b.keys.clone()).collect();
+        match capture.register_hotkeys(chords).await {
+            Ok(hotkeys) => Ok(Some(hotkeys)),
+            Err(CaptureError::HotkeysUnsupported) => {
+                log::info!("{}", CaptureError::HotkeysUnsupported);
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
     async fn do_capture_session(
         &mut self,
         capture: &mut InputCapture,
     ) -> Result<(), InputCaptureError> {
+        let mut hotkeys = self.register_hotkeys(capture).await?;
         loop {
+            let idle_deadline = self.idle_deadline();
+            let idle_timer = time::sleep_until(idle_deadline.unwrap_or_else(Instant::now).into());
             tokio::select! {
                 event = capture.next() => match event {
                     Some(event) => self.handle_capture_event(capture, event?).await?,
                     None => return Ok(()),
                 },
                 (handle, event) = self.conn.recv() => {
+                    if let ProtoEvent::ScreenLock(locked) = event {
+                        self.event_tx.send(ICaptureEvent::ScreenLock(locked)).expect("channel closed");
+                        continue;
+                    }
                     if let Some(active) = self.active_client {
                         if handle != active {
                             // we only care about events coming from the client we are currently connected to
@@ -277,31 +401,83 @@ impl CaptureTask {
                             self.state = State::Sending;
                         }
                         // client disconnected
+                        ProtoEvent::Leave(_) if self.locked => {
+                            log::info!("ignoring leave event: input is locked to client {handle}");
+                        }
                         ProtoEvent::Leave(_) => {
                             log::info!("releasing capture: left remote client device region");
-                            self.release_capture(capture).await?;
+                            self.release_capture(capture, ReleaseReason::Left).await?;
                         },
                         _ => {}
                     }
                 },
+                Some(index) = next_hotkey(&mut hotkeys) => {
+                    self.handle_hotkey(capture, index).await?;
+                }
                 e = self.request_rx.recv() => match e.expect("channel closed") {
+                    CaptureRequest::Reenable if self.suspended => self.resume(capture).await?,
                     CaptureRequest::Reenable => { /* already active */ },
-                    CaptureRequest::Release => self.release_capture(capture).await?,
+                    CaptureRequest::Release(_) if self.locked => {
+                        log::info!("ignoring release request: input is locked");
+                    }
+                    CaptureRequest::Release(reason) => self.release_capture(capture, reason).await?,
+                    CaptureRequest::ToggleLock => self.toggle_lock(),
+                    CaptureRequest::ScreenLock(locked) => self.send_screen_lock(locked).await,
+                    CaptureRequest::Enter(h) => self.enter(capture, h).await,
                     CaptureRequest::Create(h, p, t) => {
                         self.add_capture(h, p, t);
-                        capture.create(h, p).await?;
+                        if !self.suspended {
+                            capture.create(h, p).await?;
+                        }
                     }
                     CaptureRequest::Destroy(h) => {
+                        // neither the capture nor a lock outlive the client
+                        if self.active_client == Some(h) {
+                            self.release_capture(capture, ReleaseReason::Deactivated).await?;
+                        }
                         self.remove_capture(h);
-                        capture.destroy(h).await?;
+                        if !self.suspended {
+                            capture.destroy(h).await?;
+                        }
                     }
                 },
+                _ = idle_timer, if idle_deadline.is_some() => {
+                    self.release_idle(capture).await?;
+                }
                 _ = self.cancellation_token.cancelled() => break,
             }
         }
         Ok(())
     }
 
+    /// time at which the capture is released due to inactivity
+    fn idle_deadline(&self) -> Option<Instant> {
+        match (self.idle_timeout, self.active_client) {
+            // input stays on the client while locked
+            (Some(timeout), Some(_)) if !self.locked => Some(self.last_input + timeout),
+            _ => None,
+        }
+    }
+
+    async fn release_idle(&mut self, capture: &mut InputCapture) -> Result<(), CaptureError> {
+        let Some(client) = self.active_client else {
+            return Ok(());
+        };
+        log::info!("releasing capture: no input for client {client}");
+        let _ = self.conn.send(ProtoEvent::Leave(0), client).await;
+        self.release_capture(capture, ReleaseReason::IdleTimeout)
+            .await?;
+        if self.idle_center_cursor {
+            match capture.center_cursor().await {
+                Err(CaptureError::WarpUnsupported) => {
+                    log::warn!("{}", CaptureError::WarpUnsupported)
+                }
+                r => r?,
+            }
+        }
+        Ok(())
+    }
+
     async fn handle_capture_event(
         &mut self,
         capture: &mut InputCapture,
@@ -310,12 +486,19 @@ impl CaptureTask {
         let (handle, event) = event;
         log::trace!("({handle}): {event:?}");
 
+        // no barriers exist while suspended
+        if self.suspended {
+            return Ok(());
+        }
+
         if capture.keys_pressed(&self.release_bind.borrow()) {
             log::info!("releasing capture: release-bind pressed");
-            return self.release_capture(capture).await;
+            return self
+                .release_capture(capture, ReleaseReason::ReleaseBind)
+                .await;
         }
 
-        if event == CaptureEvent::Begin {
+        if matches!(event, CaptureEvent::Begin(_)) {
             self.event_tx
                 .send(ICaptureEvent::CaptureBegin(handle))
                 .expect("channel closed");
@@ -333,36 +516,351 @@ impl CaptureTask {
             return Ok(());
         }
 
-        // activated a new client
-        if event == CaptureEvent::Begin && Some(handle) != self.active_client {
+        // edge crossing safeguards: input is only forwarded
+        // once the edge policy of the client is satisfied
+        let event = match self.active_client {
+            Some(_) => event,
+            None => match self.check_edge_policy(capture, handle, event) {
+                Verdict::Enter => CaptureEvent::Begin(None),
+                Verdict::Wait => return Ok(()),
+                Verdict::Reject => {
+                    log::info!("releasing capture: edge crossing rejected");
+                    return capture.release().await;
+                }
+            },
+        };
+
+        // activated a new client (edge crossings do not change the client while locked)
+        if matches!(event, CaptureEvent::Begin(_))
+            && Some(handle) != self.active_client
+            && !self.locked
+        {
             self.state = State::WaitingForAck;
             self.active_client.replace(handle);
+            self.key_remappers.clear();
             self.event_tx
                 .send(ICaptureEvent::ClientEntered(handle))
                 .expect("channel closed");
         }
 
-        let opposite_pos = to_proto_pos(self.get_pos(handle).opposite());
+        // key bindings
+        if let CaptureEvent::Input(Event::Keyboard(KeyboardEvent::Key { key, state, .. })) = event {
+            if state == 1 {
+                if let Some(binding) = self.triggered_binding(capture, key) {
+                    self.release_chord(&binding.keys, key).await;
+                    return self.handle_action(capture, binding.action).await;
+                }
+            } else if self.swallowed_keys.remove(&key) {
+                return Ok(());
+            }
+        }
+
+        // the active client may differ from the capture handle
+        // after switching clients through a binding
+        let target = self.active_client.unwrap_or(handle);
+        let opposite_pos = to_proto_pos(self.get_pos(target).opposite());
 
-        let event = match event {
-            CaptureEvent::Begin => ProtoEvent::Enter(opposite_pos),
+        let proto_event = match event {
+            CaptureEvent::Begin(_) => ProtoEvent::Enter(opposite_pos),
             CaptureEvent::Input(e) => match self.state {
                 // connection not acknowledged, repeat `Enter` event
                 State::WaitingForAck => ProtoEvent::Enter(opposite_pos),
-                State::Sending => ProtoEvent::Input(e),
+                State::Sending => ProtoEvent::Input(self.apply_client_config(target, e)),
             },
         };
 
-        if let Err(e) = self.conn.send(event, handle).await {
+        self.last_input = Instant::now();
+        if let Err(e) = self.conn.send(proto_event, target).await {
             const DUR: Duration = Duration::from_millis(500);
             debounce!(PREV_LOG, DUR, log::warn!("releasing capture: {e}"));
+            // the client stays active, so crossing the barrier again
+            // retries it, but input is no longer locked or broadcasted
+            self.locked = false;
+            self.set_broadcast(false).await;
             capture.release().await?;
         }
+
+        if let (true, CaptureEvent::Input(e)) = (self.broadcast, event) {
+            for other in self.broadcast_targets() {
+                let e = self.apply_client_config(other, e);
+                let _ = self.conn.send(ProtoEvent::Input(e), other).await;
+            }
+        }
         Ok(())
     }
 
-    async fn release_capture(&mut self, capture: &mut InputCapture) -> Result<(), CaptureError> {
-        self.active_client.take();
+    fn check_edge_policy(
+        &mut self,
+        capture: &InputCapture,
+        handle: CaptureHandle,
+        event: CaptureEvent,
+    ) -> Verdict {
+        match event {
+            CaptureEvent::Begin(_) if self.requested_enter == Some(handle) => {
+                self.requested_enter = None;
+                Verdict::Enter
+            }
+            CaptureEvent::Begin(edge) => {
+                let policy = self
+                    .client_manager
+                    .get_edge_policy(handle)
+                    .unwrap_or_default();
+                let pos = self.get_pos(handle);
+                // left and right modifiers are not distinguished
+                let modifier_held = policy.modifier.is_some_and(|m| {
+                    capture
+                        .pressed_keys()
+                        .any(|k| keymap::normalize(k as u32) == keymap::normalize(m))
+                });
+                self.edge_guard
+                    .begin(handle, pos, policy, edge, modifier_held)
+            }
+            CaptureEvent::Input(e) if self.edge_guard.pending() == Some(handle) => {
+                self.edge_guard.update(e)
+            }
+            // events of other clients at the same position
+            CaptureEvent::Input(_) => Verdict::Wait,
+        }
+    }
+
+    /// the binding triggered by pressing `key`, if any.
+    /// If multiple bindings match, the one with the most keys is used.
+    fn triggered_binding(&self, capture: &InputCapture, key: u32) -> Option<Binding> {
+        self.bindings
+            .iter()
+            .filter(|b| b.keys.iter().any(|&k| k as u32 == key))
+            .filter(|b| capture.keys_pressed(&b.keys))
+            .max_by_key(|b| b.keys.len())
+            .cloned()
+    }
+
+    /// keys of a chord pressed before `key` (e.g. modifiers) were already
+    /// sent to the current clients => release them there, so they do not
+    /// stay pressed, and swallow the releases of all keys of the chord,
+    /// which would otherwise reach the client switched to
+    async fn release_chord(&mut self, keys: &[scancode::Linux], key: u32) {
+        let targets = self
+            .active_client
+            .into_iter()
+            .chain(
+                self.broadcast
+                    .then(|| self.broadcast_targets())
+                    .into_iter()
+                    .flatten(),
+            )
+            .collect::<Vec<_>>();
+        for &k in keys.iter().filter(|&&k| k as u32 != key) {
+            let release = Event::Keyboard(KeyboardEvent::Key {
+                time: 0,
+                key: k as u32,
+                state: 0,
+            });
+            for &target in &targets {
+                let event = self.apply_client_config(target, release);
+                let _ = self.conn.send(ProtoEvent::Input(event), target).await;
+            }
+        }
+        self.swallowed_keys.extend(keys.iter().map(|&k| k as u32));
+    }
+
+    /// a binding was triggered while input is not captured
+    async fn handle_hotkey(
+        &mut self,
+        capture: &mut InputCapture,
+        index: usize,
+    ) -> Result<(), CaptureError> {
+        match self.bindings.get(index) {
+            Some(binding) => self.handle_action(capture, binding.action).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn handle_action(
+        &mut self,
+        capture: &mut InputCapture,
+        action: Action,
+    ) -> Result<(), CaptureError> {
+        log::info!("binding triggered: {action:?}");
+        match action {
+            Action::Release => {
+                self.release_capture(capture, ReleaseReason::Requested)
+                    .await?
+            }
+            // not captured => grab input for the client
+            Action::SwitchTo { client } if self.active_client.is_none() => {
+                self.enter(capture, client).await
+            }
+            Action::SwitchTo { client } => self.switch_to(client).await,
+            Action::ToggleCapture if self.suspended => self.resume(capture).await?,
+            Action::ToggleCapture => self.suspend(capture).await?,
+            Action::ToggleEmulation => self
+                .event_tx
+                .send(ICaptureEvent::ToggleEmulation)
+                .expect("channel closed"),
+            Action::ToggleLock => self.toggle_lock(),
+            Action::ToggleBroadcast if self.active_client.is_none() => {
+                log::warn!("can not broadcast input: no client entered");
+            }
+            Action::ToggleBroadcast => self.set_broadcast(!self.broadcast).await,
+        }
+        Ok(())
+    }
+
+    /// disable input capture without terminating the backend,
+    /// so bindings can still enable it again
+    async fn suspend(&mut self, capture: &mut InputCapture) -> Result<(), CaptureError> {
+        log::info!("disabling input capture");
+        self.release_capture(capture, ReleaseReason::Disabled)
+            .await?;
+        for (handle, ..) in self.captures.clone() {
+            capture.destroy(handle).await?;
+        }
+        self.suspended = true;
+        self.event_tx
+            .send(ICaptureEvent::CaptureDisabled)
+            .expect("channel closed");
+        Ok(())
+    }
+
+    async fn resume(&mut self, capture: &mut InputCapture) -> Result<(), CaptureError> {
+        log::info!("enabling input capture");
+        self.create_captures(capture).await?;
+        self.suspended = false;
+        self.event_tx
+            .send(ICaptureEvent::CaptureEnabled)
+            .expect("channel closed");
+        Ok(())
+    }
+
+    /// grab input for the given client, as if its barrier was crossed
+    async fn enter(&mut self, capture: &mut InputCapture, client: CaptureHandle) {
+        if self.suspended {
+            return self.enter_failed(client, "input capture is disabled");
+        }
+        if !self.is_default_capture(client) {
+            return self.enter_failed(client, "client is not active");
+        }
+        // input is already captured => only change the target
+        if self.active_client.is_some() {
+            return self.switch_to(client).await;
+        }
+        log::info!("entering client {client}");
+        self.requested_enter = Some(client);
+        // a failed request does not end the capture session
+        if let Err(e) = capture.capture(client).await {
+            self.requested_enter = None;
+            self.enter_failed(client, &e.to_string());
+        }
+    }
+
+    /// report a failed enter request to the frontend
+    fn enter_failed(&self, client: CaptureHandle, reason: &str) {
+        log::warn!("can not enter client {client}: {reason}");
+        self.event_tx
+            .send(ICaptureEvent::EnterFailed(client, reason.to_owned()))
+            .expect("channel closed");
+    }
+
+    /// send input to the given client instead of the current one
+    async fn switch_to(&mut self, client: CaptureHandle) {
+        if !self.is_default_capture(client) {
+            log::warn!("can not switch to client {client}: client is not active");
+            return;
+        }
+        if self.active_client == Some(client) {
+            return;
+        }
+        if let Some(previous) = self.active_client {
+            let _ = self.conn.send(ProtoEvent::Leave(0), previous).await;
+        }
+        log::info!("switching to client {client}");
+        self.state = State::WaitingForAck;
+        self.active_client.replace(client);
+        self.key_remappers.clear();
+        self.event_tx
+            .send(ICaptureEvent::ClientEntered(client))
+            .expect("channel closed");
+        let pos = to_proto_pos(self.get_pos(client).opposite());
+        let _ = self.conn.send(ProtoEvent::Enter(pos), client).await;
+    }
+
+    async fn send_screen_lock(&self, locked: bool) {
+        for handle in self.client_manager.active_clients() {
+            if let Err(e) = self.conn.send(ProtoEvent::ScreenLock(locked), handle).await {
+                log::warn!("could not send screen lock state to client {handle}: {e}");
+            }
+        }
+    }
+
+    /// pin input to the active client: while locked, neither a `Leave` event
+    /// nor a release request ends the capture, only unlocking or the release bind
+    fn toggle_lock(&mut self) {
+        let Some(client) = self.active_client else {
+            log::warn!("can not lock input: no client entered");
+            return;
+        };
+        self.locked = !self.locked;
+        match self.locked {
+            true => log::info!("input locked to client {client}"),
+            false => log::info!("input unlocked"),
+        }
+    }
+
+    /// active clients receiving broadcasted input (besides the active one)
+    fn broadcast_targets(&self) -> Vec<CaptureHandle> {
+        self.captures
+            .iter()
+            .filter(|&&(h, _, t)| t == CaptureType::Default && Some(h) != self.active_client)
+            .map(|&(h, ..)| h)
+            .collect()
+    }
+
+    async fn set_broadcast(&mut self, broadcast: bool) {
+        if self.broadcast == broadcast {
+            return;
+        }
+        self.broadcast = broadcast;
+        log::info!("broadcast {}", if broadcast { "on" } else { "off" });
+        for client in self.broadcast_targets() {
+            let event = match broadcast {
+                true => ProtoEvent::Enter(to_proto_pos(self.get_pos(client).opposite())),
+                false => ProtoEvent::Leave(0),
+            };
+            let _ = self.conn.send(event, client).await;
+        }
+    }
+
+    /// apply pointer settings and key remapping of the given client
+    fn apply_client_config(&mut self, handle: CaptureHandle, event: Event) -> Event {
+        let key_remapper = self.key_remappers.entry(handle).or_default();
+        match event {
+            Event::Pointer(_) => match self.client_manager.get_pointer_config(handle) {
+                Some(config) => pointer::apply(&config, event),
+                None => event,
+            },
+            Event::Keyboard(_) => match self.client_manager.get_key_remap(handle) {
+                Some(remap) => key_remapper.apply(&remap, event),
+                None => event,
+            },
+        }
+    }
+
+    async fn release_capture(
+        &mut self,
+        capture: &mut InputCapture,
+        reason: ReleaseReason,
+    ) -> Result<(), CaptureError> {
+        self.set_broadcast(false).await;
+        if let Some(client) = self.active_client.take() {
+            self.event_tx
+                .send(ICaptureEvent::Released(client, reason))
+                .expect("channel closed");
+        }
+        self.locked = false;
+        self.edge_guard.clear();
+        self.requested_enter = None;
+        self.key_remappers.clear();
+        self.swallowed_keys.clear();
         capture.release().await
     }
 }
@@ -378,6 +876,14 @@ enum State {
     Sending,
 }
 
+/// the next binding triggered while input is not captured
+async fn next_hotkey(hotkeys: &mut Option<UnboundedReceiver<usize>>) -> Option<usize> {
+    match hotkeys {
+        Some(hotkeys) => hotkeys.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
 fn to_capture_pos(pos: lan_mouse_ipc::Position) -> input_capture::Position {
     match pos {
         lan_mouse_ipc::Position::Left => input_capture::Position::Left,
@@ -0,0 +1,95 @@
+use std::{fs, io, path::PathBuf};
+
+use clap::{Args, Subcommand};
+use thiserror::Error;
+use webrtc_dtls::crypto::Certificate;
+
+use crate::{config::Config, crypto};
+
+#[derive(Debug, Error)]
+pub enum CertError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Crypto(#[from] crypto::Error),
+}
+
+#[derive(Args, Clone, Debug, Eq, PartialEq)]
+pub struct CertArgs {
+    #[command(subcommand)]
+    command: CertCommand,
+}
+
+#[derive(Subcommand, Clone, Debug, Eq, PartialEq)]
+enum CertCommand {
+    /// print the fingerprint and expiry of the certificate of this device
+    Show,
+    /// replace the key and certificate of this device with a new one
+    Rotate {
+        /// do not sign a key transition statement with the old key.
+        /// Devices that trusted the old certificate then need to authorize the new one.
+        #[arg(long)]
+        no_transition: bool,
+    },
+    /// create a certificate signing request for the key of this device
+    Request {
+        /// DNS name to request the certificate for (may be repeated)
+        #[arg(long = "name", required = true)]
+        names: Vec<String>,
+        /// write the request to a file instead of stdout
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+    /// replace the certificate of this device with one issued for its key
+    Install {
+        /// PEM file with the issued certificate, followed by intermediate CA certificates
+        chain: PathBuf,
+    },
+}
+
+pub fn run(config: Config, args: CertArgs) -> Result<(), CertError> {
+    match args.command {
+        CertCommand::Show => {
+            let cert = crypto::load_or_generate_key_and_cert(config.cert_path())?;
+            println!("{}", crypto::certificate_fingerprint(&cert));
+            let days = crypto::days_until(crypto::certificate_expiry(&cert)?);
+            match days {
+                d if d < 0 => println!("expired {} days ago", -d),
+                d => println!("expires in {d} days"),
+            }
+            if let Some(previous) = crypto::key_transition(&der_chain(&cert)) {
+                println!("key transition from {previous}");
+            }
+        }
+        CertCommand::Rotate { no_transition } => {
+            let old = crypto::load_certificate(config.cert_path())?;
+            let cert = crypto::rotate_key_and_cert(config.cert_path(), !no_transition)?;
+            println!("old certificate: {}", crypto::certificate_fingerprint(&old));
+            println!(
+                "new certificate: {}",
+                crypto::certificate_fingerprint(&cert)
+            );
+            println!("restart lan-mouse to use the new certificate");
+        }
+        CertCommand::Request { names, output } => {
+            let cert = crypto::load_or_generate_key_and_cert(config.cert_path())?;
+            let csr = crypto::generate_csr(&cert, names)?;
+            match output {
+                Some(path) => fs::write(path, csr)?,
+                None => print!("{csr}"),
+            }
+        }
+        CertCommand::Install { chain } => {
+            let chain = fs::read(chain)?;
+            let cert = crypto::install_certificate(config.cert_path(), &chain)?;
+            let fingerprint = crypto::certificate_fingerprint(&cert);
+            println!("installed certificate {fingerprint}");
+            println!("the fingerprint of this device changed, restart lan-mouse to use it");
+        }
+    }
+    Ok(())
+}
+
+fn der_chain(cert: &Certificate) -> Vec<Vec<u8>> {
+    cert.certificate.iter().map(|c| c.to_vec()).collect()
+}
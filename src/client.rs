@@ -1,13 +1,19 @@
 use std::{
     cell::RefCell,
-    collections::HashSet,
+    collections::{HashMap, HashSet},
     net::{IpAddr, SocketAddr},
     rc::Rc,
 };
 
 use slab::Slab;
 
-use lan_mouse_ipc::{ClientConfig, ClientHandle, ClientState, Position};
+use lan_mouse_ipc::{
+    ClientConfig, ClientHandle, ClientState, ConnectionState, EdgePolicy, MacAddress,
+    PointerConfig, Position, Transport,
+};
+
+/// number of addresses a device was seen at that are remembered
+const MAX_SEEN_IPS: usize = 4;
 
 #[derive(Clone, Default)]
 pub struct ClientManager {
@@ -60,15 +66,16 @@ impl ClientManager {
         }
     }
 
-    /// find a client by its address
-    pub fn get_client(&self, addr: SocketAddr) -> Option<ClientHandle> {
+    /// find a client by the certificate fingerprint of the device,
+    /// which identifies it regardless of its current address
+    pub fn get_client(&self, fingerprint: &str) -> Option<ClientHandle> {
         // since there shouldn't be more than a handful of clients at any given
         // time this is likely faster than using a HashMap
         self.clients
             .borrow()
             .iter()
-            .find_map(|(k, (_, s))| {
-                if s.active && s.ips.contains(&addr.ip()) {
+            .find_map(|(k, (c, _))| {
+                if c.fingerprint.as_deref() == Some(fingerprint) {
                     Some(k)
                 } else {
                     None
@@ -150,10 +157,32 @@ impl ClientManager {
                 .iter()
                 .cloned()
                 .chain(s.dns_ips.iter().cloned())
+                .chain(s.seen_ips.iter().cloned())
                 .collect::<HashSet<_>>();
         }
     }
 
+    /// remember that the device was seen at `ip`,
+    /// the address is tried as well when connecting to it
+    pub(crate) fn add_seen_ip(&self, handle: ClientHandle, ip: IpAddr) {
+        if let Some((_, s)) = self.clients.borrow_mut().get_mut(handle as usize) {
+            s.seen_ips.retain(|i| *i != ip);
+            s.seen_ips.insert(0, ip);
+            s.seen_ips.truncate(MAX_SEEN_IPS);
+        }
+        self.update_ips(handle);
+    }
+
+    /// set the address of the incoming connection of the device
+    pub(crate) fn set_incoming(&self, handle: ClientHandle, addr: Option<SocketAddr>) {
+        if let Some((_, s)) = self.clients.borrow_mut().get_mut(handle as usize) {
+            s.incoming = addr;
+        }
+        if let Some(addr) = addr {
+            self.add_seen_ip(handle, addr.ip());
+        }
+    }
+
     /// update the hostname of the given client
     /// this automatically clears the active ip address and ips from dns
     pub fn set_hostname(&self, handle: ClientHandle, hostname: Option<String>) -> bool {
@@ -206,6 +235,77 @@ impl ClientManager {
         }
     }
 
+    /// update the pointer and scroll settings of the client
+    pub(crate) fn set_pointer_config(&self, handle: ClientHandle, pointer: PointerConfig) {
+        if let Some((c, _s)) = self.clients.borrow_mut().get_mut(handle as usize) {
+            c.pointer = pointer;
+        }
+    }
+
+    /// get the pointer and scroll settings of the client
+    pub(crate) fn get_pointer_config(&self, handle: ClientHandle) -> Option<PointerConfig> {
+        self.clients
+            .borrow()
+            .get(handle as usize)
+            .map(|(c, _)| c.pointer)
+    }
+
+    /// get the key remap table of the client
+    pub(crate) fn get_key_remap(&self, handle: ClientHandle) -> Option<HashMap<u32, u32>> {
+        self.clients
+            .borrow()
+            .get(handle as usize)
+            .map(|(c, _)| c.key_remap.clone())
+    }
+
+    /// get the edge crossing safeguards of the client
+    pub(crate) fn get_edge_policy(&self, handle: ClientHandle) -> Option<EdgePolicy> {
+        self.clients
+            .borrow()
+            .get(handle as usize)
+            .map(|(c, _)| c.edge)
+    }
+
+    /// get the hardware address of the client (for Wake-on-LAN)
+    pub(crate) fn get_mac_address(&self, handle: ClientHandle) -> Option<MacAddress> {
+        self.clients
+            .borrow()
+            .get(handle as usize)
+            .and_then(|(c, _)| c.mac_address)
+    }
+
+    /// get the transport used to connect to the client
+    pub(crate) fn get_transport(&self, handle: ClientHandle) -> Transport {
+        self.clients
+            .borrow()
+            .get(handle as usize)
+            .map(|(c, _)| c.transport)
+            .unwrap_or_default()
+    }
+
+    /// get the relay used to reach the client
+    pub(crate) fn get_relay(&self, handle: ClientHandle) -> Option<String> {
+        self.clients
+            .borrow()
+            .get(handle as usize)
+            .and_then(|(c, _)| c.relay.clone())
+    }
+
+    /// get the pinned certificate fingerprint of the client
+    pub(crate) fn get_fingerprint(&self, handle: ClientHandle) -> Option<String> {
+        self.clients
+            .borrow()
+            .get(handle as usize)
+            .and_then(|(c, _)| c.fingerprint.clone())
+    }
+
+    /// pin the certificate fingerprint of the client
+    pub(crate) fn set_fingerprint(&self, handle: ClientHandle, fingerprint: Option<String>) {
+        if let Some((c, _)) = self.clients.borrow_mut().get_mut(handle as usize) {
+            c.fingerprint = fingerprint;
+        }
+    }
+
     /// set resolving status of the client
     pub(crate) fn set_resolving(&self, handle: ClientHandle, status: bool) {
         if let Some((_, s)) = self.clients.borrow_mut().get_mut(handle as usize) {
@@ -237,6 +337,12 @@ impl ClientManager {
         }
     }
 
+    pub(crate) fn set_connection_state(&self, handle: ClientHandle, connection: ConnectionState) {
+        if let Some((_, s)) = self.clients.borrow_mut().get_mut(handle as usize) {
+            s.connection = connection;
+        }
+    }
+
     pub(crate) fn set_alive(&self, handle: ClientHandle, alive: bool) {
         if let Some((_, s)) = self.clients.borrow_mut().get_mut(handle as usize) {
             s.alive = alive;
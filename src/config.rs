@@ -1,5 +1,7 @@
 use crate::capture_test::TestCaptureArgs;
+use crate::cert::CertArgs;
 use crate::emulation_test::TestEmulationArgs;
+use crate::relay::RelayArgs;
 use clap::{Parser, Subcommand, ValueEnum};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -8,12 +10,16 @@ use std::fmt::Display;
 use std::fs;
 use std::net::IpAddr;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 use std::{collections::HashSet, io};
 use thiserror::Error;
 use toml;
 
 use lan_mouse_cli::CliArgs;
-use lan_mouse_ipc::{DEFAULT_PORT, Position};
+use lan_mouse_ipc::{
+    Acceleration, ClientConfig, ClientHandle, DEFAULT_PORT, EdgePolicy, MacAddress, PointerConfig,
+    Position, Transport,
+};
 
 use input_event::scancode::{
     self,
@@ -26,6 +32,16 @@ shadow!(build);
 
 const CONFIG_FILE_NAME: &str = "config.toml";
 const CERT_FILE_NAME: &str = "lan-mouse.pem";
+const AUDIT_FILE_NAME: &str = "audit.jsonl";
+/// default size (KiB) after which the audit log is rotated
+const DEFAULT_AUDIT_MAX_SIZE: u64 = 1024;
+/// default number of rotated audit logs kept
+const DEFAULT_AUDIT_KEEP: usize = 5;
+const DEFAULT_HANDSHAKE_RATE: u32 = 30;
+const DEFAULT_BAN_AFTER: u32 = 10;
+const DEFAULT_BAN_DURATION: u64 = 600;
+const DEFAULT_MAX_EVENT_RATE: u32 = 5000;
+const DEFAULT_MAX_CONNECTIONS: usize = 16;
 
 fn default_path() -> Result<PathBuf, VarError> {
     #[cfg(unix)]
@@ -52,10 +68,19 @@ struct ConfigToml {
     release_bind: Option<Vec<scancode::Linux>>,
     cert_path: Option<PathBuf>,
     clients: Option<Vec<TomlClient>>,
-    authorized_fingerprints: Option<HashMap<String, String>>,
+    authorized_fingerprints: Option<HashMap<String, TomlAuthorizedKey>>,
+    certificate_authority: Option<TomlCertificateAuthority>,
+    audit: Option<TomlAudit>,
+    limits: Option<TomlLimits>,
+    bindings: Option<Vec<Binding>>,
+    idle_timeout: Option<u64>,
+    idle_center_cursor: Option<bool>,
+    sync_screen_lock: Option<bool>,
+    sync_screen_unlock: Option<bool>,
+    discovery: Option<bool>,
 }
 
-#[derive(Clone, Serialize, Deserialize, Debug, Eq, PartialEq)]
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
 struct TomlClient {
     hostname: Option<String>,
     host_name: Option<String>,
@@ -64,6 +89,169 @@ struct TomlClient {
     position: Option<Position>,
     activate_on_startup: Option<bool>,
     enter_hook: Option<String>,
+    sensitivity: Option<f64>,
+    acceleration: Option<Acceleration>,
+    scroll_speed: Option<f64>,
+    natural_scrolling: Option<bool>,
+    key_remap: Option<HashMap<scancode::Linux, scancode::Linux>>,
+    edge: Option<TomlEdgePolicy>,
+    mac_address: Option<MacAddress>,
+    fingerprint: Option<String>,
+    transport: Option<Transport>,
+    relay: Option<String>,
+}
+
+/// an authorized fingerprint: either only a description
+/// or a description and the input permissions of the device
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(untagged)]
+enum TomlAuthorizedKey {
+    Description(String),
+    Permissions {
+        description: String,
+        pointer: Option<bool>,
+        keyboard: Option<bool>,
+        deny_keys: Option<Vec<Vec<scancode::Linux>>>,
+    },
+}
+
+impl TomlAuthorizedKey {
+    fn description(&self) -> &str {
+        match self {
+            TomlAuthorizedKey::Description(description) => description,
+            TomlAuthorizedKey::Permissions { description, .. } => description,
+        }
+    }
+}
+
+impl From<TomlAuthorizedKey> for InputPermissions {
+    fn from(key: TomlAuthorizedKey) -> Self {
+        match key {
+            TomlAuthorizedKey::Description(_) => Default::default(),
+            TomlAuthorizedKey::Permissions {
+                pointer,
+                keyboard,
+                deny_keys,
+                ..
+            } => Self {
+                pointer: pointer.unwrap_or(true),
+                keyboard: keyboard.unwrap_or(true),
+                deny_keys: deny_keys.unwrap_or_default(),
+            },
+        }
+    }
+}
+
+/// devices with a certificate issued by this CA are authorized
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+struct TomlCertificateAuthority {
+    bundle: PathBuf,
+    crl: Option<PathBuf>,
+    allowed_names: Option<Vec<String>>,
+    required_subject: Option<Vec<String>>,
+    pointer: Option<bool>,
+    keyboard: Option<bool>,
+    deny_keys: Option<Vec<Vec<scancode::Linux>>>,
+}
+
+impl From<TomlCertificateAuthority> for CertificateAuthorityConfig {
+    fn from(toml: TomlCertificateAuthority) -> Self {
+        Self {
+            bundle: toml.bundle,
+            crl: toml.crl,
+            allowed_names: toml.allowed_names.unwrap_or_default(),
+            required_subject: toml.required_subject.unwrap_or_default(),
+            // devices are not listed individually => no input unless allowed
+            permissions: InputPermissions {
+                pointer: toml.pointer.unwrap_or(false),
+                keyboard: toml.keyboard.unwrap_or(false),
+                deny_keys: toml.deny_keys.unwrap_or_default(),
+            },
+        }
+    }
+}
+
+/// audit log of connections and authorization changes
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+struct TomlAudit {
+    enabled: Option<bool>,
+    path: Option<PathBuf>,
+    max_size: Option<u64>,
+    keep: Option<usize>,
+}
+
+/// limits for inbound connections
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+struct TomlLimits {
+    handshake_rate: Option<u32>,
+    ban_after: Option<u32>,
+    ban_duration: Option<u64>,
+    max_event_rate: Option<u32>,
+    max_connections: Option<usize>,
+}
+
+impl From<TomlLimits> for LimitsConfig {
+    fn from(toml: TomlLimits) -> Self {
+        Self {
+            handshake_rate: toml.handshake_rate.unwrap_or(DEFAULT_HANDSHAKE_RATE),
+            ban_after: toml.ban_after.unwrap_or(DEFAULT_BAN_AFTER),
+            ban_duration: Duration::from_secs(toml.ban_duration.unwrap_or(DEFAULT_BAN_DURATION)),
+            max_event_rate: toml.max_event_rate.unwrap_or(DEFAULT_MAX_EVENT_RATE),
+            max_connections: toml.max_connections.unwrap_or(DEFAULT_MAX_CONNECTIONS),
+        }
+    }
+}
+
+/// edge crossing safeguards of a client (see [`EdgePolicy`])
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+struct TomlEdgePolicy {
+    dwell_time: Option<u64>,
+    push_distance: Option<f64>,
+    push_velocity: Option<f64>,
+    modifier: Option<scancode::Linux>,
+    double_tap: Option<bool>,
+    corner_dead_zone: Option<f64>,
+}
+
+impl From<TomlEdgePolicy> for EdgePolicy {
+    fn from(toml: TomlEdgePolicy) -> Self {
+        Self {
+            dwell_time: toml.dwell_time.unwrap_or_default(),
+            push_distance: toml.push_distance.unwrap_or_default(),
+            push_velocity: toml.push_velocity.unwrap_or_default(),
+            modifier: toml.modifier.map(|k| k as u32),
+            double_tap: toml.double_tap.unwrap_or_default(),
+            corner_dead_zone: toml.corner_dead_zone.unwrap_or_default(),
+        }
+    }
+}
+
+/// a key chord bound to an [`Action`]
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct Binding {
+    /// keys that need to be pressed to trigger the action
+    pub keys: Vec<scancode::Linux>,
+    /// the action to trigger
+    #[serde(flatten)]
+    pub action: Action,
+}
+
+/// actions that can be bound to a key chord
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(tag = "action", rename_all = "kebab-case")]
+pub enum Action {
+    /// release the capture and return control to this device
+    Release,
+    /// send input to the given client directly
+    SwitchTo { client: ClientHandle },
+    /// disable / enable input capture
+    ToggleCapture,
+    /// disable / enable input emulation
+    ToggleEmulation,
+    /// keep input pinned to the current client
+    ToggleLock,
+    /// send input to all active clients at once
+    ToggleBroadcast,
 }
 
 impl ConfigToml {
@@ -109,6 +297,10 @@ pub enum Command {
     TestCapture(TestCaptureArgs),
     /// Lan Mouse commandline interface
     Cli(CliArgs),
+    /// manage the certificate of this device
+    Cert(CertArgs),
+    /// forward connections between devices that can not reach each other directly
+    Relay(RelayArgs),
     /// run in daemon mode
     Daemon,
 }
@@ -246,6 +438,97 @@ pub struct Config {
     config_toml: Option<ConfigToml>,
 }
 
+/// input an authorized device may emulate on this device
+#[derive(Clone, Debug, PartialEq)]
+pub struct InputPermissions {
+    /// pointer events are allowed
+    pub pointer: bool,
+    /// keyboard events are allowed
+    pub keyboard: bool,
+    /// key combinations that are never emulated
+    pub deny_keys: Vec<Vec<scancode::Linux>>,
+}
+
+impl InputPermissions {
+    /// no input of the device is emulated
+    pub fn none() -> Self {
+        Self {
+            pointer: false,
+            keyboard: false,
+            deny_keys: Default::default(),
+        }
+    }
+
+    /// all input of the device is emulated
+    pub fn unrestricted(&self) -> bool {
+        self.pointer && self.keyboard && self.deny_keys.is_empty()
+    }
+}
+
+impl Default for InputPermissions {
+    fn default() -> Self {
+        Self {
+            pointer: true,
+            keyboard: true,
+            deny_keys: Default::default(),
+        }
+    }
+}
+
+/// organizational certificate authority trusted in addition to the authorized fingerprints
+#[derive(Clone, Debug, PartialEq)]
+pub struct CertificateAuthorityConfig {
+    /// PEM file with the CA certificates
+    pub bundle: PathBuf,
+    /// PEM or DER file with certificate revocation lists
+    pub crl: Option<PathBuf>,
+    /// DNS names (or `*.domain` patterns) of which a certificate must contain at least one
+    pub allowed_names: Vec<String>,
+    /// `ATTRIBUTE=value` pairs the subject of a certificate must contain, e.g. `O=Example`
+    pub required_subject: Vec<String>,
+    /// input devices trusted through the CA may emulate
+    pub permissions: InputPermissions,
+}
+
+/// rotating audit log of connections and authorization changes
+#[derive(Clone, Debug, PartialEq)]
+pub struct AuditConfig {
+    /// path of the current log file
+    pub path: PathBuf,
+    /// size in bytes after which the log is rotated
+    pub max_size: u64,
+    /// number of rotated log files kept
+    pub keep: usize,
+}
+
+/// limits protecting this device against floods of inbound connections and events.
+/// A limit of 0 disables the respective limit.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LimitsConfig {
+    /// handshakes per minute and ip address
+    pub handshake_rate: u32,
+    /// rejected attempts within a minute after which an ip address is banned
+    pub ban_after: u32,
+    /// time an ip address stays banned
+    pub ban_duration: Duration,
+    /// events per second and connection
+    pub max_event_rate: u32,
+    /// concurrent incoming connections
+    pub max_connections: usize,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        Self {
+            handshake_rate: DEFAULT_HANDSHAKE_RATE,
+            ban_after: DEFAULT_BAN_AFTER,
+            ban_duration: Duration::from_secs(DEFAULT_BAN_DURATION),
+            max_event_rate: DEFAULT_MAX_EVENT_RATE,
+            max_connections: DEFAULT_MAX_CONNECTIONS,
+        }
+    }
+}
+
 pub struct ConfigClient {
     pub ips: HashSet<IpAddr>,
     pub hostname: Option<String>,
@@ -253,6 +536,16 @@ pub struct ConfigClient {
     pub pos: Position,
     pub active: bool,
     pub enter_hook: Option<String>,
+    pub pointer: PointerConfig,
+    /// key remap table (linux scancodes)
+    pub key_remap: HashMap<u32, u32>,
+    pub edge: EdgePolicy,
+    pub mac_address: Option<MacAddress>,
+    /// pinned certificate fingerprint
+    pub fingerprint: Option<String>,
+    pub transport: Transport,
+    /// relay used to reach the client
+    pub relay: Option<String>,
 }
 
 impl From<TomlClient> for ConfigClient {
@@ -263,6 +556,27 @@ impl From<TomlClient> for ConfigClient {
         let ips = HashSet::from_iter(toml.ips.into_iter().flatten());
         let port = toml.port.unwrap_or(DEFAULT_PORT);
         let pos = toml.position.unwrap_or_default();
+        let default_pointer = PointerConfig::default();
+        let pointer = PointerConfig {
+            sensitivity: toml.sensitivity.unwrap_or(default_pointer.sensitivity),
+            acceleration: toml.acceleration,
+            scroll_speed: toml.scroll_speed.unwrap_or(default_pointer.scroll_speed),
+            natural_scrolling: toml
+                .natural_scrolling
+                .unwrap_or(default_pointer.natural_scrolling),
+        };
+        let sanitized = pointer.sanitized();
+        if sanitized != pointer {
+            log::warn!("invalid pointer settings {pointer:?}, using {sanitized:?}");
+        }
+        let pointer = sanitized;
+        let key_remap = toml
+            .key_remap
+            .into_iter()
+            .flatten()
+            .map(|(from, to)| (from as u32, to as u32))
+            .collect();
+        let edge = toml.edge.map(EdgePolicy::from).unwrap_or_default();
         Self {
             ips,
             hostname,
@@ -270,6 +584,13 @@ impl From<TomlClient> for ConfigClient {
             pos,
             active,
             enter_hook,
+            pointer,
+            key_remap,
+            edge,
+            mac_address: toml.mac_address,
+            fingerprint: toml.fingerprint,
+            transport: toml.transport.unwrap_or_default(),
+            relay: toml.relay,
         }
     }
 }
@@ -282,6 +603,120 @@ pub enum ConfigError {
     Io(#[from] io::Error),
     #[error(transparent)]
     Var(#[from] VarError),
+    #[error(transparent)]
+    TomlEdit(#[from] toml_edit::TomlError),
+    #[error("client {0} not found in config file")]
+    NoSuchClient(usize),
+    #[error("clients in config file are not an array of tables")]
+    InvalidClients,
+}
+
+/// pin the certificate fingerprint of the client at `index` of the `[[clients]]`
+/// in the config file. Formatting and comments of the file are preserved.
+pub fn save_client_fingerprint(
+    path: &Path,
+    index: usize,
+    fingerprint: &str,
+) -> Result<(), ConfigError> {
+    let mut doc = fs::read_to_string(path)?.parse::<toml_edit::DocumentMut>()?;
+    let client = doc
+        .get_mut("clients")
+        .and_then(|c| c.as_array_of_tables_mut())
+        .and_then(|c| c.get_mut(index))
+        .ok_or(ConfigError::NoSuchClient(index))?;
+    client["fingerprint"] = toml_edit::value(fingerprint);
+    fs::write(path, doc.to_string())?;
+    Ok(())
+}
+
+/// append a client to the `[[clients]]` of the config file,
+/// which is created if it does not exist. Returns the index of the client.
+pub fn save_client(path: &Path, client: &ClientConfig) -> Result<usize, ConfigError> {
+    let mut doc = match fs::read_to_string(path) {
+        Ok(s) => s.parse::<toml_edit::DocumentMut>()?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Default::default(),
+        Err(e) => return Err(e.into()),
+    };
+    let mut table = toml_edit::Table::new();
+    table["position"] = toml_edit::value(client.pos.to_string());
+    if let Some(hostname) = &client.hostname {
+        table["hostname"] = toml_edit::value(hostname);
+    }
+    if !client.fix_ips.is_empty() {
+        let ips = client.fix_ips.iter().map(|ip| ip.to_string());
+        table["ips"] = toml_edit::value(toml_edit::Array::from_iter(ips));
+    }
+    if client.port != DEFAULT_PORT {
+        table["port"] = toml_edit::value(i64::from(client.port));
+    }
+    if let Some(relay) = &client.relay {
+        table["relay"] = toml_edit::value(relay);
+    }
+    if let Some(fingerprint) = &client.fingerprint {
+        table["fingerprint"] = toml_edit::value(fingerprint);
+    }
+    let clients = doc["clients"]
+        .or_insert(toml_edit::ArrayOfTables::new().into())
+        .as_array_of_tables_mut()
+        .ok_or(ConfigError::InvalidClients)?;
+    clients.push(table);
+    let index = clients.len() - 1;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, doc.to_string())?;
+    Ok(index)
+}
+
+/// authorize a fingerprint in the config file.
+/// Input permissions of an already listed fingerprint are kept.
+pub fn save_authorized_key(
+    path: &Path,
+    fingerprint: &str,
+    description: &str,
+) -> Result<(), ConfigError> {
+    let mut doc = fs::read_to_string(path)?.parse::<toml_edit::DocumentMut>()?;
+    let keys = doc["authorized_fingerprints"].or_insert(toml_edit::table());
+    match keys.get_mut(fingerprint) {
+        Some(key) if key.is_table_like() => key["description"] = toml_edit::value(description),
+        _ => keys[fingerprint] = toml_edit::value(description),
+    }
+    fs::write(path, doc.to_string())?;
+    Ok(())
+}
+
+/// remove a fingerprint from the authorized fingerprints in the config file
+pub fn remove_authorized_key(path: &Path, fingerprint: &str) -> Result<(), ConfigError> {
+    let mut doc = fs::read_to_string(path)?.parse::<toml_edit::DocumentMut>()?;
+    let removed = doc
+        .get_mut("authorized_fingerprints")
+        .and_then(|k| k.as_table_like_mut())
+        .and_then(|k| k.remove(fingerprint));
+    if removed.is_some() {
+        fs::write(path, doc.to_string())?;
+    }
+    Ok(())
+}
+
+/// authorize the new fingerprint of a device that rotated its key in place of
+/// the previous one, together with its description and input permissions
+pub fn save_key_transition(
+    path: &Path,
+    previous: &str,
+    fingerprint: &str,
+) -> Result<(), ConfigError> {
+    let mut doc = fs::read_to_string(path)?.parse::<toml_edit::DocumentMut>()?;
+    let Some(keys) = doc
+        .get_mut("authorized_fingerprints")
+        .and_then(|k| k.as_table_like_mut())
+    else {
+        return Ok(());
+    };
+    if let Some(key) = keys.remove(previous) {
+        keys.insert(fingerprint, key);
+        fs::write(path, doc.to_string())?;
+    }
+    Ok(())
 }
 
 const DEFAULT_RELEASE_KEYS: [scancode::Linux; 4] =
@@ -332,10 +767,63 @@ impl Config {
 
     /// public key fingerprints authorized for connection
     pub fn authorized_fingerprints(&self) -> HashMap<String, String> {
+        self.config_toml
+            .as_ref()
+            .and_then(|c| c.authorized_fingerprints.as_ref())
+            .into_iter()
+            .flatten()
+            .map(|(fp, key)| (fp.clone(), key.description().to_owned()))
+            .collect()
+    }
+
+    /// input permissions of authorized public key fingerprints.
+    /// Authorized devices not listed here may send any input.
+    pub fn input_permissions(&self) -> HashMap<String, InputPermissions> {
         self.config_toml
             .as_ref()
             .and_then(|c| c.authorized_fingerprints.clone())
             .unwrap_or_default()
+            .into_iter()
+            .map(|(fp, key)| (fp, key.into()))
+            .collect()
+    }
+
+    /// certificate authority whose certificates are trusted (if any)
+    pub fn certificate_authority(&self) -> Option<CertificateAuthorityConfig> {
+        self.config_toml
+            .as_ref()
+            .and_then(|c| c.certificate_authority.clone())
+            .map(|ca| ca.into())
+    }
+
+    /// the audit log (if enabled), next to the config file by default
+    pub fn audit(&self) -> Option<AuditConfig> {
+        let audit = self.config_toml.as_ref().and_then(|c| c.audit.clone());
+        if audit.as_ref().and_then(|a| a.enabled) == Some(false) {
+            return None;
+        }
+        let path = audit.as_ref().and_then(|a| a.path.clone()).unwrap_or(
+            self.config_path
+                .parent()
+                .unwrap_or(Path::new("."))
+                .join(AUDIT_FILE_NAME),
+        );
+        let max_size = audit.as_ref().and_then(|a| a.max_size);
+        let keep = audit.as_ref().and_then(|a| a.keep);
+        Some(AuditConfig {
+            path,
+            max_size: max_size.unwrap_or(DEFAULT_AUDIT_MAX_SIZE) * 1024,
+            keep: keep.unwrap_or(DEFAULT_AUDIT_KEEP),
+        })
+    }
+
+    /// limits for inbound connections
+    pub fn limits(&self) -> LimitsConfig {
+        self.config_toml
+            .as_ref()
+            .and_then(|c| c.limits.clone())
+            .map(|l| l.into())
+            .unwrap_or_default()
     }
 
     /// path to certificate
@@ -377,6 +865,55 @@ impl Config {
             .collect()
     }
 
+    /// key bindings
+    pub fn bindings(&self) -> Vec<Binding> {
+        self.config_toml
+            .as_ref()
+            .and_then(|c| c.bindings.clone())
+            .unwrap_or_default()
+    }
+
+    /// time without input after which control returns to this device
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        self.config_toml
+            .as_ref()
+            .and_then(|c| c.idle_timeout)
+            .map(Duration::from_secs)
+    }
+
+    /// whether the cursor is moved to the center of the screen after the idle timeout
+    pub fn idle_center_cursor(&self) -> bool {
+        self.config_toml
+            .as_ref()
+            .and_then(|c| c.idle_center_cursor)
+            .unwrap_or(false)
+    }
+
+    /// whether the screen lock is synchronized with connected devices
+    pub fn sync_screen_lock(&self) -> bool {
+        self.config_toml
+            .as_ref()
+            .and_then(|c| c.sync_screen_lock)
+            .unwrap_or(false)
+    }
+
+    /// whether peers may also unlock the screen of this device
+    /// (only honored for permanently authorized devices without input restrictions)
+    pub fn sync_screen_unlock(&self) -> bool {
+        self.config_toml
+            .as_ref()
+            .and_then(|c| c.sync_screen_unlock)
+            .unwrap_or(false)
+    }
+
+    /// whether this device is advertised and other devices are discovered via mDNS
+    pub fn discovery(&self) -> bool {
+        self.config_toml
+            .as_ref()
+            .and_then(|c| c.discovery)
+            .unwrap_or(false)
+    }
+
     /// release bind for returning control to the host
     pub fn release_bind(&self) -> Vec<scancode::Linux> {
         self.config_toml
@@ -385,3 +922,66 @@ impl Config {
             .unwrap_or(Vec::from_iter(DEFAULT_RELEASE_KEYS.iter().cloned()))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_transitions_keep_description_and_permissions() {
+        let path = env::temp_dir().join(format!("lan-mouse-keys-{}.toml", std::process::id()));
+        fs::write(
+            &path,
+            "port = 4242\n\n[authorized_fingerprints]\n\"aa\" = \"laptop\"\n\n\
+             [authorized_fingerprints.\"bb\"]\ndescription = \"tablet\"\nkeyboard = false\n",
+        )
+        .unwrap();
+        save_key_transition(&path, "aa", "cc").unwrap();
+        save_key_transition(&path, "bb", "dd").unwrap();
+        save_authorized_key(&path, "dd", "phone").unwrap();
+        save_authorized_key(&path, "ee", "desktop").unwrap();
+        remove_authorized_key(&path, "ee").unwrap();
+        let doc = fs::read_to_string(&path)
+            .unwrap()
+            .parse::<toml_edit::DocumentMut>()
+            .unwrap();
+        fs::remove_file(&path).unwrap();
+        let keys = doc["authorized_fingerprints"].as_table_like().unwrap();
+        assert_eq!(keys.len(), 2);
+        assert_eq!(keys.get("cc").and_then(|k| k.as_str()), Some("laptop"));
+        let dd = keys.get("dd").unwrap();
+        assert_eq!(dd["description"].as_str(), Some("phone"));
+        assert_eq!(dd["keyboard"].as_bool(), Some(false));
+    }
+
+    #[test]
+    fn clients_are_appended_to_the_config_file() {
+        let dir = env::temp_dir().join(format!("lan-mouse-clients-{}", std::process::id()));
+        let path = dir.join("config.toml");
+        let client = ClientConfig {
+            hostname: Some("thorium".into()),
+            fix_ips: vec!["192.168.178.189".parse().unwrap()],
+            pos: Position::Top,
+            fingerprint: Some("aa".into()),
+            ..Default::default()
+        };
+        assert_eq!(save_client(&path, &client).unwrap(), 0);
+        assert_eq!(save_client(&path, &client).unwrap(), 1);
+        save_client_fingerprint(&path, 1, "bb").unwrap();
+        let config = ConfigToml::new(&path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        let clients = config
+            .clients
+            .unwrap()
+            .into_iter()
+            .map(ConfigClient::from)
+            .collect::<Vec<_>>();
+        assert_eq!(clients.len(), 2);
+        assert_eq!(clients[0].hostname.as_deref(), Some("thorium"));
+        assert_eq!(clients[0].ips, client.fix_ips.into_iter().collect());
+        assert_eq!(clients[0].pos, Position::Top);
+        assert_eq!(clients[0].port, DEFAULT_PORT);
+        assert_eq!(clients[0].fingerprint.as_deref(), Some("aa"));
+        assert_eq!(clients[1].fingerprint.as_deref(), Some("bb"));
+    }
+}
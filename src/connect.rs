@@ -1,21 +1,31 @@
-use crate::client::ClientManager;
-use lan_mouse_ipc::{ClientHandle, DEFAULT_PORT};
+use crate::{
+    ca::CertificateAuthority,
+    client::ClientManager,
+    crypto,
+    listen::VerifyPeerCertificateFn,
+    relay::{self, RelayError},
+    tls::{self, TlsError},
+    wol,
+};
+use lan_mouse_ipc::{ClientHandle, ConnectionState, DEFAULT_PORT, MacAddress, Transport};
 use lan_mouse_proto::{MAX_EVENT_SIZE, ProtoEvent};
 use local_channel::mpsc::{Receiver, Sender, channel};
+use rustls::pki_types::CertificateDer;
 use std::{
     cell::RefCell,
     collections::{HashMap, HashSet},
-    io,
+    io, iter,
     net::SocketAddr,
     rc::Rc,
     sync::Arc,
-    time::Duration,
+    time::{Duration, Instant},
 };
 use thiserror::Error;
 use tokio::{
     net::UdpSocket,
-    sync::Mutex,
+    sync::{Mutex, Notify},
     task::{JoinSet, spawn_local},
+    time::{Interval, MissedTickBehavior},
 };
 use webrtc_dtls::{
     config::{Config, ExtendedMasterSecretType},
@@ -32,19 +42,73 @@ pub(crate) enum LanMouseConnectionError {
     Dtls(#[from] webrtc_dtls::Error),
     #[error(transparent)]
     Webrtc(#[from] webrtc_util::Error),
+    #[error(transparent)]
+    Tls(#[from] TlsError),
+    #[error(transparent)]
+    Relay(#[from] RelayError),
     #[error("not connected")]
     NotConnected,
     #[error("emulation is disabled on the target device")]
     TargetEmulationDisabled,
     #[error("Connection timed out")]
     Timeout,
+    #[error("certificate fingerprint changed (expected {expected}, received {received})")]
+    FingerprintMismatch { expected: String, received: String },
+}
+
+pub(crate) enum ConnectionEvent {
+    /// connected to a client
+    Connected {
+        handle: ClientHandle,
+        addr: SocketAddr,
+        fingerprint: String,
+    },
+    /// the certificate of a client was trusted on first use
+    /// or the client rotated its key
+    FingerprintPinned(ClientHandle, String),
+    /// a client presented a certificate that does not match the pinned fingerprint
+    FingerprintMismatch {
+        handle: ClientHandle,
+        expected: String,
+        received: String,
+    },
+    /// the connection state of a client changed
+    StateChanged(ClientHandle),
 }
 
 const DEFAULT_CONNECTION_TIMEOUT: Duration = Duration::from_secs(5);
 
-async fn connect(
+/// number of connection attempts after waking a client
+const WAKE_ATTEMPTS: usize = 6;
+/// time to wait for a client to wake up before each connection attempt
+const WAKE_RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// interval in which active clients without a connection are checked
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+/// time to wait after the first failed connection attempt,
+/// doubled with every further failure up to [`MAX_BACKOFF`]
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// how the certificate presented by a server is verified
+#[derive(Clone, Default)]
+pub(crate) struct Verification {
+    /// fingerprint the certificate must match
+    pinned: Option<String>,
+    /// certificate authority checked if no fingerprint is pinned,
+    /// with the hostname the certificate must be issued for
+    ca: Option<(Arc<CertificateAuthority>, String)>,
+}
+
+/// connect to the given address.
+/// If a fingerprint is `pinned`, the handshake fails
+/// unless the server presents a matching certificate
+/// or a key transition statement signed by the pinned certificate.
+/// The certificate authority never overrides a pinned fingerprint.
+pub(crate) async fn connect(
     addr: SocketAddr,
     cert: Certificate,
+    verification: Verification,
 ) -> Result<(Arc<dyn Conn + Sync + Send>, SocketAddr), (SocketAddr, LanMouseConnectionError)> {
     log::info!("connecting to {addr} ...");
     let conn = Arc::new(
@@ -53,10 +117,44 @@ async fn connect(
             .map_err(|e| (addr, e.into()))?,
     );
     conn.connect(addr).await.map_err(|e| (addr, e.into()))?;
+    dtls_connect(conn, addr, cert, verification).await
+}
+
+/// connect to the device with the pinned fingerprint through a relay.
+/// The relay only forwards the DTLS records, certificates are verified as in [`connect`].
+async fn connect_relayed(
+    relay: &str,
+    cert: Certificate,
+    verification: Verification,
+) -> Result<(Arc<dyn Conn + Sync + Send>, SocketAddr), LanMouseConnectionError> {
+    let fingerprint = verification
+        .pinned
+        .clone()
+        .ok_or(RelayError::NoFingerprint)?;
+    log::info!("connecting to {fingerprint} via relay {relay} ...");
+    let (conn, addr) = relay::open(relay, &fingerprint).await?;
+    dtls_connect(Arc::new(conn), addr, cert, verification)
+        .await
+        .map_err(|(_, e)| e)
+}
+
+/// perform the DTLS handshake on a connected socket
+async fn dtls_connect(
+    conn: Arc<UdpSocket>,
+    addr: SocketAddr,
+    cert: Certificate,
+    verification: Verification,
+) -> Result<(Arc<dyn Conn + Sync + Send>, SocketAddr), (SocketAddr, LanMouseConnectionError)> {
+    let pinned = verification.pinned.clone();
+    let mismatch: Mismatch = Default::default();
+    let verify_peer_certificate = verify_server(verification, mismatch.clone());
     let config = Config {
         certificates: vec![cert],
+        // certificates are self-signed, the pinned fingerprint
+        // or the certificate authority is checked instead
         server_name: "ignored".to_owned(),
         insecure_skip_verify: true,
+        verify_peer_certificate,
         extended_master_secret: ExtendedMasterSecretType::Require,
         ..Default::default()
     };
@@ -65,24 +163,115 @@ async fn connect(
         _ = timeout => Err((addr, LanMouseConnectionError::Timeout)),
         result = DTLSConn::new(conn, config, true, None) => match result {
             Ok(dtls_conn) => Ok((Arc::new(dtls_conn), addr)),
-            Err(e) => Err((addr, e.into())),
+            Err(e) => Err((addr, mismatch_error(pinned, &mismatch, e.into()))),
+        }
+    }
+}
+
+/// connect to the given address using TLS over TCP.
+/// Certificates are verified as in [`connect`].
+pub(crate) async fn connect_tls(
+    addr: SocketAddr,
+    cert: Certificate,
+    verification: Verification,
+) -> Result<(Arc<dyn Conn + Sync + Send>, SocketAddr), (SocketAddr, LanMouseConnectionError)> {
+    log::info!("connecting to {addr} (TLS over TCP) ...");
+    let pinned = verification.pinned.clone();
+    let mismatch: Mismatch = Default::default();
+    let verify_peer_certificate = verify_server(verification, mismatch.clone());
+    let config = tls::client_config(&cert, verify_peer_certificate)
+        .map_err(|e| (addr, TlsError::from(e).into()))?;
+    match tls::connect(addr, config).await {
+        Ok(tls_conn) => Ok((Arc::new(tls_conn), addr)),
+        Err(e) => Err((addr, mismatch_error(pinned, &mismatch, e.into()))),
+    }
+}
+
+/// fingerprint of the certificate presented instead of the pinned one
+type Mismatch = Arc<std::sync::Mutex<Option<String>>>;
+
+/// verifies the certificate of the server against the pinned fingerprint,
+/// the fingerprint of a mismatching certificate is stored in `mismatch`.
+/// Without a pinned fingerprint, the certificate is checked against the
+/// certificate authority, if any, and trusted on first use otherwise.
+fn verify_server(
+    verification: Verification,
+    mismatch: Mismatch,
+) -> Option<VerifyPeerCertificateFn> {
+    let Some(expected) = verification.pinned else {
+        let (ca, hostname) = verification.ca?;
+        return Some(Arc::new(
+            move |certs: &[Vec<u8>], _chains: &[CertificateDer<'static>]| {
+                match ca.verify_server(certs, &hostname) {
+                    Ok(()) => {
+                        log::info!("certificate of {hostname} issued by the certificate authority")
+                    }
+                    Err(e) => log::debug!("certificate authority: {e}"),
+                }
+                Ok(())
+            },
+        ));
+    };
+    Some(Arc::new(
+        move |certs: &[Vec<u8>], _chains: &[CertificateDer<'static>]| {
+            let fingerprint = certs
+                .first()
+                .map(|c| crypto::generate_fingerprint(c))
+                .unwrap_or_default();
+            if fingerprint == expected || crypto::key_transition(certs).as_ref() == Some(&expected)
+            {
+                Ok(())
+            } else {
+                mismatch.lock().expect("lock").replace(fingerprint);
+                Err(webrtc_dtls::Error::ErrVerifyDataMismatch)
+            }
+        },
+    ) as VerifyPeerCertificateFn)
+}
+
+/// report a changed certificate in favour of the handshake error
+fn mismatch_error(
+    pinned: Option<String>,
+    mismatch: &Mismatch,
+    e: LanMouseConnectionError,
+) -> LanMouseConnectionError {
+    match (pinned, mismatch.lock().expect("lock").take()) {
+        (Some(expected), Some(received)) => {
+            LanMouseConnectionError::FingerprintMismatch { expected, received }
         }
+        _ => e,
     }
 }
 
+/// connect to any of the given addresses using DTLS or, if `transport` is
+/// [`Transport::Tcp`], TLS over TCP
 async fn connect_any(
     addrs: &[SocketAddr],
     cert: Certificate,
+    verification: Verification,
+    transport: Transport,
 ) -> Result<(Arc<dyn Conn + Send + Sync>, SocketAddr), LanMouseConnectionError> {
     let mut joinset = JoinSet::new();
     for &addr in addrs {
-        joinset.spawn_local(connect(addr, cert.clone()));
+        let (cert, verification) = (cert.clone(), verification.clone());
+        match transport {
+            Transport::Tcp => joinset.spawn_local(connect_tls(addr, cert, verification)),
+            Transport::Auto | Transport::Udp => {
+                joinset.spawn_local(connect(addr, cert, verification))
+            }
+        };
     }
+    // a changed certificate is reported in favour of unreachable addresses
+    let mut mismatch = None;
     loop {
         match joinset.join_next().await {
-            None => return Err(LanMouseConnectionError::NotConnected),
+            None => return Err(mismatch.unwrap_or(LanMouseConnectionError::NotConnected)),
             Some(r) => match r.expect("join error") {
                 Ok(conn) => return Ok(conn),
+                Err((a, e @ LanMouseConnectionError::FingerprintMismatch { .. })) => {
+                    log::warn!("failed to connect to {a}: `{e}`");
+                    mismatch = Some(e);
+                }
                 Err((a, e)) => {
                     log::warn!("failed to connect to {a}: `{e}`")
                 }
@@ -91,32 +280,144 @@ async fn connect_any(
     }
 }
 
+/// connect using the transport of the client. With [`Transport::Auto`],
+/// TLS over TCP is tried if none of the addresses is reachable over DTLS.
+async fn connect_transport(
+    addrs: &[SocketAddr],
+    cert: Certificate,
+    verification: Verification,
+    transport: Transport,
+) -> Result<(Arc<dyn Conn + Send + Sync>, SocketAddr), LanMouseConnectionError> {
+    let result = connect_any(addrs, cert.clone(), verification.clone(), transport).await;
+    match (transport, result) {
+        (Transport::Auto, Err(LanMouseConnectionError::NotConnected)) => {
+            log::info!("unreachable over DTLS, falling back to TLS over TCP ...");
+            connect_any(addrs, cert, verification, Transport::Tcp).await
+        }
+        (_, result) => result,
+    }
+}
+
+/// wake a client that could not be reached and try connecting to it again
+async fn wake_and_connect(
+    handle: ClientHandle,
+    mac: MacAddress,
+    addrs: &[SocketAddr],
+    cert: Certificate,
+    verification: Verification,
+    transport: Transport,
+) -> Result<(Arc<dyn Conn + Send + Sync>, SocketAddr), LanMouseConnectionError> {
+    let mut result = Err(LanMouseConnectionError::NotConnected);
+    for attempt in 1..=WAKE_ATTEMPTS {
+        log::info!("client ({handle}) unreachable, waking ({attempt}/{WAKE_ATTEMPTS}) ...");
+        if let Err(e) = wol::wake(mac).await {
+            log::warn!("failed to send wake-on-lan packet: {e}");
+        }
+        tokio::time::sleep(WAKE_RETRY_INTERVAL).await;
+        result = connect_transport(addrs, cert.clone(), verification.clone(), transport).await;
+        if result.is_ok() {
+            break;
+        }
+    }
+    result
+}
+
+/// failed connection attempts to a client
+struct Backoff {
+    failures: u32,
+    /// no connection attempt is made before this point in time
+    retry_at: Instant,
+    /// the last attempt tried to wake the client
+    woken: bool,
+}
+
+impl Backoff {
+    fn failed(previous: Option<&Backoff>, woken: bool) -> Self {
+        let failures = previous.map_or(0, |b| b.failures) + 1;
+        let delay = MIN_BACKOFF
+            .saturating_mul(1 << (failures - 1).min(16))
+            .min(MAX_BACKOFF);
+        Self {
+            failures,
+            retry_at: Instant::now() + delay,
+            woken,
+        }
+    }
+
+    /// whether a connection attempt is made now.
+    /// Waking a client is not delayed by failed attempts that did not wake it.
+    fn may_retry(&self, wake: bool) -> bool {
+        Instant::now() >= self.retry_at || (wake && !self.woken)
+    }
+}
+
 pub(crate) struct LanMouseConnection {
     cert: Certificate,
+    ca: Option<Arc<CertificateAuthority>>,
     client_manager: ClientManager,
     conns: Rc<Mutex<HashMap<SocketAddr, Arc<dyn Conn + Send + Sync>>>>,
     connecting: Rc<Mutex<HashSet<ClientHandle>>>,
     recv_rx: Receiver<(ClientHandle, ProtoEvent)>,
     recv_tx: Sender<(ClientHandle, ProtoEvent)>,
+    event_tx: Sender<ConnectionEvent>,
     ping_response: Rc<RefCell<HashSet<SocketAddr>>>,
+    backoff: Rc<RefCell<HashMap<ClientHandle, Backoff>>>,
+    reconnect: Interval,
+    network_changed: Rc<Notify>,
 }
 
 impl LanMouseConnection {
-    pub(crate) fn new(cert: Certificate, client_manager: ClientManager) -> Self {
+    /// certificates pinned or rejected while connecting are reported to `event_tx`,
+    /// failed clients are retried without waiting for the backoff once `network_changed` is notified
+    pub(crate) fn new(
+        cert: Certificate,
+        ca: Option<Arc<CertificateAuthority>>,
+        client_manager: ClientManager,
+        event_tx: Sender<ConnectionEvent>,
+        network_changed: Rc<Notify>,
+    ) -> Self {
         let (recv_tx, recv_rx) = channel();
+        let mut reconnect = tokio::time::interval(RECONNECT_INTERVAL);
+        reconnect.set_missed_tick_behavior(MissedTickBehavior::Skip);
         Self {
             cert,
+            ca,
             client_manager,
             conns: Default::default(),
             connecting: Default::default(),
             recv_rx,
             recv_tx,
+            event_tx,
             ping_response: Default::default(),
+            backoff: Default::default(),
+            reconnect,
+            network_changed,
         }
     }
 
+    /// receive the next event from any client.
+    ///
+    /// This is polled continuously, so it also keeps connecting
+    /// active clients without a connection in the background.
     pub(crate) async fn recv(&mut self) -> (ClientHandle, ProtoEvent) {
-        self.recv_rx.recv().await.expect("channel closed")
+        loop {
+            tokio::select! {
+                event = self.recv_rx.recv() => return event.expect("channel closed"),
+                _ = self.reconnect.tick() => self.reconnect().await,
+                _ = self.network_changed.notified() => {
+                    self.backoff.borrow_mut().clear();
+                    self.reconnect().await;
+                }
+            }
+        }
+    }
+
+    async fn reconnect(&self) {
+        for handle in self.client_manager.active_clients() {
+            if self.client_manager.active_addr(handle).is_none() {
+                self.connect(handle, false).await;
+            }
+        }
     }
 
     pub(crate) async fn send(
@@ -139,7 +440,14 @@ impl LanMouseConnection {
                     Ok(_) => {}
                     Err(e) => {
                         log::warn!("client {handle} failed to send: {e}");
-                        disconnect(&self.client_manager, handle, addr, &self.conns).await;
+                        disconnect(
+                            &self.client_manager,
+                            handle,
+                            addr,
+                            &self.conns,
+                            &self.event_tx,
+                        )
+                        .await;
                     }
                 }
                 log::trace!("{event} >->->->->- {addr}");
@@ -147,34 +455,133 @@ impl LanMouseConnection {
             }
         }
 
+        // only entering a client wakes it up, reconnecting in the background never does
+        self.connect(handle, matches!(event, ProtoEvent::Enter(_)))
+            .await;
+        Err(LanMouseConnectionError::NotConnected)
+    }
+
+    /// connect to the client in the background,
+    /// unless already connecting or waiting after failed attempts.
+    /// If `wake` is set, an unreachable client with a hardware address is woken up.
+    async fn connect(&self, handle: ClientHandle, wake: bool) {
+        if let Some(backoff) = self.backoff.borrow().get(&handle) {
+            if !backoff.may_retry(wake) {
+                return;
+            }
+        }
         // check if we are already trying to connect
         let mut connecting = self.connecting.lock().await;
         if !connecting.contains(&handle) {
             connecting.insert(handle);
-            // connect in the background
-            spawn_local(connect_to_handle(
+            set_connection_state(
+                &self.client_manager,
+                &self.event_tx,
+                handle,
+                ConnectionState::Connecting,
+            );
+            let connect = connect_to_handle(
                 self.client_manager.clone(),
                 self.cert.clone(),
+                self.ca.clone(),
                 handle,
                 self.conns.clone(),
                 self.connecting.clone(),
                 self.recv_tx.clone(),
+                self.event_tx.clone(),
                 self.ping_response.clone(),
-            ));
+                wake,
+            );
+            let client_manager = self.client_manager.clone();
+            let event_tx = self.event_tx.clone();
+            let backoff = self.backoff.clone();
+            spawn_local(async move {
+                let result = connect.await;
+                match &result {
+                    Ok(_) => {
+                        backoff.borrow_mut().remove(&handle);
+                    }
+                    Err(e) => {
+                        let mut backoff = backoff.borrow_mut();
+                        let woken = wake && client_manager.get_mac_address(handle).is_some();
+                        let next = Backoff::failed(backoff.get(&handle), woken);
+                        let delay = next.retry_at.saturating_duration_since(Instant::now());
+                        log::info!(
+                            "client ({handle}): {e}, retrying in {}s",
+                            delay.as_secs_f32().round()
+                        );
+                        backoff.insert(handle, next);
+                        let state = ConnectionState::Failed(e.to_string());
+                        set_connection_state(&client_manager, &event_tx, handle, state);
+                    }
+                }
+                let events = match result {
+                    Ok(connected) => {
+                        let pinned = connected.pinned.then(|| {
+                            ConnectionEvent::FingerprintPinned(
+                                handle,
+                                connected.fingerprint.clone(),
+                            )
+                        });
+                        let connected = ConnectionEvent::Connected {
+                            handle,
+                            addr: connected.addr,
+                            fingerprint: connected.fingerprint,
+                        };
+                        iter::once(connected).chain(pinned).collect()
+                    }
+                    Err(LanMouseConnectionError::FingerprintMismatch { expected, received }) => {
+                        vec![ConnectionEvent::FingerprintMismatch {
+                            handle,
+                            expected,
+                            received,
+                        }]
+                    }
+                    Err(_) => vec![],
+                };
+                for event in events {
+                    event_tx.send(event).expect("channel closed");
+                }
+            });
         }
-        Err(LanMouseConnectionError::NotConnected)
     }
 }
 
+fn set_connection_state(
+    client_manager: &ClientManager,
+    event_tx: &Sender<ConnectionEvent>,
+    handle: ClientHandle,
+    state: ConnectionState,
+) {
+    client_manager.set_connection_state(handle, state);
+    event_tx
+        .send(ConnectionEvent::StateChanged(handle))
+        .expect("channel closed");
+}
+
+/// an established connection to a client
+struct Connected {
+    addr: SocketAddr,
+    /// fingerprint of the certificate presented by the client
+    fingerprint: String,
+    /// the fingerprint was newly pinned (trusted on first use or after a key rotation)
+    pinned: bool,
+}
+
+/// connect to the given client, waking it up if `wake` is set.
+#[allow(clippy::too_many_arguments)]
 async fn connect_to_handle(
     client_manager: ClientManager,
     cert: Certificate,
+    ca: Option<Arc<CertificateAuthority>>,
     handle: ClientHandle,
     conns: Rc<Mutex<HashMap<SocketAddr, Arc<dyn Conn + Send + Sync>>>>,
     connecting: Rc<Mutex<HashSet<ClientHandle>>>,
     tx: Sender<(ClientHandle, ProtoEvent)>,
+    event_tx: Sender<ConnectionEvent>,
     ping_response: Rc<RefCell<HashSet<SocketAddr>>>,
-) -> Result<(), LanMouseConnectionError> {
+    wake: bool,
+) -> Result<Connected, LanMouseConnectionError> {
     log::info!("client {handle} connecting ...");
     // sending did not work, figure out active conn.
     if let Some(addrs) = client_manager.get_ips(handle) {
@@ -184,7 +591,24 @@ async fn connect_to_handle(
             .map(|a| SocketAddr::new(a, port))
             .collect::<Vec<_>>();
         log::info!("client ({handle}) connecting ... (ips: {addrs:?})");
-        let res = connect_any(&addrs, cert).await;
+        let pinned = client_manager.get_fingerprint(handle);
+        let verification = Verification {
+            pinned: pinned.clone(),
+            ca: ca.zip(client_manager.get_hostname(handle)),
+        };
+        let transport = client_manager.get_transport(handle);
+        let res = match client_manager.get_relay(handle) {
+            Some(relay) => connect_relayed(&relay, cert.clone(), verification.clone()).await,
+            None => connect_transport(&addrs, cert.clone(), verification.clone(), transport).await,
+        };
+        let mac = client_manager.get_mac_address(handle).filter(|_| wake);
+        let res = match (res, mac) {
+            // the client is probably asleep
+            (Err(LanMouseConnectionError::NotConnected), Some(mac)) => {
+                wake_and_connect(handle, mac, &addrs, cert, verification, transport).await
+            }
+            (res, _) => res,
+        };
         let (conn, addr) = match res {
             Ok(c) => c,
             Err(e) => {
@@ -193,9 +617,24 @@ async fn connect_to_handle(
             }
         };
         log::info!("client ({handle}) connected @ {addr}");
+        let newly_pinned = match pinned {
+            Some(pinned) => follow_key_transition(&client_manager, handle, &conn, &pinned).await,
+            None => trust_on_first_use(&client_manager, handle, &conn).await,
+        };
+        let pinned = newly_pinned.is_some();
+        let fingerprint = match newly_pinned {
+            Some(fingerprint) => fingerprint,
+            None => peer_fingerprint(&conn).await.unwrap_or_default(),
+        };
         client_manager.set_active_addr(handle, Some(addr));
         conns.lock().await.insert(addr, conn.clone());
         connecting.lock().await.remove(&handle);
+        set_connection_state(
+            &client_manager,
+            &event_tx,
+            handle,
+            ConnectionState::Connected,
+        );
 
         // poll connection for active
         spawn_local(ping_pong(addr, conn.clone(), ping_response.clone()));
@@ -208,14 +647,58 @@ async fn connect_to_handle(
             conn,
             conns,
             tx,
+            event_tx,
             ping_response.clone(),
         ));
-        return Ok(());
+        return Ok(Connected {
+            addr,
+            fingerprint,
+            pinned,
+        });
     }
     connecting.lock().await.remove(&handle);
     Err(LanMouseConnectionError::NotConnected)
 }
 
+/// pin the fingerprint of the certificate presented by the client.
+/// Certificates issued by the certificate authority are pinned as well,
+/// so that later connections do not fall back to trusting another certificate.
+async fn trust_on_first_use(
+    client_manager: &ClientManager,
+    handle: ClientHandle,
+    conn: &Arc<dyn Conn + Send + Sync>,
+) -> Option<String> {
+    let certs = tls::peer_certificates(conn).await;
+    let fingerprint = crypto::generate_fingerprint(certs.first()?);
+    log::info!("client ({handle}): trusting certificate {fingerprint} on first use");
+    client_manager.set_fingerprint(handle, Some(fingerprint.clone()));
+    Some(fingerprint)
+}
+
+/// pin the new certificate of a client that rotated its key.
+/// Returns the new fingerprint, if the pinned certificate was replaced.
+async fn follow_key_transition(
+    client_manager: &ClientManager,
+    handle: ClientHandle,
+    conn: &Arc<dyn Conn + Send + Sync>,
+    pinned: &str,
+) -> Option<String> {
+    let certs = tls::peer_certificates(conn).await;
+    let fingerprint = crypto::generate_fingerprint(certs.first()?);
+    if fingerprint == pinned || crypto::key_transition(&certs).as_deref() != Some(pinned) {
+        return None;
+    }
+    log::info!("client ({handle}): rotated its key, pinning certificate {fingerprint}");
+    client_manager.set_fingerprint(handle, Some(fingerprint.clone()));
+    Some(fingerprint)
+}
+
+/// fingerprint of the certificate presented by the other device
+async fn peer_fingerprint(conn: &Arc<dyn Conn + Send + Sync>) -> Option<String> {
+    let certs = tls::peer_certificates(conn).await;
+    certs.first().map(|c| crypto::generate_fingerprint(c))
+}
+
 async fn ping_pong(
     addr: SocketAddr,
     conn: Arc<dyn Conn + Send + Sync>,
@@ -244,6 +727,7 @@ async fn ping_pong(
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn receive_loop(
     client_manager: ClientManager,
     handle: ClientHandle,
@@ -251,6 +735,7 @@ async fn receive_loop(
     conn: Arc<dyn Conn + Send + Sync>,
     conns: Rc<Mutex<HashMap<SocketAddr, Arc<dyn Conn + Send + Sync>>>>,
     tx: Sender<(ClientHandle, ProtoEvent)>,
+    event_tx: Sender<ConnectionEvent>,
     ping_response: Rc<RefCell<HashSet<SocketAddr>>>,
 ) {
     let mut buf = [0u8; MAX_EVENT_SIZE];
@@ -268,7 +753,7 @@ async fn receive_loop(
         }
     }
     log::warn!("recv error");
-    disconnect(&client_manager, handle, addr, &conns).await;
+    disconnect(&client_manager, handle, addr, &conns, &event_tx).await;
 }
 
 async fn disconnect(
@@ -276,10 +761,124 @@ async fn disconnect(
     handle: ClientHandle,
     addr: SocketAddr,
     conns: &Mutex<HashMap<SocketAddr, Arc<dyn Conn + Send + Sync>>>,
+    event_tx: &Sender<ConnectionEvent>,
 ) {
     log::warn!("client ({handle}) @ {addr} connection closed");
     conns.lock().await.remove(&addr);
     client_manager.set_active_addr(handle, None);
+    set_connection_state(
+        client_manager,
+        event_tx,
+        handle,
+        ConnectionState::Disconnected,
+    );
     let active: Vec<SocketAddr> = conns.lock().await.keys().copied().collect();
     log::info!("active connections: {active:?}");
 }
+
+#[cfg(test)]
+mod tests {
+    use rcgen::{CertificateParams, KeyPair};
+
+    use super::*;
+    use crate::ca::tests::{Issuer, dns};
+
+    fn certificate() -> Vec<u8> {
+        let key = KeyPair::generate().expect("key");
+        let params = CertificateParams::new(vec!["desktop.lan.example.com".to_owned()]);
+        let cert = params.expect("params").self_signed(&key).expect("cert");
+        cert.der().to_vec()
+    }
+
+    #[test]
+    fn backoff_grows_up_to_max() {
+        let mut backoff = Backoff::failed(None, false);
+        assert_eq!(backoff.failures, 1);
+        for _ in 0..20 {
+            backoff = Backoff::failed(Some(&backoff), false);
+        }
+        let delay = backoff.retry_at - Instant::now();
+        assert!(delay <= MAX_BACKOFF && delay > MAX_BACKOFF - Duration::from_secs(1));
+    }
+
+    #[test]
+    fn only_entering_skips_backoff_to_wake() {
+        let backoff = Backoff::failed(None, false);
+        assert!(!backoff.may_retry(false));
+        assert!(backoff.may_retry(true));
+        // the client was already woken in vain
+        let backoff = Backoff::failed(Some(&backoff), true);
+        assert!(!backoff.may_retry(true));
+        assert!(!backoff.may_retry(false));
+    }
+
+    fn pinned(fingerprint: String) -> Verification {
+        Verification {
+            pinned: Some(fingerprint),
+            ca: None,
+        }
+    }
+
+    #[test]
+    fn unpinned_is_not_verified() {
+        assert!(verify_server(Default::default(), Default::default()).is_none());
+    }
+
+    #[test]
+    fn pinned_certificate_is_accepted() {
+        let cert = certificate();
+        let pinned = pinned(crypto::generate_fingerprint(&cert));
+        let mismatch: Mismatch = Default::default();
+        let verify = verify_server(pinned, mismatch.clone()).expect("verifier");
+        assert!(verify(&[cert], &[]).is_ok());
+        assert!(mismatch.lock().expect("lock").is_none());
+    }
+
+    #[test]
+    fn other_certificate_is_rejected() {
+        let expected = crypto::generate_fingerprint(&certificate());
+        let other = certificate();
+        let mismatch: Mismatch = Default::default();
+        let verify = verify_server(pinned(expected.clone()), mismatch.clone()).expect("verifier");
+        assert!(verify(std::slice::from_ref(&other), &[]).is_err());
+        assert_eq!(
+            mismatch.lock().expect("lock").clone(),
+            Some(crypto::generate_fingerprint(&other))
+        );
+        let error = mismatch_error(Some(expected), &mismatch, LanMouseConnectionError::Timeout);
+        assert!(matches!(
+            error,
+            LanMouseConnectionError::FingerprintMismatch { .. }
+        ));
+    }
+
+    #[test]
+    fn certificate_issued_by_ca_is_pinned() {
+        let issuer = Issuer::new();
+        let ca = Arc::new(issuer.authority(vec![]));
+        let hostname = "desktop.lan.example.com".to_owned();
+        let issued = issuer.issue(vec![dns(&hostname)]);
+        let first = Verification {
+            pinned: None,
+            ca: Some((ca.clone(), hostname.clone())),
+        };
+        let verify = verify_server(first, Default::default()).expect("verifier");
+        assert!(verify(&issued, &[]).is_ok());
+        // the first connection pins the certificate issued by the CA
+        let fingerprint = crypto::generate_fingerprint(&issued[0]);
+        let second = Verification {
+            pinned: Some(fingerprint),
+            ca: Some((ca, hostname)),
+        };
+        let mismatch: Mismatch = Default::default();
+        let verify = verify_server(second, mismatch.clone()).expect("verifier");
+        assert!(verify(&issued, &[]).is_ok());
+        // a self-signed certificate is not trusted on the second connection
+        let other = certificate();
+        assert!(verify(std::slice::from_ref(&other), &[]).is_err());
+        assert_eq!(
+            mismatch.lock().expect("lock").clone(),
+            Some(crypto::generate_fingerprint(&other))
+        );
+    }
+}
@@ -1,14 +1,19 @@
 use std::fs;
 use std::io::{self, BufWriter, Read, Write};
 use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use std::{fs::File, io::BufReader};
 
 #[cfg(unix)]
 use std::os::unix::fs::PermissionsExt;
 
+use rcgen::{CertificateParams, DnType, KeyPair};
+use rustls::pki_types::{CertificateDer, pem::PemObject};
 use sha2::{Digest, Sha256};
 use thiserror::Error;
-use webrtc_dtls::crypto::Certificate;
+use webpki::EndEntityCert;
+use webrtc_dtls::crypto::{Certificate, CryptoPrivateKey};
+use x509_parser::{certificate::X509Certificate, error::X509Error, prelude::FromDer};
 
 #[derive(Debug, Error)]
 pub enum Error {
@@ -16,6 +21,16 @@ pub enum Error {
     Io(#[from] io::Error),
     #[error(transparent)]
     Dtls(#[from] webrtc_dtls::Error),
+    #[error(transparent)]
+    Rcgen(#[from] rcgen::Error),
+    #[error("invalid PEM file: {0}")]
+    Pem(#[from] rustls::pki_types::pem::Error),
+    #[error("invalid certificate: {0}")]
+    X509(#[from] x509_parser::nom::Err<X509Error>),
+    #[error("no certificate found")]
+    NoCertificate,
+    #[error("the certificate is not issued for the key of this device")]
+    KeyMismatch,
 }
 
 pub fn generate_fingerprint(cert: &[u8]) -> String {
@@ -34,6 +49,72 @@ pub fn certificate_fingerprint(cert: &Certificate) -> String {
     generate_fingerprint(certificate)
 }
 
+/// commitment to a pairing nonce (truncated sha256)
+pub(crate) fn pairing_commitment(nonce: u128) -> u128 {
+    let hash = Sha256::digest(nonce.to_be_bytes());
+    u128::from_be_bytes(hash[..16].try_into().expect("16 bytes"))
+}
+
+/// 6 digit short authentication string derived from the certificates
+/// of both devices and the nonces exchanged during pairing
+pub(crate) fn pairing_code(
+    initiator_fingerprint: &str,
+    responder_fingerprint: &str,
+    initiator_nonce: u128,
+    responder_nonce: u128,
+) -> String {
+    let mut hash = Sha256::new();
+    hash.update(initiator_fingerprint);
+    hash.update(responder_fingerprint);
+    hash.update(initiator_nonce.to_be_bytes());
+    hash.update(responder_nonce.to_be_bytes());
+    let hash = hash.finalize();
+    let n = u32::from_be_bytes(hash[..4].try_into().expect("4 bytes"));
+    format!("{:06}", n % 1_000_000)
+}
+
+/// time at which the certificate of this device expires
+pub(crate) fn certificate_expiry(cert: &Certificate) -> Result<SystemTime, Error> {
+    let der = cert.certificate.first().ok_or(Error::NoCertificate)?;
+    let (_, cert) = X509Certificate::from_der(der)?;
+    let secs = cert.validity().not_after.timestamp().max(0) as u64;
+    Ok(UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+/// whole days until `time` (negative if it has passed)
+pub(crate) fn days_until(time: SystemTime) -> i64 {
+    let secs = match time.duration_since(SystemTime::now()) {
+        Ok(d) => d.as_secs() as i64,
+        Err(e) => -(e.duration().as_secs() as i64),
+    };
+    secs.div_euclid(24 * 60 * 60)
+}
+
+/// If the certificate chain of a device carries a key transition statement,
+/// returns the fingerprint of its previous certificate.
+///
+/// A rotated certificate is followed by a certificate for the new key issued
+/// by the old key (the statement) and the previous certificate itself,
+/// see [`rotate_key_and_cert`].
+pub(crate) fn key_transition(certs: &[Vec<u8>]) -> Option<String> {
+    let [new, statement, old, ..] = certs else {
+        return None;
+    };
+    let (_, new_cert) = X509Certificate::from_der(new).ok()?;
+    let (_, statement_cert) = X509Certificate::from_der(statement).ok()?;
+    if statement_cert.public_key().raw != new_cert.public_key().raw {
+        return None;
+    }
+    let old_der = CertificateDer::from(old.as_slice());
+    let old_cert = EndEntityCert::try_from(&old_der).ok()?;
+    let tbs = statement_cert.tbs_certificate.as_ref();
+    let signature = &statement_cert.signature_value.data;
+    webpki::ALL_VERIFICATION_ALGS
+        .iter()
+        .any(|alg| old_cert.verify_signature(*alg, tbs, signature).is_ok())
+        .then(|| generate_fingerprint(old))
+}
+
 /// load certificate from file
 pub fn load_certificate(path: &Path) -> Result<Certificate, Error> {
     let f = File::open(path)?;
@@ -54,10 +135,81 @@ pub(crate) fn load_or_generate_key_and_cert(path: &Path) -> Result<Certificate,
 
 pub(crate) fn generate_key_and_cert(path: &Path) -> Result<Certificate, Error> {
     let cert = Certificate::generate_self_signed(["ignored".to_owned()])?;
+    save_certificate(path, &cert)?;
+    Ok(cert)
+}
+
+/// replace the key and certificate of this device with a new self-signed one.
+///
+/// With `transition`, the old key signs a statement for the new key,
+/// which lets devices that trusted the old certificate accept the new one
+/// (see [`key_transition`]).
+pub(crate) fn rotate_key_and_cert(path: &Path, transition: bool) -> Result<Certificate, Error> {
+    let old = load_certificate(path)?;
+    let key_pair = KeyPair::generate()?;
+    let cert = CertificateParams::new(vec!["ignored".to_owned()])?.self_signed(&key_pair)?;
+    let mut chain = vec![cert.der().clone()];
+    if transition {
+        let old_cert = old.certificate.first().ok_or(Error::NoCertificate)?;
+        let old_key = KeyPair::try_from(old.private_key.serialized_der.as_slice())?;
+        let issuer = CertificateParams::default().self_signed(&old_key)?;
+        let mut params = CertificateParams::default();
+        params
+            .distinguished_name
+            .push(DnType::CommonName, "lan-mouse key transition");
+        let statement = params.signed_by(&key_pair, &issuer, &old_key)?;
+        chain.push(statement.der().clone());
+        chain.push(old_cert.clone());
+    }
+    let cert = Certificate {
+        certificate: chain,
+        private_key: CryptoPrivateKey::try_from(&key_pair)?,
+    };
+    save_certificate(path, &cert)?;
+    Ok(cert)
+}
+
+/// certificate signing request for the key of this device.
+/// The first of the given DNS names is used as common name.
+pub(crate) fn generate_csr(cert: &Certificate, names: Vec<String>) -> Result<String, Error> {
+    let key_pair = KeyPair::try_from(cert.private_key.serialized_der.as_slice())?;
+    let mut params = CertificateParams::new(names.clone())?;
+    if let Some(name) = names.first() {
+        params
+            .distinguished_name
+            .push(DnType::CommonName, name.as_str());
+    }
+    Ok(params.serialize_request(&key_pair)?.pem()?)
+}
+
+/// replace the certificate of this device with a certificate chain
+/// (PEM, end-entity certificate first) issued for its key
+pub(crate) fn install_certificate(path: &Path, chain_pem: &[u8]) -> Result<Certificate, Error> {
+    let cert = load_certificate(path)?;
+    let chain = CertificateDer::pem_slice_iter(chain_pem).collect::<Result<Vec<_>, _>>()?;
+    let end_entity = chain.first().ok_or(Error::NoCertificate)?;
+    let (_, end_entity) = X509Certificate::from_der(end_entity)?;
+    let key_pair = KeyPair::try_from(cert.private_key.serialized_der.as_slice())?;
+    if end_entity.public_key().raw != key_pair.public_key_der() {
+        return Err(Error::KeyMismatch);
+    }
+    let cert = Certificate {
+        certificate: chain,
+        private_key: cert.private_key,
+    };
+    save_certificate(path, &cert)?;
+    Ok(cert)
+}
+
+/// write the key and certificate chain to `path`, readable only by the owner.
+/// The file is replaced atomically.
+fn save_certificate(path: &Path, cert: &Certificate) -> Result<(), Error> {
     let serialized = cert.serialize_pem();
     let parent = path.parent().expect("is a path");
     fs::create_dir_all(parent)?;
-    let f = File::create(path)?;
+    let tmp_path = path.with_extension("pem.tmp");
+    let _ = fs::remove_file(&tmp_path);
+    let f = File::create(&tmp_path)?;
     #[cfg(unix)]
     {
         let mut perm = f.metadata()?.permissions();
@@ -67,5 +219,127 @@ pub(crate) fn generate_key_and_cert(path: &Path) -> Result<Certificate, Error> {
     /* FIXME windows permissions */
     let mut writer = BufWriter::new(f);
     writer.write_all(serialized.as_bytes())?;
-    Ok(cert)
+    writer.flush()?;
+    fs::rename(tmp_path, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    /// path of a certificate file, removed after the test
+    struct CertPath(PathBuf);
+
+    impl CertPath {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!(
+                "lan-mouse-crypto-{name}-{}.pem",
+                std::process::id()
+            ));
+            let _ = fs::remove_file(&path);
+            Self(path)
+        }
+    }
+
+    impl Drop for CertPath {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    fn chain(cert: &Certificate) -> Vec<Vec<u8>> {
+        cert.certificate.iter().map(|c| c.to_vec()).collect()
+    }
+
+    /// a certificate for `key` and a statement for it signed by `signer`
+    fn statement(key: &KeyPair, signer: &KeyPair) -> (Vec<u8>, Vec<u8>) {
+        let cert = CertificateParams::new(vec!["ignored".to_owned()])
+            .expect("params")
+            .self_signed(key)
+            .expect("cert");
+        let issuer = CertificateParams::default()
+            .self_signed(signer)
+            .expect("issuer");
+        let statement = CertificateParams::default()
+            .signed_by(key, &issuer, signer)
+            .expect("statement");
+        (cert.der().to_vec(), statement.der().to_vec())
+    }
+
+    #[test]
+    fn rotation_with_transition() {
+        let path = CertPath::new("transition");
+        let old = generate_key_and_cert(&path.0).expect("generate");
+        let new = rotate_key_and_cert(&path.0, true).expect("rotate");
+        assert_eq!(new.certificate.len(), 3);
+        assert_ne!(certificate_fingerprint(&new), certificate_fingerprint(&old));
+        assert_eq!(
+            key_transition(&chain(&new)),
+            Some(certificate_fingerprint(&old))
+        );
+        // the rotated certificate is loaded with its statement
+        let loaded = load_certificate(&path.0).expect("load");
+        assert_eq!(chain(&loaded), chain(&new));
+    }
+
+    #[test]
+    fn rotation_without_transition() {
+        let path = CertPath::new("no-transition");
+        generate_key_and_cert(&path.0).expect("generate");
+        let new = rotate_key_and_cert(&path.0, false).expect("rotate");
+        assert_eq!(new.certificate.len(), 1);
+        assert_eq!(key_transition(&chain(&new)), None);
+    }
+
+    #[test]
+    fn statement_must_be_signed_by_previous_key() {
+        let old_key = KeyPair::generate().expect("key");
+        let old = CertificateParams::new(vec!["ignored".to_owned()])
+            .expect("params")
+            .self_signed(&old_key)
+            .expect("cert")
+            .der()
+            .to_vec();
+        let new_key = KeyPair::generate().expect("key");
+        let (new, valid) = statement(&new_key, &old_key);
+        assert_eq!(
+            key_transition(&[new.clone(), valid, old.clone()]),
+            Some(generate_fingerprint(&old))
+        );
+        // signed by another key
+        let forger = KeyPair::generate().expect("key");
+        let (_, forged) = statement(&new_key, &forger);
+        assert_eq!(key_transition(&[new.clone(), forged, old.clone()]), None);
+        // issued for another key
+        let (_, other) = statement(&KeyPair::generate().expect("key"), &old_key);
+        assert_eq!(key_transition(&[new.clone(), other, old.clone()]), None);
+        // incomplete chains
+        assert_eq!(key_transition(&[new.clone(), old.clone()]), None);
+        assert_eq!(key_transition(&[new]), None);
+        assert_eq!(key_transition(&[]), None);
+    }
+
+    #[test]
+    fn fingerprint_format() {
+        let fingerprint = generate_fingerprint(b"lan-mouse");
+        assert_eq!(fingerprint.len(), 32 * 3 - 1);
+        assert!(
+            fingerprint
+                .split(':')
+                .all(|b| b.len() == 2 && b.chars().all(|c| c.is_ascii_hexdigit()))
+        );
+        assert_eq!(fingerprint, fingerprint.to_lowercase());
+    }
+
+    #[test]
+    fn pairing_code_depends_on_all_inputs() {
+        let code = pairing_code("a", "b", 1, 2);
+        assert_eq!(code.len(), 6);
+        assert_eq!(code, pairing_code("a", "b", 1, 2));
+        assert_ne!(code, pairing_code("b", "a", 1, 2));
+        assert_ne!(code, pairing_code("a", "b", 2, 1));
+    }
 }
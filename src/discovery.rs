@@ -0,0 +1,332 @@
+use std::{
+    collections::HashMap,
+    io,
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4},
+    time::Duration,
+};
+
+use hickory_proto::{
+    op::{Message, MessageType, Query},
+    rr::{
+        Name, RData, Record, RecordType,
+        rdata::{PTR, SRV, TXT},
+    },
+};
+use local_channel::mpsc::{Receiver, Sender, channel};
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::{
+    net::UdpSocket,
+    task::{JoinHandle, spawn_local},
+};
+use tokio_util::sync::CancellationToken;
+
+pub(crate) const MDNS_ADDR: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+pub(crate) const MDNS_PORT: u16 = 5353;
+const SERVICE: &str = "_lan-mouse._udp.local.";
+/// time to live of announced records
+const TTL: u32 = 120;
+/// interval in which other instances are queried and this device is announced
+const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// advertises this device as `_lan-mouse._udp` via mDNS / DNS-SD
+/// and browses for other instances on the local network
+pub(crate) struct Discovery {
+    cancellation_token: CancellationToken,
+    task: Option<JoinHandle<()>>,
+    request_tx: Sender<DiscoveryRequest>,
+    event_rx: Receiver<DiscoveryEvent>,
+}
+
+enum DiscoveryRequest {
+    ChangePort(u16),
+}
+
+pub(crate) enum DiscoveryEvent {
+    /// an instance was found or its address changed
+    PeerDiscovered {
+        /// device name of the instance
+        name: String,
+        /// certificate fingerprint of the instance
+        fingerprint: String,
+        /// address the instance is listening on
+        addr: SocketAddr,
+    },
+}
+
+struct DiscoveryTask {
+    socket: UdpSocket,
+    service: Name,
+    name: String,
+    port: u16,
+    fingerprint: String,
+    peers: HashMap<String, SocketAddr>,
+    request_rx: Receiver<DiscoveryRequest>,
+    event_tx: Sender<DiscoveryEvent>,
+}
+
+impl Discovery {
+    pub(crate) fn new(port: u16, fingerprint: String) -> io::Result<Self> {
+        let socket = mdns_socket()?;
+        let name = hostname::get()
+            .map(|h| h.to_string_lossy().into_owned())
+            .unwrap_or_else(|_| "lan-mouse".to_owned());
+        let (request_tx, request_rx) = channel();
+        let (event_tx, event_rx) = channel();
+        let cancellation_token = CancellationToken::new();
+        let discovery_task = DiscoveryTask {
+            socket,
+            service: Name::from_ascii(SERVICE).expect("service name"),
+            name,
+            port,
+            fingerprint,
+            peers: Default::default(),
+            request_rx,
+            event_tx,
+        };
+        let task = Some(spawn_local(discovery_task.run(cancellation_token.clone())));
+        Ok(Self {
+            cancellation_token,
+            task,
+            request_tx,
+            event_rx,
+        })
+    }
+
+    /// advertise a new listen port
+    pub(crate) fn change_port(&self, port: u16) {
+        self.request_tx
+            .send(DiscoveryRequest::ChangePort(port))
+            .expect("channel closed");
+    }
+
+    pub(crate) async fn event(&mut self) -> DiscoveryEvent {
+        self.event_rx.recv().await.expect("channel closed")
+    }
+
+    pub(crate) async fn terminate(&mut self) {
+        self.cancellation_token.cancel();
+        self.task.take().expect("task").await.expect("join error");
+    }
+}
+
+/// multicast socket shared with other mDNS responders on this host
+fn mdns_socket() -> io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_address(true)?;
+    #[cfg(unix)]
+    socket.set_reuse_port(true)?;
+    socket.bind(&SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, MDNS_PORT).into())?;
+    socket.join_multicast_v4(&MDNS_ADDR, &Ipv4Addr::UNSPECIFIED)?;
+    // required to discover other instances on the same host
+    socket.set_multicast_loop_v4(true)?;
+    socket.set_nonblocking(true)?;
+    UdpSocket::from_std(socket.into())
+}
+
+impl DiscoveryTask {
+    async fn run(mut self, cancellation_token: CancellationToken) {
+        tokio::select! {
+            _ = self.do_discovery() => {},
+            _ = cancellation_token.cancelled() => {},
+        }
+    }
+
+    async fn do_discovery(&mut self) {
+        let mut interval = tokio::time::interval(ANNOUNCE_INTERVAL);
+        let mut buf = [0u8; 9000];
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    self.send(self.query()).await;
+                    self.send(self.announcement()).await;
+                }
+                r = self.socket.recv_from(&mut buf) => match r {
+                    Ok((len, src)) => self.handle_packet(&buf[..len], src).await,
+                    Err(e) => log::warn!("mdns: {e}"),
+                },
+                request = self.request_rx.recv() => match request {
+                    Some(DiscoveryRequest::ChangePort(port)) => {
+                        self.port = port;
+                        self.send(self.announcement()).await;
+                    }
+                    None => break,
+                },
+            }
+        }
+    }
+
+    async fn handle_packet(&mut self, buf: &[u8], src: SocketAddr) {
+        let Ok(msg) = Message::from_vec(buf) else {
+            return;
+        };
+        match msg.message_type() {
+            MessageType::Query => {
+                let asked = msg.queries().iter().any(|q| {
+                    q.name() == &self.service
+                        && matches!(q.query_type(), RecordType::PTR | RecordType::ANY)
+                });
+                if asked {
+                    self.send(self.announcement()).await;
+                }
+            }
+            MessageType::Response => self.handle_response(&msg, src),
+        }
+    }
+
+    fn handle_response(&mut self, msg: &Message, src: SocketAddr) {
+        let records = msg.answers().iter().chain(msg.additionals());
+        let instances = records
+            .clone()
+            .filter(|r| r.name() == &self.service)
+            .filter_map(|r| match r.data() {
+                RData::PTR(PTR(instance)) => Some(instance),
+                _ => None,
+            });
+        for instance in instances {
+            let port = records.clone().find_map(|r| match r.data() {
+                RData::SRV(srv) if r.name() == instance => Some(srv.port()),
+                _ => None,
+            });
+            let txt = records.clone().find_map(|r| match r.data() {
+                RData::TXT(txt) if r.name() == instance => Some(txt_entries(txt)),
+                _ => None,
+            });
+            let (Some(port), Some(txt)) = (port, txt) else {
+                continue;
+            };
+            let (Some(fingerprint), Some(name)) = (txt.get("fp"), txt.get("name")) else {
+                continue;
+            };
+            if *fingerprint == self.fingerprint {
+                continue;
+            }
+            let addr = SocketAddr::new(src.ip(), port);
+            if self.peers.insert(fingerprint.clone(), addr) == Some(addr) {
+                continue;
+            }
+            log::info!("discovered {name} @ {addr}");
+            self.event_tx
+                .send(DiscoveryEvent::PeerDiscovered {
+                    name: name.clone(),
+                    fingerprint: fingerprint.clone(),
+                    addr,
+                })
+                .expect("channel closed");
+        }
+    }
+
+    fn query(&self) -> Message {
+        let mut msg = Message::new();
+        msg.set_message_type(MessageType::Query);
+        msg.add_query(Query::query(self.service.clone(), RecordType::PTR));
+        msg
+    }
+
+    /// PTR, SRV and TXT records of this instance.
+    /// No address records are announced, peers use the source address instead.
+    fn announcement(&self) -> Message {
+        let label = &self.name.as_bytes()[..self.name.len().min(63)];
+        let instance = Name::from_labels([label])
+            .and_then(|n| n.append_name(&self.service))
+            .expect("instance name");
+        let target = Name::from_labels([label])
+            .and_then(|n| n.append_label("local"))
+            .expect("host name");
+        let txt = TXT::new(vec![
+            format!("fp={}", self.fingerprint),
+            format!("name={}", self.name),
+        ]);
+        let mut msg = Message::new();
+        msg.set_message_type(MessageType::Response)
+            .set_authoritative(true);
+        msg.add_answer(Record::from_rdata(
+            self.service.clone(),
+            TTL,
+            RData::PTR(PTR(instance.clone())),
+        ));
+        msg.add_additional(Record::from_rdata(
+            instance.clone(),
+            TTL,
+            RData::SRV(SRV::new(0, 0, self.port, target)),
+        ));
+        msg.add_additional(Record::from_rdata(instance, TTL, RData::TXT(txt)));
+        msg
+    }
+
+    async fn send(&self, msg: Message) {
+        let buf = match msg.to_vec() {
+            Ok(buf) => buf,
+            Err(e) => return log::warn!("mdns: failed to encode message: {e}"),
+        };
+        if let Err(e) = self.socket.send_to(&buf, (MDNS_ADDR, MDNS_PORT)).await {
+            log::warn!("mdns: {e}");
+        }
+    }
+}
+
+fn txt_entries(txt: &TXT) -> HashMap<String, String> {
+    txt.txt_data()
+        .iter()
+        .filter_map(|entry| {
+            let entry = String::from_utf8_lossy(entry);
+            let (key, value) = entry.split_once('=')?;
+            Some((key.to_owned(), value.to_owned()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::task::LocalSet;
+
+    use super::*;
+
+    /// the next peer discovered by `discovery` with the given fingerprint
+    async fn discovered(discovery: &mut Discovery, fingerprint: &str) -> SocketAddr {
+        loop {
+            let DiscoveryEvent::PeerDiscovered {
+                fingerprint: fp,
+                addr,
+                ..
+            } = discovery.event().await;
+            if fp == fingerprint {
+                return addr;
+            }
+        }
+    }
+
+    /// two instances on the same host find each other
+    /// through the multicast loopback
+    #[tokio::test]
+    async fn instances_discover_each_other() {
+        LocalSet::new()
+            .run_until(async {
+                let (Ok(mut a), Ok(mut b)) = (
+                    Discovery::new(4241, "fp-a".to_owned()),
+                    Discovery::new(4242, "fp-b".to_owned()),
+                ) else {
+                    return eprintln!("multicast not available, skipping");
+                };
+                let timeout = Duration::from_secs(5);
+                let found_by_a = tokio::time::timeout(timeout, discovered(&mut a, "fp-b"))
+                    .await
+                    .expect("a did not discover b");
+                let found_by_b = tokio::time::timeout(timeout, discovered(&mut b, "fp-a"))
+                    .await
+                    .expect("b did not discover a");
+                assert_eq!(found_by_a.port(), 4242);
+                assert_eq!(found_by_b.port(), 4241);
+
+                // a new port is announced right away
+                a.change_port(4243);
+                let found_by_b = tokio::time::timeout(timeout, discovered(&mut b, "fp-a"))
+                    .await
+                    .expect("b did not notice the port change");
+                assert_eq!(found_by_b.port(), 4243);
+
+                a.terminate().await;
+                b.terminate().await;
+            })
+            .await;
+    }
+}
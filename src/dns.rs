@@ -1,13 +1,35 @@
-use std::{collections::HashMap, net::IpAddr};
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    time::Duration,
+};
 
+use hickory_proto::{
+    op::{Message, MessageType, Query},
+    rr::{Name, RData, RecordType},
+};
 use local_channel::mpsc::{Receiver, Sender, channel};
-use tokio::task::{JoinHandle, spawn_local};
+use tokio::{
+    net::UdpSocket,
+    task::{JoinHandle, spawn_local},
+    time::Instant,
+};
 
 use hickory_resolver::{ResolveError, TokioResolver};
 use tokio_util::sync::CancellationToken;
 
 use lan_mouse_ipc::ClientHandle;
 
+use crate::discovery::{MDNS_ADDR, MDNS_PORT};
+
+/// hostnames are resolved again when their records expire,
+/// but not more often than this
+const MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+/// and at least in this interval
+const MAX_REFRESH_INTERVAL: Duration = Duration::from_secs(600);
+/// time to wait for the answer to an mDNS query
+const MDNS_TIMEOUT: Duration = Duration::from_secs(1);
+
 pub(crate) struct DnsResolver {
     cancellation_token: CancellationToken,
     task: Option<JoinHandle<()>>,
@@ -15,9 +37,16 @@ pub(crate) struct DnsResolver {
     event_rx: Receiver<DnsEvent>,
 }
 
-struct DnsRequest {
-    handle: ClientHandle,
-    hostname: String,
+enum DnsRequest {
+    /// resolve the hostname of a client and keep it up to date
+    Resolve {
+        handle: ClientHandle,
+        hostname: String,
+    },
+    /// stop resolving the hostname of a client
+    Forget(ClientHandle),
+    /// resolve all hostnames again, e.g. after the network changed
+    Refresh,
 }
 
 pub(crate) enum DnsEvent {
@@ -30,6 +59,7 @@ struct DnsTask {
     request_rx: Receiver<DnsRequest>,
     event_tx: Sender<DnsEvent>,
     cancellation_token: CancellationToken,
+    hostnames: HashMap<ClientHandle, String>,
     active_tasks: HashMap<ClientHandle, JoinHandle<()>>,
 }
 
@@ -41,6 +71,7 @@ impl DnsResolver {
         let cancellation_token = CancellationToken::new();
         let dns_task = DnsTask {
             active_tasks: Default::default(),
+            hostnames: Default::default(),
             resolver,
             request_rx,
             event_tx,
@@ -55,11 +86,24 @@ impl DnsResolver {
         })
     }
 
+    /// resolve `hostname` now and again whenever its records expire
     pub(crate) fn resolve(&self, handle: ClientHandle, hostname: String) {
-        let request = DnsRequest { handle, hostname };
+        let request = DnsRequest::Resolve { handle, hostname };
         self.request_tx.send(request).expect("channel closed");
     }
 
+    pub(crate) fn forget(&self, handle: ClientHandle) {
+        let request = DnsRequest::Forget(handle);
+        self.request_tx.send(request).expect("channel closed");
+    }
+
+    /// resolve all hostnames again
+    pub(crate) fn refresh(&self) {
+        self.request_tx
+            .send(DnsRequest::Refresh)
+            .expect("channel closed");
+    }
+
     pub(crate) async fn event(&mut self) -> DnsEvent {
         self.event_rx.recv().await.expect("channel closed")
     }
@@ -81,37 +125,153 @@ impl DnsTask {
 
     async fn do_dns(&mut self) {
         while let Some(dns_request) = self.request_rx.recv().await {
-            let DnsRequest { handle, hostname } = dns_request;
+            match dns_request {
+                DnsRequest::Resolve { handle, hostname } => {
+                    self.event_tx
+                        .send(DnsEvent::Resolving(handle))
+                        .expect("channel closed");
+                    self.hostnames.insert(handle, hostname.clone());
+                    self.spawn(handle, hostname, true);
+                }
+                DnsRequest::Forget(handle) => {
+                    self.hostnames.remove(&handle);
+                    self.abort(handle);
+                }
+                DnsRequest::Refresh => {
+                    for (handle, hostname) in self.hostnames.clone() {
+                        self.spawn(handle, hostname, false);
+                    }
+                }
+            }
+        }
+    }
+
+    /// abort previous dns task
+    fn abort(&mut self, handle: ClientHandle) {
+        if let Some(task) = self.active_tasks.remove(&handle) {
+            if !task.is_finished() {
+                task.abort();
+            }
+        }
+    }
+
+    /// spawn task for dns request, replacing the previous one.
+    /// Failures are only reported for the `initial` resolution,
+    /// otherwise the previous addresses are kept.
+    fn spawn(&mut self, handle: ClientHandle, hostname: String, initial: bool) {
+        self.abort(handle);
+        let event_tx = self.event_tx.clone();
+        let resolver = self.resolver.clone();
+        let cancellation_token = self.cancellation_token.clone();
+
+        let task = spawn_local(async move {
+            tokio::select! {
+                _ = keep_resolving(resolver, handle, hostname, initial, event_tx) => {},
+                _ = cancellation_token.cancelled() => {},
+            }
+        });
+        self.active_tasks.insert(handle, task);
+    }
+}
 
-            /* abort previous dns task */
-            let previous_task = self.active_tasks.remove(&handle);
-            if let Some(task) = previous_task {
-                if !task.is_finished() {
-                    task.abort();
+async fn keep_resolving(
+    resolver: TokioResolver,
+    handle: ClientHandle,
+    hostname: String,
+    mut initial: bool,
+    event_tx: Sender<DnsEvent>,
+) {
+    let mut previous = None;
+    loop {
+        let refresh = match lookup(&resolver, &hostname).await {
+            Ok((mut ips, valid_until)) => {
+                ips.sort();
+                if initial || previous.as_ref() != Some(&ips) {
+                    log::debug!("{hostname}: {ips:?}");
+                    let event = DnsEvent::Resolved(handle, hostname.clone(), Ok(ips.clone()));
+                    event_tx.send(event).expect("channel closed");
                 }
+                previous = Some(ips);
+                valid_until.saturating_duration_since(Instant::now())
+            }
+            Err(e) if initial => {
+                let event = DnsEvent::Resolved(handle, hostname.clone(), Err(e));
+                event_tx.send(event).expect("channel closed");
+                MIN_REFRESH_INTERVAL
+            }
+            Err(e) => {
+                log::debug!("could not resolve {hostname} again: {e}");
+                MIN_REFRESH_INTERVAL
             }
+        };
+        initial = false;
+        tokio::time::sleep(refresh.clamp(MIN_REFRESH_INTERVAL, MAX_REFRESH_INTERVAL)).await;
+    }
+}
+
+/// addresses of `hostname` and until when they are valid.
+/// Hostnames in the `.local` domain are resolved via mDNS.
+async fn lookup(
+    resolver: &TokioResolver,
+    hostname: &str,
+) -> Result<(Vec<IpAddr>, Instant), ResolveError> {
+    let name = Name::from_utf8(hostname)?;
+    if Name::from_ascii("local.")?.zone_of(&name) {
+        return mdns_lookup(name).await;
+    }
+    let lookup = resolver.lookup_ip(hostname).await?;
+    let valid_until = Instant::from_std(lookup.valid_until());
+    Ok((lookup.iter().collect(), valid_until))
+}
 
-            self.event_tx
-                .send(DnsEvent::Resolving(handle))
-                .expect("channel closed");
-
-            /* spawn task for dns request */
-            let event_tx = self.event_tx.clone();
-            let resolver = self.resolver.clone();
-            let cancellation_token = self.cancellation_token.clone();
-
-            let task = tokio::task::spawn_local(async move {
-                tokio::select! {
-                    ips = resolver.lookup_ip(&hostname) => {
-                       let ips = ips.map(|ips| ips.iter().collect::<Vec<_>>());
-                       event_tx
-                           .send(DnsEvent::Resolved(handle, hostname, ips))
-                           .expect("channel closed");
+/// one-shot mDNS query (RFC 6762, section 5.1):
+/// the query is sent from an ephemeral port,
+/// so the first responder answers with a unicast response
+async fn mdns_lookup(mut name: Name) -> Result<(Vec<IpAddr>, Instant), ResolveError> {
+    name.set_fqdn(true);
+    let id = rand::random();
+    let mut query = Message::new();
+    query
+        .set_id(id)
+        .set_message_type(MessageType::Query)
+        .add_query(Query::query(name.clone(), RecordType::A))
+        .add_query(Query::query(name.clone(), RecordType::AAAA));
+    let socket = UdpSocket::bind(SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), 0)).await?;
+    socket
+        .send_to(&query.to_vec()?, (MDNS_ADDR, MDNS_PORT))
+        .await?;
+    let mut buf = [0u8; 9000];
+    let response = async {
+        loop {
+            let len = socket.recv(&mut buf).await?;
+            let Ok(msg) = Message::from_vec(&buf[..len]) else {
+                continue;
+            };
+            if msg.id() != id || msg.message_type() != MessageType::Response {
+                continue;
+            }
+            let now = Instant::now();
+            let addresses: Vec<(IpAddr, Instant)> = msg
+                .answers()
+                .iter()
+                .chain(msg.additionals())
+                .filter(|r| r.name() == &name)
+                .filter_map(|r| {
+                    let valid_until = now + Duration::from_secs(r.ttl().into());
+                    match r.data() {
+                        RData::A(a) => Some((IpAddr::V4(a.0), valid_until)),
+                        RData::AAAA(aaaa) => Some((IpAddr::V6(aaaa.0), valid_until)),
+                        _ => None,
                     }
-                    _ = cancellation_token.cancelled() => {},
-                }
-            });
-            self.active_tasks.insert(handle, task);
+                })
+                .collect();
+            if let Some(valid_until) = addresses.iter().map(|(_, v)| *v).min() {
+                let ips = addresses.into_iter().map(|(ip, _)| ip).collect();
+                return Ok::<_, ResolveError>((ips, valid_until));
+            }
         }
-    }
+    };
+    tokio::time::timeout(MDNS_TIMEOUT, response)
+        .await
+        .map_err(|_| ResolveError::from(format!("no mDNS response for {name}")))?
 }
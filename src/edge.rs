@@ -0,0 +1,312 @@
+use std::time::{Duration, Instant};
+
+use input_capture::{CaptureHandle, EdgePosition, Position};
+use input_event::{Event, KeyboardEvent, PointerEvent};
+use lan_mouse_ipc::EdgePolicy;
+
+use crate::keymap;
+
+/// maximum time between two hits of an edge to count as a double tap
+const DOUBLE_TAP_INTERVAL: Duration = Duration::from_millis(500);
+
+/// distance in pixels the pointer may move back from the edge
+/// before a pending crossing is cancelled
+const RETREAT_TOLERANCE: f64 = 4.0;
+
+/// outcome of evaluating the [`EdgePolicy`] of a crossing
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Verdict {
+    /// all conditions are met, the client can be entered
+    Enter,
+    /// waiting for the remaining conditions
+    Wait,
+    /// the crossing was rejected, capture must be released
+    Reject,
+}
+
+/// crossing of an edge that does not yet satisfy its policy
+struct Crossing {
+    handle: CaptureHandle,
+    pos: Position,
+    policy: EdgePolicy,
+    start: Instant,
+    /// distance pushed through the edge
+    pushed: f64,
+    /// a single motion event exceeded the push velocity
+    fast: bool,
+    /// the required modifier is held
+    modifier_held: bool,
+}
+
+/// Decides whether crossing the edge of a client enters the client.
+///
+/// After a barrier is crossed, input stays captured but is not forwarded
+/// until the conditions of the [`EdgePolicy`] of the client are met.
+#[derive(Default)]
+pub(crate) struct EdgeGuard {
+    /// crossing waiting for its policy to be satisfied
+    pending: Option<Crossing>,
+    /// previous hit of an edge, for double tap detection
+    last_tap: Option<(CaptureHandle, Instant)>,
+}
+
+impl EdgeGuard {
+    /// the edge of the given client was crossed at `edge` (if known).
+    /// `modifier_held` tells whether the required modifier was already
+    /// held before the crossing.
+    pub(crate) fn begin(
+        &mut self,
+        handle: CaptureHandle,
+        pos: Position,
+        policy: EdgePolicy,
+        edge: Option<EdgePosition>,
+        modifier_held: bool,
+    ) -> Verdict {
+        self.pending = None;
+        if edge.is_some_and(|e| e.corner_distance() < policy.corner_dead_zone) {
+            log::debug!("edge crossing rejected: corner dead zone");
+            return Verdict::Reject;
+        }
+        if policy.double_tap {
+            match self.last_tap.take() {
+                Some((h, t)) if h == handle && t.elapsed() <= DOUBLE_TAP_INTERVAL => {}
+                _ => {
+                    log::debug!("edge crossing rejected: waiting for double tap");
+                    self.last_tap = Some((handle, Instant::now()));
+                    return Verdict::Reject;
+                }
+            }
+        }
+        let crossing = Crossing {
+            handle,
+            pos,
+            policy,
+            start: Instant::now(),
+            pushed: 0.,
+            fast: false,
+            modifier_held,
+        };
+        let verdict = crossing.verdict();
+        if verdict == Verdict::Wait {
+            self.pending = Some(crossing);
+        }
+        verdict
+    }
+
+    /// the client whose crossing is pending, if any
+    pub(crate) fn pending(&self) -> Option<CaptureHandle> {
+        self.pending.as_ref().map(|c| c.handle)
+    }
+
+    /// evaluate an event captured while the crossing is pending
+    pub(crate) fn update(&mut self, event: Event) -> Verdict {
+        let Some(crossing) = self.pending.as_mut() else {
+            return Verdict::Enter;
+        };
+        let verdict = crossing.update(event);
+        if verdict != Verdict::Wait {
+            self.pending = None;
+        }
+        verdict
+    }
+
+    /// cancel the pending crossing
+    pub(crate) fn clear(&mut self) {
+        self.pending = None;
+    }
+}
+
+impl Crossing {
+    fn update(&mut self, event: Event) -> Verdict {
+        match event {
+            Event::Pointer(PointerEvent::Motion { dx, dy, .. }) => {
+                let push = match self.pos {
+                    Position::Left => -dx,
+                    Position::Right => dx,
+                    Position::Top => -dy,
+                    Position::Bottom => dy,
+                };
+                self.pushed += push;
+                if self.pushed < -RETREAT_TOLERANCE {
+                    log::debug!("edge crossing rejected: pointer moved away from the edge");
+                    return Verdict::Reject;
+                }
+                self.fast |= push >= self.policy.push_velocity;
+            }
+            // clicking or typing means the edge was hit by accident
+            Event::Pointer(PointerEvent::Button { state: 1, .. }) => return Verdict::Reject,
+            Event::Keyboard(KeyboardEvent::Key { key, state, .. }) => match self.policy.modifier {
+                Some(modifier) if keymap::normalize(modifier) == keymap::normalize(key) => {
+                    self.modifier_held = state == 1
+                }
+                _ if state == 1 => return Verdict::Reject,
+                _ => {}
+            },
+            Event::Keyboard(KeyboardEvent::Modifiers { depressed, .. }) => {
+                let mask = self.policy.modifier.map(keymap::modifier_mask);
+                if let Some(mask) = mask.filter(|&m| m != 0) {
+                    self.modifier_held = depressed & mask != 0;
+                }
+            }
+            _ => {}
+        }
+        self.verdict()
+    }
+
+    fn verdict(&self) -> Verdict {
+        let dwelled = self.start.elapsed() >= Duration::from_millis(self.policy.dwell_time);
+        let pushed = self.pushed >= self.policy.push_distance;
+        let fast = self.fast || self.policy.push_velocity <= 0.;
+        let modifier = self.modifier_held || self.policy.modifier.is_none();
+        match dwelled && pushed && fast && modifier {
+            true => Verdict::Enter,
+            false => Verdict::Wait,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use input_event::scancode::Linux;
+
+    use super::*;
+
+    fn key(key: Linux, state: u8) -> Event {
+        Event::Keyboard(KeyboardEvent::Key {
+            time: 0,
+            key: key as u32,
+            state,
+        })
+    }
+
+    fn motion(dx: f64, dy: f64) -> Event {
+        Event::Pointer(PointerEvent::Motion { time: 0, dx, dy })
+    }
+
+    fn modifier_policy() -> EdgePolicy {
+        EdgePolicy {
+            modifier: Some(Linux::KeyLeftCtrl as u32),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn no_policy_enters_immediately() {
+        let mut guard = EdgeGuard::default();
+        let verdict = guard.begin(0, Position::Right, EdgePolicy::default(), None, false);
+        assert_eq!(verdict, Verdict::Enter);
+        assert_eq!(guard.pending(), None);
+    }
+
+    #[test]
+    fn modifier_held_before_crossing() {
+        let mut guard = EdgeGuard::default();
+        let verdict = guard.begin(0, Position::Right, modifier_policy(), None, true);
+        assert_eq!(verdict, Verdict::Enter);
+    }
+
+    #[test]
+    fn right_modifier_satisfies_left() {
+        let mut guard = EdgeGuard::default();
+        let verdict = guard.begin(0, Position::Right, modifier_policy(), None, false);
+        assert_eq!(verdict, Verdict::Wait);
+        assert_eq!(guard.pending(), Some(0));
+        assert_eq!(guard.update(key(Linux::KeyRightCtrl, 1)), Verdict::Enter);
+    }
+
+    #[test]
+    fn modifier_mask_satisfies_modifier() {
+        let mut guard = EdgeGuard::default();
+        guard.begin(0, Position::Right, modifier_policy(), None, false);
+        let modifiers = Event::Keyboard(KeyboardEvent::Modifiers {
+            depressed: keymap::modifier_mask(Linux::KeyLeftCtrl as u32),
+            latched: 0,
+            locked: 0,
+            group: 0,
+        });
+        assert_eq!(guard.update(modifiers), Verdict::Enter);
+    }
+
+    #[test]
+    fn typing_rejects_crossing() {
+        let mut guard = EdgeGuard::default();
+        guard.begin(0, Position::Right, modifier_policy(), None, false);
+        assert_eq!(guard.update(key(Linux::KeyA, 1)), Verdict::Reject);
+        assert_eq!(guard.pending(), None);
+    }
+
+    #[test]
+    fn push_distance() {
+        let policy = EdgePolicy {
+            push_distance: 10.,
+            ..Default::default()
+        };
+        let mut guard = EdgeGuard::default();
+        assert_eq!(
+            guard.begin(0, Position::Left, policy, None, false),
+            Verdict::Wait
+        );
+        assert_eq!(guard.update(motion(-6., 0.)), Verdict::Wait);
+        assert_eq!(guard.update(motion(-6., 0.)), Verdict::Enter);
+    }
+
+    #[test]
+    fn retreat_rejects_crossing() {
+        let policy = EdgePolicy {
+            push_distance: 10.,
+            ..Default::default()
+        };
+        let mut guard = EdgeGuard::default();
+        guard.begin(0, Position::Top, policy, None, false);
+        assert_eq!(
+            guard.update(motion(0., RETREAT_TOLERANCE + 1.)),
+            Verdict::Reject
+        );
+    }
+
+    #[test]
+    fn corner_dead_zone() {
+        let policy = EdgePolicy {
+            corner_dead_zone: 50.,
+            ..Default::default()
+        };
+        let corner = EdgePosition {
+            offset: 10.,
+            length: 1000.,
+        };
+        let center = EdgePosition {
+            offset: 500.,
+            length: 1000.,
+        };
+        let mut guard = EdgeGuard::default();
+        assert_eq!(
+            guard.begin(0, Position::Right, policy, Some(corner), false),
+            Verdict::Reject
+        );
+        assert_eq!(
+            guard.begin(0, Position::Right, policy, Some(center), false),
+            Verdict::Enter
+        );
+    }
+
+    #[test]
+    fn double_tap() {
+        let policy = EdgePolicy {
+            double_tap: true,
+            ..Default::default()
+        };
+        let mut guard = EdgeGuard::default();
+        assert_eq!(
+            guard.begin(0, Position::Right, policy, None, false),
+            Verdict::Reject
+        );
+        assert_eq!(
+            guard.begin(1, Position::Left, policy, None, false),
+            Verdict::Reject
+        );
+        assert_eq!(
+            guard.begin(1, Position::Left, policy, None, false),
+            Verdict::Enter
+        );
+    }
+}
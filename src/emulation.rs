@@ -1,13 +1,19 @@
-use crate::listen::{LanMouseListener, ListenEvent, ListenerCreationError};
+use crate::{
+    config::InputPermissions,
+    crypto,
+    listen::{LanMouseListener, ListenEvent, ListenerCreationError},
+    permissions::{DevicePermissions, InputFilter},
+};
 use futures::StreamExt;
 use input_emulation::{EmulationHandle, InputEmulation, InputEmulationError};
 use input_event::Event;
+use lan_mouse_ipc::InboundLimit;
 use lan_mouse_proto::{Position, ProtoEvent};
 use local_channel::mpsc::{Receiver, Sender, channel};
 use std::{
     cell::Cell,
-    collections::HashMap,
-    net::SocketAddr,
+    collections::{HashMap, HashSet},
+    net::{IpAddr, SocketAddr},
     rc::Rc,
     time::{Duration, Instant},
 };
@@ -28,6 +34,11 @@ pub(crate) enum EmulationEvent {
         addr: SocketAddr,
         fingerprint: String,
     },
+    /// an unauthorized device connected for pairing
+    PairingConnected {
+        addr: SocketAddr,
+        fingerprint: String,
+    },
     ConnectionAttempt {
         fingerprint: String,
     },
@@ -44,6 +55,20 @@ pub(crate) enum EmulationEvent {
     Disconnected {
         addr: SocketAddr,
     },
+    /// an authorized device rotated its key and is now authorized by its new fingerprint
+    KeyTransition {
+        previous: String,
+        fingerprint: String,
+    },
+    /// connection closed because the device is no longer authorized
+    Revoked {
+        addr: SocketAddr,
+    },
+    /// a device exceeded one of the inbound limits
+    LimitExceeded {
+        ip: IpAddr,
+        limit: InboundLimit,
+    },
     /// the port of the listener has changed
     PortChanged(Result<u16, ListenerCreationError>),
     /// emulation was disabled
@@ -52,12 +77,30 @@ pub(crate) enum EmulationEvent {
     EmulationEnabled,
     /// capture should be released
     ReleaseNotify,
+    /// a connected device locked (true) or unlocked (false) its screen
+    ScreenLock {
+        locked: bool,
+        fingerprint: Option<String>,
+        /// the input of the device is restricted
+        restricted: bool,
+    },
+    /// pairing with a device finished, the code must be compared by the user
+    PairingCode {
+        addr: SocketAddr,
+        fingerprint: String,
+        code: String,
+    },
 }
 
 enum EmulationRequest {
     Reenable,
+    Disable,
     Release(SocketAddr),
     ChangePort(u16),
+    ScreenLock(bool),
+    AllowPairing(Duration),
+    Authorize(String),
+    Revoke(String),
     Terminate,
 }
 
@@ -65,6 +108,7 @@ impl Emulation {
     pub(crate) fn new(
         backend: Option<input_emulation::Backend>,
         listener: LanMouseListener,
+        permissions: DevicePermissions,
     ) -> Self {
         let emulation_proxy = EmulationProxy::new(backend);
         let (request_tx, request_rx) = channel();
@@ -72,6 +116,7 @@ impl Emulation {
         let emulation_task = ListenTask {
             listener,
             emulation_proxy,
+            permissions,
             request_rx,
             event_tx,
         };
@@ -95,12 +140,47 @@ impl Emulation {
             .expect("channel closed");
     }
 
+    pub(crate) fn disable(&self) {
+        self.request_tx
+            .send(EmulationRequest::Disable)
+            .expect("channel closed");
+    }
+
     pub(crate) fn request_port_change(&self, port: u16) {
         self.request_tx
             .send(EmulationRequest::ChangePort(port))
             .expect("channel closed")
     }
 
+    /// notify all connected devices about a change of the screen lock state
+    pub(crate) fn send_screen_lock(&self, locked: bool) {
+        self.request_tx
+            .send(EmulationRequest::ScreenLock(locked))
+            .expect("channel closed")
+    }
+
+    /// accept pairing requests from unauthorized devices for the given duration
+    pub(crate) fn allow_pairing(&self, duration: Duration) {
+        self.request_tx
+            .send(EmulationRequest::AllowPairing(duration))
+            .expect("channel closed")
+    }
+
+    /// accept input from the device with the given fingerprint,
+    /// including its connections accepted for pairing
+    pub(crate) fn authorize(&self, fingerprint: String) {
+        self.request_tx
+            .send(EmulationRequest::Authorize(fingerprint))
+            .expect("channel closed")
+    }
+
+    /// close all connections of the device with the given fingerprint
+    pub(crate) fn revoke(&self, fingerprint: String) {
+        self.request_tx
+            .send(EmulationRequest::Revoke(fingerprint))
+            .expect("channel closed")
+    }
+
     pub(crate) async fn event(&mut self) -> EmulationEvent {
         self.event_rx.recv().await.expect("channel closed")
     }
@@ -120,6 +200,8 @@ impl Emulation {
 struct ListenTask {
     listener: LanMouseListener,
     emulation_proxy: EmulationProxy,
+    /// input permissions by certificate fingerprint
+    permissions: DevicePermissions,
     request_rx: Receiver<EmulationRequest>,
     event_tx: Sender<EmulationEvent>,
 }
@@ -127,35 +209,87 @@ struct ListenTask {
 impl ListenTask {
     async fn run(mut self) {
         let mut interval = tokio::time::interval(Duration::from_secs(5));
-        let mut last_response = HashMap::new();
         let mut rejected_connections = HashMap::new();
+        // connected devices by certificate fingerprint
+        let mut peers = Peers::default();
+        // connections of unauthorized devices, only accepted for pairing
+        let mut pairing_conns: HashMap<SocketAddr, String> = HashMap::new();
+        // pairing commitment and own nonce by certificate fingerprint
+        let mut pairings = HashMap::new();
         loop {
             select! {
                 e = self.listener.next() => {match e {
                     Some(ListenEvent::Msg { event, addr }) => {
                         log::trace!("{event} <-<-<-<-<- {addr}");
-                        last_response.insert(addr, Instant::now());
+                        if let Some(fingerprint) = pairing_conns.get(&addr) {
+                            match event {
+                                ProtoEvent::PairCommit(_) | ProtoEvent::PairNonce(_) => {
+                                    self.pair(&mut pairings, addr, fingerprint, event).await
+                                }
+                                _ => log::debug!("ignoring {event} from {addr}: not authorized"),
+                            }
+                            continue;
+                        }
+                        // the device is forgotten when it stops responding,
+                        // but the connection may still be alive
+                        if !peers.contains(addr) {
+                            let Some(fingerprint) = self.listener.get_certificate_fingerprint(addr).await else {
+                                continue;
+                            };
+                            let permissions = self.permissions.get(&fingerprint);
+                            peers.insert(addr, fingerprint, &permissions);
+                        }
+                        let Some((fingerprint, peer)) = peers.responded(addr) else {
+                            continue;
+                        };
                         match event {
                             ProtoEvent::Enter(pos) => {
-                                if let Some(fingerprint) = self.listener.get_certificate_fingerprint(addr).await {
-                                    log::info!("releasing capture: {addr} entered this device");
-                                    self.event_tx.send(EmulationEvent::ReleaseNotify).expect("channel closed");
-                                    self.listener.reply(addr, ProtoEvent::Ack(0)).await;
-                                    self.event_tx.send(EmulationEvent::Entered{addr, pos: to_ipc_pos(pos), fingerprint}).expect("channel closed");
-                                }
+                                log::info!("releasing capture: {addr} entered this device");
+                                self.event_tx.send(EmulationEvent::ReleaseNotify).expect("channel closed");
+                                self.listener.reply(addr, ProtoEvent::Ack(0)).await;
+                                let fingerprint = fingerprint.to_owned();
+                                self.event_tx.send(EmulationEvent::Entered{addr, pos: to_ipc_pos(pos), fingerprint}).expect("channel closed");
                             }
                             ProtoEvent::Leave(_) => {
-                                self.emulation_proxy.remove(addr);
+                                self.emulation_proxy.remove(peer.handle);
                                 self.listener.reply(addr, ProtoEvent::Ack(0)).await;
                             }
-                            ProtoEvent::Input(event) => self.emulation_proxy.consume(event, addr),
+                            ProtoEvent::Input(event) => match peer.filter.allow(&event) {
+                                true => self.emulation_proxy.consume(event, peer.handle),
+                                false => log::debug!("ignoring {event} from {addr}: not permitted"),
+                            },
                             ProtoEvent::Ping => self.listener.reply(addr, ProtoEvent::Pong(self.emulation_proxy.emulation_active.get())).await,
+                            ProtoEvent::ScreenLock(locked) => {
+                                let restricted = !self.permissions.get(fingerprint).unrestricted();
+                                let fingerprint = Some(fingerprint.to_owned());
+                                self.event_tx.send(EmulationEvent::ScreenLock { locked, fingerprint, restricted }).expect("channel closed");
+                            }
+                            ProtoEvent::PairCommit(_) | ProtoEvent::PairNonce(_) => {
+                                self.pair(&mut pairings, addr, fingerprint, event).await
+                            }
                             _ => {}
                         }
                     }
-                    Some(ListenEvent::Accept { addr, fingerprint }) => {
+                    Some(ListenEvent::Accept { addr, fingerprint, pairing: true }) => {
+                        log::info!("{addr} ({fingerprint}) connected for pairing");
+                        pairing_conns.insert(addr, fingerprint.clone());
+                        self.event_tx.send(EmulationEvent::PairingConnected { addr, fingerprint }).expect("channel closed");
+                    }
+                    Some(ListenEvent::Accept { addr, fingerprint, pairing: false }) => {
+                        let permissions = self.permissions.get(&fingerprint);
+                        peers.insert(addr, fingerprint.clone(), &permissions);
                         self.event_tx.send(EmulationEvent::Connected { addr, fingerprint }).expect("channel closed");
                     }
+                    Some(ListenEvent::KeyTransition { previous, fingerprint }) => {
+                        self.permissions.key_transition(&previous, fingerprint.clone());
+                        self.event_tx.send(EmulationEvent::KeyTransition { previous, fingerprint }).expect("channel closed");
+                    }
+                    Some(ListenEvent::LimitExceeded { ip, limit }) => {
+                        self.event_tx.send(EmulationEvent::LimitExceeded { ip, limit }).expect("channel closed");
+                    }
+                    Some(ListenEvent::Closed { addr }) => {
+                        pairing_conns.remove(&addr);
+                    }
                     Some(ListenEvent::Rejected { fingerprint }) => {
                         if rejected_connections.insert(fingerprint.clone(), Instant::now())
                             .is_none_or(|i| i.elapsed() >= Duration::from_secs(2)) {
@@ -170,6 +304,8 @@ impl ListenTask {
                 request = self.request_rx.recv() => match request.expect("channel closed") {
                     // reenable emulation
                     EmulationRequest::Reenable => self.emulation_proxy.reenable(),
+                    // disable emulation
+                    EmulationRequest::Disable => self.emulation_proxy.disable(),
                     // notify the other end that we hit a barrier (should release capture)
                     EmulationRequest::Release(addr) => self.listener.reply(addr, ProtoEvent::Leave(0)).await,
                     EmulationRequest::ChangePort(port) => {
@@ -177,25 +313,176 @@ impl ListenTask {
                         let result = self.listener.port_changed().await;
                         self.event_tx.send(EmulationEvent::PortChanged(result)).expect("channel closed");
                     }
+                    EmulationRequest::ScreenLock(locked) => self.listener.broadcast(ProtoEvent::ScreenLock(locked)).await,
+                    EmulationRequest::AllowPairing(duration) => self.listener.allow_pairing(duration),
+                    EmulationRequest::Authorize(fingerprint) => {
+                        let paired = pairing_conns
+                            .iter()
+                            .filter(|(_, fp)| **fp == fingerprint)
+                            .map(|(&addr, _)| addr)
+                            .collect::<Vec<_>>();
+                        for addr in paired {
+                            log::info!("{addr} ({fingerprint}) is now authorized");
+                            pairing_conns.remove(&addr);
+                            let permissions = self.permissions.get(&fingerprint);
+                            peers.insert(addr, fingerprint.clone(), &permissions);
+                            let fingerprint = fingerprint.clone();
+                            self.event_tx.send(EmulationEvent::Connected { addr, fingerprint }).expect("channel closed");
+                        }
+                    }
+                    EmulationRequest::Revoke(fingerprint) => {
+                        for addr in self.listener.close_connections(&fingerprint).await {
+                            log::info!("releasing keys: {addr} ({fingerprint}) is no longer authorized");
+                            if let Some(handle) = peers.remove(addr) {
+                                self.emulation_proxy.remove(handle);
+                            }
+                            self.event_tx.send(EmulationEvent::Revoked { addr }).expect("channel closed");
+                        }
+                    }
                     EmulationRequest::Terminate => break,
                 },
                 _ = interval.tick() => {
-                    last_response.retain(|&addr,instant| {
-                        if instant.elapsed() > Duration::from_secs(1) {
-                            log::warn!("releasing keys: {addr} not responding!");
-                            self.emulation_proxy.remove(addr);
-                            self.event_tx.send(EmulationEvent::Disconnected { addr }).expect("channel closed");
-                            false
-                        } else {
-                            true
+                    for addr in peers.unresponsive(Duration::from_secs(1)) {
+                        log::warn!("{addr} not responding!");
+                        // other connections of the device may still be alive
+                        if let Some(handle) = peers.remove(addr) {
+                            log::info!("releasing keys: no connection of the device left");
+                            self.emulation_proxy.remove(handle);
                         }
-                    });
+                        self.event_tx.send(EmulationEvent::Disconnected { addr }).expect("channel closed");
+                    }
                 }
             }
         }
         self.listener.terminate().await;
         self.emulation_proxy.terminate().await;
     }
+
+    /// answer the commitment of a pairing initiator with a nonce
+    /// or finish pairing once it revealed its own nonce
+    async fn pair(
+        &self,
+        pairings: &mut HashMap<String, (u128, u128)>,
+        addr: SocketAddr,
+        fingerprint: &str,
+        event: ProtoEvent,
+    ) {
+        match event {
+            ProtoEvent::PairCommit(commitment) => {
+                let nonce = rand::random();
+                pairings.insert(fingerprint.to_owned(), (commitment, nonce));
+                self.listener
+                    .reply(addr, ProtoEvent::PairNonce(nonce))
+                    .await;
+            }
+            ProtoEvent::PairNonce(peer_nonce) => {
+                let pairing = pairings.remove(fingerprint);
+                self.finish_pairing(addr, fingerprint.to_owned(), peer_nonce, pairing);
+            }
+            _ => {}
+        }
+    }
+
+    /// verify the revealed nonce of the initiator against its commitment
+    /// and derive the pairing code
+    fn finish_pairing(
+        &self,
+        addr: SocketAddr,
+        fingerprint: String,
+        peer_nonce: u128,
+        pairing: Option<(u128, u128)>,
+    ) {
+        let Some((commitment, nonce)) = pairing else {
+            return log::warn!("{addr}: unexpected pairing nonce");
+        };
+        if crypto::pairing_commitment(peer_nonce) != commitment {
+            return log::warn!("{addr}: pairing failed, nonce does not match commitment");
+        }
+        let code =
+            crypto::pairing_code(&fingerprint, self.listener.fingerprint(), peer_nonce, nonce);
+        self.event_tx
+            .send(EmulationEvent::PairingCode {
+                addr,
+                fingerprint,
+                code,
+            })
+            .expect("channel closed");
+    }
+}
+
+/// a device connected to this device
+struct Peer {
+    /// connections of the device by address and when they last responded
+    conns: HashMap<SocketAddr, Instant>,
+    /// enforces the input permissions of the device
+    filter: InputFilter,
+    /// emulated device receiving the input of the device
+    handle: EmulationHandle,
+}
+
+/// connected devices by certificate fingerprint.
+///
+/// A device may be connected from several addresses at once,
+/// e.g. while roaming between networks, all of which share its state.
+#[derive(Default)]
+struct Peers {
+    devices: HashMap<String, Peer>,
+    /// fingerprint of the device connected from each address
+    fingerprints: HashMap<SocketAddr, String>,
+    next_handle: EmulationHandle,
+}
+
+impl Peers {
+    /// add a connection of the device with the given fingerprint
+    fn insert(&mut self, addr: SocketAddr, fingerprint: String, permissions: &InputPermissions) {
+        let next_handle = &mut self.next_handle;
+        let peer = self.devices.entry(fingerprint.clone()).or_insert_with(|| {
+            let handle = *next_handle;
+            *next_handle += 1;
+            Peer {
+                conns: Default::default(),
+                filter: InputFilter::new(permissions),
+                handle,
+            }
+        });
+        peer.conns.insert(addr, Instant::now());
+        self.fingerprints.insert(addr, fingerprint);
+    }
+
+    fn contains(&self, addr: SocketAddr) -> bool {
+        self.fingerprints.contains_key(&addr)
+    }
+
+    /// the connection from `addr` responded,
+    /// returns the fingerprint and state of its device
+    fn responded(&mut self, addr: SocketAddr) -> Option<(&str, &mut Peer)> {
+        let fingerprint = self.fingerprints.get(&addr)?;
+        let peer = self.devices.get_mut(fingerprint)?;
+        peer.conns.insert(addr, Instant::now());
+        Some((fingerprint, peer))
+    }
+
+    /// remove the connection from `addr`. The device is forgotten once it has
+    /// no connections left, in which case its emulation handle is returned.
+    fn remove(&mut self, addr: SocketAddr) -> Option<EmulationHandle> {
+        let fingerprint = self.fingerprints.remove(&addr)?;
+        let peer = self.devices.get_mut(&fingerprint)?;
+        peer.conns.remove(&addr);
+        if !peer.conns.is_empty() {
+            return None;
+        }
+        self.devices.remove(&fingerprint).map(|peer| peer.handle)
+    }
+
+    /// connections that did not respond within `timeout`
+    fn unresponsive(&self, timeout: Duration) -> Vec<SocketAddr> {
+        self.devices
+            .values()
+            .flat_map(|peer| peer.conns.iter())
+            .filter(|(_, seen)| seen.elapsed() > timeout)
+            .map(|(&addr, _)| addr)
+            .collect()
+    }
 }
 
 /// proxy handling the actual input emulation,
@@ -209,10 +496,11 @@ pub(crate) struct EmulationProxy {
 }
 
 enum ProxyRequest {
-    Input(Event, SocketAddr),
-    Remove(SocketAddr),
+    Input(Event, EmulationHandle),
+    Remove(EmulationHandle),
     Terminate,
     Reenable,
+    Disable,
 }
 
 impl EmulationProxy {
@@ -227,7 +515,6 @@ impl EmulationProxy {
             request_rx,
             event_tx,
             handles: Default::default(),
-            next_id: 0,
         };
         let task = spawn_local(emulation_task.run());
         Self {
@@ -250,18 +537,18 @@ impl EmulationProxy {
         event
     }
 
-    fn consume(&self, event: Event, addr: SocketAddr) {
+    fn consume(&self, event: Event, handle: EmulationHandle) {
         // ignore events if emulation is currently disabled
         if self.emulation_active.get() {
             self.request_tx
-                .send(ProxyRequest::Input(event, addr))
+                .send(ProxyRequest::Input(event, handle))
                 .expect("channel closed");
         }
     }
 
-    fn remove(&self, addr: SocketAddr) {
+    fn remove(&self, handle: EmulationHandle) {
         self.request_tx
-            .send(ProxyRequest::Remove(addr))
+            .send(ProxyRequest::Remove(handle))
             .expect("channel closed");
     }
 
@@ -271,6 +558,12 @@ impl EmulationProxy {
             .expect("channel closed");
     }
 
+    fn disable(&self) {
+        self.request_tx
+            .send(ProxyRequest::Disable)
+            .expect("channel closed");
+    }
+
     async fn terminate(&mut self) {
         self.exit_requested.replace(true);
         self.request_tx
@@ -285,8 +578,8 @@ struct EmulationTask {
     exit_requested: Rc<Cell<bool>>,
     request_rx: Receiver<ProxyRequest>,
     event_tx: Sender<EmulationEvent>,
-    handles: HashMap<SocketAddr, EmulationHandle>,
-    next_id: EmulationHandle,
+    /// emulated devices, created on their first input
+    handles: HashSet<EmulationHandle>,
 }
 
 impl EmulationTask {
@@ -305,6 +598,7 @@ impl EmulationTask {
                     ProxyRequest::Terminate => return,
                     ProxyRequest::Input(..) => { /* emulation inactive => ignore */ }
                     ProxyRequest::Remove(..) => { /* emulation inactive => ignore */ }
+                    ProxyRequest::Disable => { /* already disabled */ }
                 }
             }
         }
@@ -341,7 +635,7 @@ impl EmulationTask {
         &mut self,
         emulation: &mut InputEmulation,
     ) -> Result<(), InputEmulationError> {
-        for handle in self.handles.values() {
+        for handle in self.handles.iter() {
             tokio::select! {
                 _ = emulation.create(*handle) => {},
                 _ = wait_for_termination(&mut self.request_rx) => return Ok(()),
@@ -357,26 +651,23 @@ impl EmulationTask {
         loop {
             tokio::select! {
                 e = self.request_rx.recv() => match e.expect("channel closed") {
-                    ProxyRequest::Input(event, addr) => {
-                        let handle = match self.handles.get(&addr) {
-                            Some(&handle) => handle,
-                            None => {
-                                let handle = self.next_id;
-                                self.next_id += 1;
-                                emulation.create(handle).await;
-                                self.handles.insert(addr, handle);
-                                handle
-                            }
-                        };
+                    ProxyRequest::Input(event, handle) => {
+                        if self.handles.insert(handle) {
+                            emulation.create(handle).await;
+                        }
                         emulation.consume(event, handle).await?;
                     },
-                    ProxyRequest::Remove(addr) => {
-                        if let Some(handle) = self.handles.remove(&addr) {
+                    ProxyRequest::Remove(handle) => {
+                        if self.handles.remove(&handle) {
                             emulation.destroy(handle).await;
                         }
                     }
                     ProxyRequest::Terminate => break Ok(()),
                     ProxyRequest::Reenable => continue,
+                    ProxyRequest::Disable => {
+                        log::info!("disabling input emulation");
+                        break Ok(());
+                    }
                 },
             }
         }
@@ -399,6 +690,7 @@ async fn wait_for_termination(rx: &mut Receiver<ProxyRequest>) {
             ProxyRequest::Input(_, _) => continue,
             ProxyRequest::Remove(_) => continue,
             ProxyRequest::Reenable => continue,
+            ProxyRequest::Disable => continue,
         }
     }
 }
@@ -423,3 +715,71 @@ impl<T> Drop for DropGuard<T> {
             .expect("channel closed");
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(port: u16) -> SocketAddr {
+        SocketAddr::new(IpAddr::from([192, 168, 1, 2]), port)
+    }
+
+    #[test]
+    fn connections_of_a_device_share_its_state() {
+        let mut peers = Peers::default();
+        let permissions = InputPermissions::default();
+        peers.insert(addr(1), "fp-a".into(), &permissions);
+        peers.insert(addr(2), "fp-a".into(), &permissions);
+        peers.insert(addr(3), "fp-b".into(), &permissions);
+        let (fingerprint, a1) = peers.responded(addr(1)).expect("connected");
+        assert_eq!(fingerprint, "fp-a");
+        let handle = a1.handle;
+        assert_eq!(
+            peers.responded(addr(2)).expect("connected").1.handle,
+            handle
+        );
+        assert_ne!(
+            peers.responded(addr(3)).expect("connected").1.handle,
+            handle
+        );
+        assert!(peers.responded(addr(4)).is_none());
+    }
+
+    #[test]
+    fn device_is_forgotten_with_its_last_connection() {
+        let mut peers = Peers::default();
+        let permissions = InputPermissions::default();
+        peers.insert(addr(1), "fp-a".into(), &permissions);
+        peers.insert(addr(2), "fp-a".into(), &permissions);
+        let handle = peers.responded(addr(1)).expect("connected").1.handle;
+        // the device keeps its emulated device while a connection is left
+        assert_eq!(peers.remove(addr(1)), None);
+        assert!(!peers.contains(addr(1)));
+        assert_eq!(
+            peers.responded(addr(2)).expect("connected").1.handle,
+            handle
+        );
+        assert_eq!(peers.remove(addr(2)), Some(handle));
+        assert!(peers.devices.is_empty());
+        assert_eq!(peers.remove(addr(2)), None);
+        // a device connecting again gets a new emulated device
+        peers.insert(addr(2), "fp-a".into(), &permissions);
+        assert_ne!(
+            peers.responded(addr(2)).expect("connected").1.handle,
+            handle
+        );
+    }
+
+    #[test]
+    fn unresponsive_connections() {
+        let mut peers = Peers::default();
+        let permissions = InputPermissions::default();
+        peers.insert(addr(1), "fp-a".into(), &permissions);
+        peers.insert(addr(2), "fp-a".into(), &permissions);
+        let conns = &mut peers.devices.get_mut("fp-a").expect("device").conns;
+        *conns.get_mut(&addr(1)).expect("connection") -= Duration::from_secs(2);
+        assert_eq!(peers.unresponsive(Duration::from_secs(1)), vec![addr(1)]);
+        peers.responded(addr(1));
+        assert!(peers.unresponsive(Duration::from_secs(1)).is_empty());
+    }
+}
@@ -0,0 +1,205 @@
+use std::collections::HashMap;
+
+use input_event::{Event, KeyboardEvent, scancode::Linux};
+
+/// Applies a per client key remap table to keyboard events.
+///
+/// The key sent for every pressed key is remembered, so that
+/// the corresponding release event is always sent for the same key,
+/// even if the remap table changes while the key is held.
+#[derive(Default)]
+pub(crate) struct KeyRemapper {
+    /// map from physical key to the key sent to the client
+    pressed: HashMap<u32, u32>,
+}
+
+impl KeyRemapper {
+    pub(crate) fn apply(&mut self, remap: &HashMap<u32, u32>, event: Event) -> Event {
+        match event {
+            Event::Keyboard(KeyboardEvent::Key { time, key, state }) => {
+                let mapped = match state {
+                    1 => {
+                        let mapped = remap.get(&key).copied().unwrap_or(key);
+                        self.pressed.insert(key, mapped);
+                        mapped
+                    }
+                    _ => self
+                        .pressed
+                        .remove(&key)
+                        .unwrap_or_else(|| remap.get(&key).copied().unwrap_or(key)),
+                };
+                Event::Keyboard(KeyboardEvent::Key {
+                    time,
+                    key: mapped,
+                    state,
+                })
+            }
+            Event::Keyboard(KeyboardEvent::Modifiers {
+                depressed,
+                latched,
+                locked,
+                group,
+            }) if !remap.is_empty() => Event::Keyboard(KeyboardEvent::Modifiers {
+                depressed: remap_modifiers(remap, depressed),
+                latched: remap_modifiers(remap, latched),
+                locked: remap_modifiers(remap, locked),
+                group,
+            }),
+            e => e,
+        }
+    }
+}
+
+/// right modifiers are treated like their left counterparts
+pub(crate) fn normalize(key: u32) -> u32 {
+    let key = match Linux::try_from(key) {
+        Ok(Linux::KeyRightCtrl) => Linux::KeyLeftCtrl,
+        Ok(Linux::KeyRightShift) => Linux::KeyLeftShift,
+        Ok(Linux::KeyRightalt) => Linux::KeyLeftAlt,
+        Ok(Linux::KeyRightmeta) => Linux::KeyLeftMeta,
+        _ => return key,
+    };
+    key as u32
+}
+
+/// xkb modifier mask corresponding to a modifier key
+pub(crate) fn modifier_mask(key: u32) -> u32 {
+    match Linux::try_from(key) {
+        Ok(Linux::KeyLeftShift | Linux::KeyRightShift) => 1 << 0,
+        Ok(Linux::KeyCapsLock) => 1 << 1,
+        Ok(Linux::KeyLeftCtrl | Linux::KeyRightCtrl) => 1 << 2,
+        Ok(Linux::KeyLeftAlt | Linux::KeyRightalt) => 1 << 3,
+        Ok(Linux::KeyLeftMeta | Linux::KeyRightmeta) => 1 << 6,
+        _ => 0,
+    }
+}
+
+/// move modifier bits according to the remapped modifier keys,
+/// so that modifier events stay consistent with the remapped key events
+fn remap_modifiers(remap: &HashMap<u32, u32>, mask: u32) -> u32 {
+    let masks = remap
+        .iter()
+        .map(|(&from, &to)| (modifier_mask(from), modifier_mask(to)))
+        .filter(|&(from, to)| from != 0 && to != 0)
+        .collect::<Vec<_>>();
+    let sources = masks.iter().fold(0, |acc, &(from, _)| acc | from);
+    masks
+        .iter()
+        .filter(|&&(from, _)| mask & from != 0)
+        .fold(mask & !sources, |acc, &(_, to)| acc | to)
+}
+
+#[cfg(test)]
+mod tests {
+    use input_event::PointerEvent;
+
+    use super::*;
+
+    const CAPS: u32 = Linux::KeyCapsLock as u32;
+    const LCTRL: u32 = Linux::KeyLeftCtrl as u32;
+    const LMETA: u32 = Linux::KeyLeftMeta as u32;
+    const A: u32 = Linux::KeyA as u32;
+    const B: u32 = Linux::KeyB as u32;
+
+    const SHIFT_MASK: u32 = 1 << 0;
+    const CAPS_MASK: u32 = 1 << 1;
+    const CTRL_MASK: u32 = 1 << 2;
+    const META_MASK: u32 = 1 << 6;
+
+    fn key(key: u32, state: u8) -> Event {
+        Event::Keyboard(KeyboardEvent::Key {
+            time: 0,
+            key,
+            state,
+        })
+    }
+
+    fn modifiers(depressed: u32, locked: u32) -> Event {
+        Event::Keyboard(KeyboardEvent::Modifiers {
+            depressed,
+            latched: 0,
+            locked,
+            group: 0,
+        })
+    }
+
+    #[test]
+    fn keys_are_remapped() {
+        let remap = HashMap::from([(CAPS, LCTRL)]);
+        let mut remapper = KeyRemapper::default();
+        assert_eq!(remapper.apply(&remap, key(CAPS, 1)), key(LCTRL, 1));
+        assert_eq!(remapper.apply(&remap, key(CAPS, 0)), key(LCTRL, 0));
+        assert_eq!(remapper.apply(&remap, key(A, 1)), key(A, 1));
+        assert_eq!(remapper.apply(&remap, key(A, 0)), key(A, 0));
+    }
+
+    #[test]
+    fn release_matches_press_after_remap_changes() {
+        let mut remapper = KeyRemapper::default();
+        let remap = HashMap::from([(A, B)]);
+        assert_eq!(remapper.apply(&remap, key(A, 1)), key(B, 1));
+        // the table is changed while the key is held
+        assert_eq!(remapper.apply(&HashMap::new(), key(A, 0)), key(B, 0));
+        // and the next press uses the new table
+        assert_eq!(remapper.apply(&HashMap::new(), key(A, 1)), key(A, 1));
+        assert_eq!(remapper.apply(&remap, key(A, 0)), key(A, 0));
+    }
+
+    #[test]
+    fn release_without_press_is_remapped() {
+        let remap = HashMap::from([(A, B)]);
+        let mut remapper = KeyRemapper::default();
+        assert_eq!(remapper.apply(&remap, key(A, 0)), key(B, 0));
+    }
+
+    #[test]
+    fn modifier_state_follows_remapped_keys() {
+        let remap = HashMap::from([(CAPS, LCTRL)]);
+        let mut remapper = KeyRemapper::default();
+        assert_eq!(
+            remapper.apply(&remap, modifiers(SHIFT_MASK, CAPS_MASK)),
+            modifiers(SHIFT_MASK, CTRL_MASK)
+        );
+    }
+
+    #[test]
+    fn swapped_modifiers() {
+        let remap = HashMap::from([(LCTRL, LMETA), (LMETA, LCTRL)]);
+        let mut remapper = KeyRemapper::default();
+        assert_eq!(
+            remapper.apply(&remap, modifiers(CTRL_MASK, 0)),
+            modifiers(META_MASK, 0)
+        );
+        assert_eq!(
+            remapper.apply(&remap, modifiers(CTRL_MASK | META_MASK, 0)),
+            modifiers(CTRL_MASK | META_MASK, 0)
+        );
+        // regular keys do not affect the modifier state
+        let remap = HashMap::from([(A, LCTRL)]);
+        assert_eq!(
+            remapper.apply(&remap, modifiers(SHIFT_MASK, 0)),
+            modifiers(SHIFT_MASK, 0)
+        );
+    }
+
+    #[test]
+    fn other_events_are_unchanged() {
+        let remap = HashMap::from([(A, B)]);
+        let mut remapper = KeyRemapper::default();
+        let motion = Event::Pointer(PointerEvent::Motion {
+            time: 0,
+            dx: 1.,
+            dy: 2.,
+        });
+        assert_eq!(remapper.apply(&remap, motion), motion);
+    }
+
+    #[test]
+    fn right_modifiers_are_normalized() {
+        assert_eq!(normalize(Linux::KeyRightCtrl as u32), LCTRL);
+        assert_eq!(normalize(Linux::KeyRightmeta as u32), LMETA);
+        assert_eq!(normalize(A), A);
+        assert_eq!(modifier_mask(Linux::KeyRightShift as u32), SHIFT_MASK);
+        assert_eq!(modifier_mask(A), 0);
+    }
+}
@@ -1,11 +1,26 @@
+mod audit;
+mod ca;
 mod capture;
 pub mod capture_test;
+pub mod cert;
 pub mod client;
 pub mod config;
 mod connect;
 mod crypto;
+mod discovery;
 mod dns;
+mod edge;
 mod emulation;
 pub mod emulation_test;
+mod keymap;
 mod listen;
+mod network;
+mod pairing;
+mod permissions;
+mod pointer;
+mod rate_limit;
+pub mod relay;
+mod screen_lock;
 pub mod service;
+mod tls;
+mod wol;
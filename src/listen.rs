@@ -1,28 +1,46 @@
-use futures::{Stream, StreamExt};
+use async_trait::async_trait;
+use futures::{Stream, StreamExt, future};
+use lan_mouse_ipc::InboundLimit;
 use lan_mouse_proto::{MAX_EVENT_SIZE, ProtoEvent};
 use local_channel::mpsc::{Receiver, Sender, channel};
+use rustls::ServerConfig;
 use rustls::pki_types::CertificateDer;
 use std::{
     collections::{HashMap, VecDeque},
-    net::SocketAddr,
+    io,
+    net::{IpAddr, SocketAddr},
     rc::Rc,
     sync::{Arc, Mutex, RwLock},
-    time::Duration,
+    time::{Duration, Instant},
 };
 use thiserror::Error;
 use tokio::{
+    net::{TcpListener, TcpStream, UdpSocket},
     sync::Mutex as AsyncMutex,
     task::{JoinHandle, spawn_local},
 };
 use webrtc_dtls::{
     config::{ClientAuthType::RequireAnyClientCert, Config, ExtendedMasterSecretType},
     conn::DTLSConn,
+    content::ContentType,
     crypto::Certificate,
-    listener::listen,
+    listener::DTLSListener,
+};
+use webrtc_util::{
+    Conn, Error,
+    conn::{Listener, conn_udp_listener::ListenConfig},
+};
+
+use crate::{
+    ca::CertificateAuthority,
+    crypto,
+    rate_limit::RateLimiter,
+    relay,
+    tls::{self, TlsError},
 };
-use webrtc_util::{Conn, Error, conn::Listener};
 
-use crate::crypto;
+/// time until the handshake of a session opened through a relay must be completed
+const RELAYED_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
 
 #[derive(Error, Debug)]
 pub enum ListenerCreationError {
@@ -30,10 +48,37 @@ pub enum ListenerCreationError {
     WebrtcUtil(#[from] webrtc_util::Error),
     #[error(transparent)]
     WebrtcDtls(#[from] webrtc_dtls::Error),
+    #[error(transparent)]
+    Tls(#[from] rustls::Error),
 }
 
 type ArcConn = Arc<dyn Conn + Send + Sync>;
 
+/// result of a handshake performed outside of the DTLS listener:
+/// TLS over TCP or DTLS through a relay
+type Handshake = Result<(ArcConn, SocketAddr), (SocketAddr, HandshakeError)>;
+
+#[derive(Debug, Error)]
+enum HandshakeError {
+    #[error(transparent)]
+    Tls(#[from] TlsError),
+    #[error(transparent)]
+    Dtls(#[from] webrtc_dtls::Error),
+    #[error("handshake timed out")]
+    Timeout,
+}
+
+impl HandshakeError {
+    /// the certificate of the other device was not accepted
+    fn is_rejection(&self) -> bool {
+        match self {
+            HandshakeError::Tls(e) => e.is_rejection(),
+            HandshakeError::Dtls(e) => matches!(e, webrtc_dtls::Error::ErrVerifyDataMismatch),
+            HandshakeError::Timeout => false,
+        }
+    }
+}
+
 pub(crate) enum ListenEvent {
     Msg {
         event: ProtoEvent,
@@ -42,22 +87,43 @@ pub(crate) enum ListenEvent {
     Accept {
         addr: SocketAddr,
         fingerprint: String,
+        /// the device is not authorized and was only accepted for pairing
+        pairing: bool,
     },
     Rejected {
         fingerprint: String,
     },
+    /// an authorized device rotated its key, the new certificate is authorized instead
+    KeyTransition {
+        previous: String,
+        fingerprint: String,
+    },
+    /// a device exceeded one of the inbound limits
+    LimitExceeded {
+        ip: IpAddr,
+        limit: InboundLimit,
+    },
+    /// a connection was closed
+    Closed {
+        addr: SocketAddr,
+    },
 }
 
 pub(crate) struct LanMouseListener {
     listen_rx: Receiver<ListenEvent>,
     listen_tx: Sender<ListenEvent>,
     listen_task: JoinHandle<()>,
+    /// registrations at relays
+    relay_tasks: Vec<JoinHandle<()>>,
     conns: Rc<AsyncMutex<Vec<(SocketAddr, ArcConn)>>>,
     request_port_change: Sender<u16>,
     port_changed: Receiver<Result<u16, ListenerCreationError>>,
+    /// unauthorized devices are accepted for pairing until then
+    pairing_until: Arc<Mutex<Option<Instant>>>,
+    fingerprint: String,
 }
 
-type VerifyPeerCertificateFn = Arc<
+pub(crate) type VerifyPeerCertificateFn = Arc<
     dyn (Fn(&[Vec<u8>], &[CertificateDer<'static>]) -> Result<(), webrtc_dtls::Error>)
         + Send
         + Sync,
@@ -68,30 +134,39 @@ impl LanMouseListener {
         port: u16,
         cert: Certificate,
         authorized_keys: Arc<RwLock<HashMap<String, String>>>,
+        ca: Option<Arc<CertificateAuthority>>,
+        rate_limiter: Arc<RateLimiter>,
+        relays: Vec<String>,
     ) -> Result<Self, ListenerCreationError> {
         let (listen_tx, listen_rx) = channel();
         let (request_port_change, mut request_port_change_rx) = channel();
         let (port_changed_tx, port_changed) = channel();
         let connection_attempts: Arc<Mutex<VecDeque<String>>> = Default::default();
+        let pairing_until: Arc<Mutex<Option<Instant>>> = Default::default();
 
         let authorized = authorized_keys.clone();
-        let verify_peer_certificate: Option<VerifyPeerCertificateFn> = {
+        let verify_peer_certificate: VerifyPeerCertificateFn = {
             let connection_attempts = connection_attempts.clone();
-            Some(Arc::new(
+            let pairing_until = pairing_until.clone();
+            let ca = ca.clone();
+            Arc::new(
                 move |certs: &[Vec<u8>], _chains: &[CertificateDer<'static>]| {
-                    assert!(certs.len() == 1);
-                    let fingerprints = certs
-                        .iter()
+                    let fingerprint = certs
+                        .first()
                         .map(|c| crypto::generate_fingerprint(c))
-                        .collect::<Vec<_>>();
-                    if authorized
-                        .read()
+                        .unwrap_or_default();
+                    let pairing = pairing_until
+                        .lock()
                         .expect("lock")
-                        .contains_key(&fingerprints[0])
+                        .is_some_and(|t| Instant::now() < t);
+                    if pairing
+                        || authorized.read().expect("lock").contains_key(&fingerprint)
+                        || ca_trusts(ca.as_deref(), certs)
+                        || crypto::key_transition(certs)
+                            .is_some_and(|p| authorized.read().expect("lock").contains_key(&p))
                     {
                         Ok(())
                     } else {
-                        let fingerprint = fingerprints.into_iter().next().expect("fingerprint");
                         connection_attempts
                             .lock()
                             .expect("lock")
@@ -99,51 +174,90 @@ impl LanMouseListener {
                         Err(webrtc_dtls::Error::ErrVerifyDataMismatch)
                     }
                 },
-            ))
+            )
         };
+        let tls_config = tls::server_config(&cert, verify_peer_certificate.clone())?;
         let cfg = Config {
             certificates: vec![cert.clone()],
             extended_master_secret: ExtendedMasterSecretType::Require,
             client_auth: RequireAnyClientCert,
-            verify_peer_certificate,
+            verify_peer_certificate: Some(verify_peer_certificate),
             ..Default::default()
         };
 
+        // source of the DTLS handshake in progress
+        let handshaking: Arc<Mutex<Option<IpAddr>>> = Default::default();
         let listen_addr = SocketAddr::new("0.0.0.0".parse().expect("invalid ip"), port);
-        let mut listener = listen(listen_addr, cfg.clone()).await?;
+        let mut listener = listen(
+            listen_addr,
+            cfg.clone(),
+            rate_limiter.clone(),
+            handshaking.clone(),
+        )
+        .await?;
+        let mut tcp_listener = listen_tcp(listen_addr).await;
+        let (handshake_tx, mut handshake_rx) = channel::<Handshake>();
+
+        // sessions opened by other devices through a relay
+        let fingerprint = crypto::certificate_fingerprint(&cert);
+        let (relay_tx, mut relay_rx) = channel();
+        let relay_tasks = relays
+            .into_iter()
+            .map(|relay| {
+                spawn_local(relay::register(
+                    relay,
+                    fingerprint.clone(),
+                    relay_tx.clone(),
+                ))
+            })
+            .collect();
 
         let conns: Rc<AsyncMutex<Vec<(SocketAddr, ArcConn)>>> =
             Rc::new(AsyncMutex::new(Vec::new()));
 
         let conns_clone = conns.clone();
         let listen_task: JoinHandle<()> = {
+            let authorized = authorized_keys.clone();
             let listen_tx = listen_tx.clone();
             let connection_attempts = connection_attempts.clone();
             spawn_local(async move {
+                let rejected = |ip: IpAddr| {
+                    rate_limiter.rejected(ip);
+                    if let Some(fingerprint) = connection_attempts.lock().expect("lock").pop_front()
+                    {
+                        listen_tx
+                            .send(ListenEvent::Rejected { fingerprint })
+                            .expect("channel closed");
+                    }
+                };
                 loop {
+                    for (ip, limit) in rate_limiter.take_exceeded() {
+                        listen_tx
+                            .send(ListenEvent::LimitExceeded { ip, limit })
+                            .expect("channel closed");
+                    }
                     let sleep = tokio::time::sleep(Duration::from_secs(2));
-                    tokio::select! {
+                    let (conn, addr) = tokio::select! {
                         /* workaround for https://github.com/webrtc-rs/webrtc/issues/614 */
                         _ = sleep => continue,
                         c = listener.accept() => match c {
                             Ok((conn, addr)) => {
                                 log::info!("dtls client connected, ip: {addr}");
-                                let mut conns = conns_clone.lock().await;
-                                conns.push((addr, conn.clone()));
-                                let dtls_conn: &DTLSConn = conn.as_any().downcast_ref().expect("dtls conn");
-                                let certs = dtls_conn.connection_state().await.peer_certificates;
-                                let cert = certs.first().expect("cert");
-                                let fingerprint = crypto::generate_fingerprint(cert);
-                                listen_tx.send(ListenEvent::Accept { addr, fingerprint }).expect("channel closed");
-                                spawn_local(read_loop(conns_clone.clone(), addr, conn, listen_tx.clone()));
-                            },
+                                handshaking.lock().expect("lock").take();
+                                (conn, addr)
+                            }
                             Err(e) => {
+                                // the handshake in progress failed, if one was admitted
+                                let ip = handshaking.lock().expect("lock").take();
+                                if ip.is_some() {
+                                    rate_limiter.handshake_failed();
+                                }
                                 if let Error::Std(ref e) = e {
                                     if let Some(e) = e.0.downcast_ref::<webrtc_dtls::Error>() {
                                         match e {
                                             webrtc_dtls::Error::ErrVerifyDataMismatch => {
-                                                if let Some(fingerprint) = connection_attempts.lock().expect("lock").pop_front() {
-                                                    listen_tx.send(ListenEvent::Rejected { fingerprint }).expect("channel closed");
+                                                if let Some(ip) = ip {
+                                                    rejected(ip);
                                                 }
                                             }
                                             _ => log::warn!("accept: {e}"),
@@ -154,24 +268,89 @@ impl LanMouseListener {
                                 } else {
                                     log::warn!("accept: {e:?}");
                                 }
+                                continue;
+                            }
+                        },
+                        c = accept_tcp(tcp_listener.as_ref()) => {
+                            match c {
+                                Ok((stream, addr)) if rate_limiter.allow_handshake(addr.ip()) => {
+                                    spawn_local(tls_handshake(stream, addr, tls_config.clone(), handshake_tx.clone()));
+                                }
+                                Ok(_) => {}
+                                Err(e) => log::warn!("tcp accept: {e}"),
+                            }
+                            continue;
+                        }
+                        Some((socket, addr)) = relay_rx.recv() => {
+                            if rate_limiter.allow_handshake(addr.ip()) {
+                                spawn_local(relayed_handshake(socket, addr, cfg.clone(), handshake_tx.clone()));
+                            }
+                            continue;
+                        }
+                        c = handshake_rx.recv() => match c.expect("channel closed") {
+                            Ok(c) => c,
+                            Err((addr, e)) => {
+                                rate_limiter.handshake_failed();
+                                if e.is_rejection() {
+                                    rejected(addr.ip());
+                                } else {
+                                    log::warn!("accept {addr}: {e}");
+                                }
+                                continue;
                             }
                         },
                         port = request_port_change_rx.recv() => {
                             let port = port.expect("channel closed");
                             let listen_addr = SocketAddr::new("0.0.0.0".parse().expect("invalid ip"), port);
-                            match listen(listen_addr, cfg.clone()).await {
+                            match listen(listen_addr, cfg.clone(), rate_limiter.clone(), handshaking.clone()).await {
                                 Ok(new_listener) => {
                                     let _ = listener.close().await;
                                     listener = new_listener;
+                                    tcp_listener = listen_tcp(listen_addr).await;
                                     port_changed_tx.send(Ok(port)).expect("channel closed");
                                 }
                                 Err(e) => {
                                     log::warn!("unable to change port: {e}");
-                                    port_changed_tx.send(Err(e.into())).expect("channel closed");
+                                    port_changed_tx.send(Err(e)).expect("channel closed");
                                 }
                             };
+                            continue;
                         },
                     };
+                    let mut conns = conns_clone.lock().await;
+                    conns.push((addr, conn.clone()));
+                    let certs = tls::peer_certificates(&conn).await;
+                    let cert = certs.first().expect("cert");
+                    let fingerprint = crypto::generate_fingerprint(cert);
+                    let mut pairing = !authorized.read().expect("lock").contains_key(&fingerprint)
+                        && !ca_trusts(ca.as_deref(), &certs);
+                    if pairing {
+                        if let Some(previous) =
+                            follow_key_transition(&authorized, &certs, &fingerprint)
+                        {
+                            listen_tx
+                                .send(ListenEvent::KeyTransition {
+                                    previous,
+                                    fingerprint: fingerprint.clone(),
+                                })
+                                .expect("channel closed");
+                            pairing = false;
+                        }
+                    }
+                    listen_tx
+                        .send(ListenEvent::Accept {
+                            addr,
+                            fingerprint,
+                            pairing,
+                        })
+                        .expect("channel closed");
+                    spawn_local(read_loop(
+                        conns_clone.clone(),
+                        addr,
+                        conn,
+                        listen_tx.clone(),
+                        rate_limiter.clone(),
+                    ));
                 }
             })
         };
@@ -181,11 +360,27 @@ impl LanMouseListener {
             listen_rx,
             listen_tx,
             listen_task,
+            relay_tasks,
             port_changed,
             request_port_change,
+            pairing_until,
+            fingerprint,
         })
     }
 
+    /// accept connections from unauthorized devices for pairing
+    pub(crate) fn allow_pairing(&self, duration: Duration) {
+        self.pairing_until
+            .lock()
+            .expect("lock")
+            .replace(Instant::now() + duration);
+    }
+
+    /// fingerprint of the certificate of this device
+    pub(crate) fn fingerprint(&self) -> &str {
+        &self.fingerprint
+    }
+
     pub(crate) fn request_port_change(&mut self, port: u16) {
         self.request_port_change.send(port).expect("channel closed");
     }
@@ -196,6 +391,9 @@ impl LanMouseListener {
 
     pub(crate) async fn terminate(&mut self) {
         self.listen_task.abort();
+        for task in &self.relay_tasks {
+            task.abort();
+        }
         let conns = self.conns.lock().await;
         for (_, conn) in conns.iter() {
             let _ = conn.close().await;
@@ -214,6 +412,33 @@ impl LanMouseListener {
         }
     }
 
+    pub(crate) async fn broadcast(&self, event: ProtoEvent) {
+        log::trace!("broadcast {event} >=>=>=>=>=> *");
+        let (buf, len): ([u8; MAX_EVENT_SIZE], usize) = event.into();
+        let conns = self.conns.lock().await;
+        for (_, conn) in conns.iter() {
+            let _ = conn.send(&buf[..len]).await;
+        }
+    }
+
+    /// close all connections of the device with the given certificate fingerprint
+    /// and return their addresses
+    pub(crate) async fn close_connections(&self, fingerprint: &str) -> Vec<SocketAddr> {
+        let conns = self.conns.lock().await.clone();
+        let mut closed = Vec::new();
+        for (addr, conn) in conns {
+            let certs = tls::peer_certificates(&conn).await;
+            if certs
+                .first()
+                .is_some_and(|c| crypto::generate_fingerprint(c) == fingerprint)
+            {
+                let _ = conn.close().await;
+                closed.push(addr);
+            }
+        }
+        closed
+    }
+
     pub(crate) async fn get_certificate_fingerprint(&self, addr: SocketAddr) -> Option<String> {
         if let Some(conn) = self
             .conns
@@ -223,8 +448,7 @@ impl LanMouseListener {
             .find(|(a, _)| *a == addr)
             .map(|(_, c)| c.clone())
         {
-            let conn: &DTLSConn = conn.as_any().downcast_ref().expect("dtls conn");
-            let certs = conn.connection_state().await.peer_certificates;
+            let certs = tls::peer_certificates(&conn).await;
             let cert = certs.first()?;
             let fingerprint = crypto::generate_fingerprint(cert);
             Some(fingerprint)
@@ -245,15 +469,169 @@ impl Stream for LanMouseListener {
     }
 }
 
+/// DTLS listener that admits handshakes according to the inbound limits
+async fn listen(
+    addr: SocketAddr,
+    config: Config,
+    rate_limiter: Arc<RateLimiter>,
+    handshaking: Arc<Mutex<Option<IpAddr>>>,
+) -> Result<DTLSListener, ListenerCreationError> {
+    let mut listen_config = ListenConfig {
+        // only handshake records open new connections
+        accept_filter: Some(Box::new(|packet: &[u8]| {
+            let handshake = packet.first() == Some(&(ContentType::Handshake as u8));
+            Box::pin(async move { handshake })
+        })),
+        ..Default::default()
+    };
+    let parent = Arc::new(listen_config.listen(addr).await?);
+    let limited = LimitedListener {
+        parent,
+        rate_limiter,
+        handshaking,
+    };
+    Ok(DTLSListener::new(Arc::new(limited), config)?)
+}
+
+/// TCP listener for TLS connections on the same port as DTLS
+async fn listen_tcp(addr: SocketAddr) -> Option<TcpListener> {
+    TcpListener::bind(addr)
+        .await
+        .inspect_err(|e| log::warn!("TLS over TCP unavailable: could not bind {addr}: {e}"))
+        .ok()
+}
+
+async fn accept_tcp(listener: Option<&TcpListener>) -> io::Result<(TcpStream, SocketAddr)> {
+    match listener {
+        Some(listener) => listener.accept().await,
+        None => future::pending().await,
+    }
+}
+
+/// handshakes of TCP connections run concurrently and report to `tx`
+async fn tls_handshake(
+    stream: TcpStream,
+    addr: SocketAddr,
+    config: Arc<ServerConfig>,
+    tx: Sender<Handshake>,
+) {
+    let result = match tls::accept(stream, config).await {
+        Ok(conn) => {
+            log::info!("tls client connected, ip: {addr}");
+            Ok((Arc::new(conn) as ArcConn, addr))
+        }
+        Err(e) => Err((addr, e.into())),
+    };
+    // the listener may have been terminated in the meantime
+    let _ = tx.send(result);
+}
+
+/// DTLS handshake of a session opened by the device at `addr` through a relay
+async fn relayed_handshake(
+    socket: UdpSocket,
+    addr: SocketAddr,
+    config: Config,
+    tx: Sender<Handshake>,
+) {
+    let handshake = DTLSConn::new(Arc::new(socket), config, false, None);
+    let result = match tokio::time::timeout(RELAYED_HANDSHAKE_TIMEOUT, handshake).await {
+        Ok(Ok(conn)) => {
+            log::info!("relayed client connected, ip: {addr}");
+            Ok((Arc::new(conn) as ArcConn, addr))
+        }
+        Ok(Err(e)) => Err((addr, e.into())),
+        Err(_) => Err((addr, HandshakeError::Timeout)),
+    };
+    // the listener may have been terminated in the meantime
+    let _ = tx.send(result);
+}
+
+/// drops connections of hosts exceeding the inbound limits before their handshake
+struct LimitedListener {
+    parent: Arc<dyn Listener + Send + Sync>,
+    rate_limiter: Arc<RateLimiter>,
+    /// source of the handshake in progress, DTLS handshakes are performed one at a time
+    handshaking: Arc<Mutex<Option<IpAddr>>>,
+}
+
+#[async_trait]
+impl Listener for LimitedListener {
+    async fn accept(&self) -> Result<(ArcConn, SocketAddr), Error> {
+        loop {
+            let (conn, addr) = self.parent.accept().await?;
+            if self.rate_limiter.allow_handshake(addr.ip()) {
+                self.handshaking.lock().expect("lock").replace(addr.ip());
+                return Ok((conn, addr));
+            }
+            let _ = conn.close().await;
+        }
+    }
+
+    async fn close(&self) -> Result<(), Error> {
+        self.parent.close().await
+    }
+
+    async fn addr(&self) -> Result<SocketAddr, Error> {
+        self.parent.addr().await
+    }
+}
+
+/// If the device rotated its key and its previous certificate is authorized,
+/// the new certificate is authorized in its place.
+/// Returns the fingerprint of the previous certificate.
+fn follow_key_transition(
+    authorized: &RwLock<HashMap<String, String>>,
+    certs: &[Vec<u8>],
+    fingerprint: &str,
+) -> Option<String> {
+    let previous = crypto::key_transition(certs)?;
+    let mut authorized = authorized.write().expect("lock");
+    let description = authorized.remove(&previous)?;
+    log::info!("{previous} ({description}) rotated its key, authorizing {fingerprint}");
+    authorized.insert(fingerprint.to_owned(), description);
+    Some(previous)
+}
+
+/// whether the certificate chain of a connecting device is issued by the CA
+fn ca_trusts(ca: Option<&CertificateAuthority>, certs: &[Vec<u8>]) -> bool {
+    let Some(ca) = ca else {
+        return false;
+    };
+    ca.verify_client(certs)
+        .inspect_err(|e| log::debug!("certificate authority: {e}"))
+        .is_ok()
+}
+
 async fn read_loop(
     conns: Rc<AsyncMutex<Vec<(SocketAddr, ArcConn)>>>,
     addr: SocketAddr,
     conn: ArcConn,
     dtls_tx: Sender<ListenEvent>,
+    rate_limiter: Arc<RateLimiter>,
 ) -> Result<(), Error> {
     let mut b = [0u8; MAX_EVENT_SIZE];
+    let max_event_rate = rate_limiter.max_event_rate();
+    // start and number of events of the current second
+    let mut window = (Instant::now(), 0);
 
     while conn.recv(&mut b).await.is_ok() {
+        if max_event_rate > 0 {
+            if window.0.elapsed() >= Duration::from_secs(1) {
+                window = (Instant::now(), 0);
+            }
+            window.1 += 1;
+            if window.1 > max_event_rate {
+                log::warn!("{addr}: more than {max_event_rate} events per second, disconnecting");
+                dtls_tx
+                    .send(ListenEvent::LimitExceeded {
+                        ip: addr.ip(),
+                        limit: InboundLimit::EventRate,
+                    })
+                    .expect("channel closed");
+                let _ = conn.close().await;
+                break;
+            }
+        }
         match b.try_into() {
             Ok(event) => dtls_tx
                 .send(ListenEvent::Msg { event, addr })
@@ -265,11 +643,14 @@ async fn read_loop(
         }
     }
     log::info!("dtls client disconnected {addr:?}");
+    rate_limiter.disconnected();
     let mut conns = conns.lock().await;
     let index = conns
         .iter()
         .position(|(a, _)| *a == addr)
         .expect("connection not found");
     conns.remove(index);
+    // the listener may already be terminated
+    let _ = dtls_tx.send(ListenEvent::Closed { addr });
     Ok(())
 }
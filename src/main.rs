@@ -5,8 +5,10 @@ use input_capture::InputCaptureError;
 use input_emulation::InputEmulationError;
 use lan_mouse::{
     capture_test,
+    cert::{self, CertError},
     config::{self, Command, Config, ConfigError},
     emulation_test,
+    relay::{self, RelayError},
     service::{Service, ServiceError},
 };
 use lan_mouse_cli::CliError;
@@ -40,6 +42,10 @@ enum LanMouseError {
     Gtk(#[from] GtkError),
     #[error(transparent)]
     Cli(#[from] CliError),
+    #[error(transparent)]
+    Cert(#[from] CertError),
+    #[error(transparent)]
+    Relay(#[from] RelayError),
 }
 
 fn main() {
@@ -60,6 +66,8 @@ fn run() -> Result<(), LanMouseError> {
             Command::TestEmulation(args) => run_async(emulation_test::run(config, args))?,
             Command::TestCapture(args) => run_async(capture_test::run(config, args))?,
             Command::Cli(cli_args) => run_async(lan_mouse_cli::run(cli_args))?,
+            Command::Cert(args) => cert::run(config, args)?,
+            Command::Relay(args) => run_async(relay::run(args))?,
             Command::Daemon => {
                 // if daemon is specified we run the service
                 match run_async(run_service(config)) {
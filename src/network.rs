@@ -0,0 +1,170 @@
+#[cfg(target_os = "linux")]
+use std::time::Duration;
+
+use local_channel::mpsc::{Receiver, Sender, channel};
+use tokio::task::{JoinHandle, spawn_local};
+use tokio_util::sync::CancellationToken;
+
+/// changes arriving within this time are reported once
+#[cfg(target_os = "linux")]
+const SETTLE_TIME: Duration = Duration::from_secs(1);
+
+/// watches the network addresses of this device, e.g. to notice
+/// when the device roams to another network or its DHCP lease changes.
+///
+/// On Linux, address changes are received via netlink (`RTMGRP_IPV4_IFADDR`,
+/// `RTMGRP_IPV6_IFADDR`), other platforms do not report any changes.
+pub(crate) struct NetworkMonitor {
+    cancellation_token: CancellationToken,
+    task: Option<JoinHandle<()>>,
+    event_rx: Receiver<()>,
+}
+
+struct NetworkMonitorTask {
+    event_tx: Sender<()>,
+    cancellation_token: CancellationToken,
+}
+
+impl NetworkMonitor {
+    pub(crate) fn new() -> Self {
+        let (event_tx, event_rx) = channel();
+        let cancellation_token = CancellationToken::new();
+        let network_task = NetworkMonitorTask {
+            event_tx,
+            cancellation_token: cancellation_token.clone(),
+        };
+        let task = Some(spawn_local(network_task.run()));
+        Self {
+            cancellation_token,
+            task,
+            event_rx,
+        }
+    }
+
+    /// the network addresses of this device changed
+    pub(crate) async fn changed(&mut self) {
+        self.event_rx.recv().await.expect("channel closed")
+    }
+
+    pub(crate) async fn terminate(&mut self) {
+        self.cancellation_token.cancel();
+        self.task.take().expect("task").await.expect("join error");
+    }
+}
+
+impl NetworkMonitorTask {
+    async fn run(self) {
+        let cancellation_token = self.cancellation_token.clone();
+        tokio::select! {
+            _ = self.do_monitor() => {},
+            _ = cancellation_token.cancelled() => {},
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    async fn do_monitor(&self) {
+        log::debug!("network changes are not monitored on this platform");
+        // `event_tx` stays open, no changes are reported
+        let _ = &self.event_tx;
+        std::future::pending().await
+    }
+
+    #[cfg(target_os = "linux")]
+    async fn do_monitor(&self) {
+        let socket = match netlink::AddressSocket::new() {
+            Ok(socket) => socket,
+            Err(e) => return log::warn!("network changes can not be monitored: {e}"),
+        };
+        loop {
+            if let Err(e) = socket.recv().await {
+                return log::warn!("netlink: {e}");
+            }
+            // interfaces usually change several addresses at once
+            let settled = tokio::time::sleep(SETTLE_TIME);
+            tokio::pin!(settled);
+            loop {
+                tokio::select! {
+                    _ = &mut settled => break,
+                    r = socket.recv() => if let Err(e) = r {
+                        return log::warn!("netlink: {e}");
+                    },
+                }
+            }
+            log::info!("network addresses changed");
+            self.event_tx.send(()).expect("channel closed");
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod netlink {
+    use std::{
+        io,
+        mem::{self, MaybeUninit},
+        os::fd::{AsRawFd, FromRawFd, OwnedFd},
+    };
+
+    use tokio::io::unix::AsyncFd;
+
+    /// netlink socket subscribed to address changes of all interfaces
+    pub(super) struct AddressSocket {
+        fd: AsyncFd<OwnedFd>,
+    }
+
+    impl AddressSocket {
+        pub(super) fn new() -> io::Result<Self> {
+            let flags = libc::SOCK_RAW | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC;
+            // SAFETY: plain system call, the returned descriptor is owned below
+            let fd = unsafe { libc::socket(libc::AF_NETLINK, flags, libc::NETLINK_ROUTE) };
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            // SAFETY: `fd` is a valid descriptor not owned by anything else
+            let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+            // SAFETY: `sockaddr_nl` is valid when zeroed
+            let mut addr: libc::sockaddr_nl = unsafe { mem::zeroed() };
+            addr.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+            addr.nl_groups = (libc::RTMGRP_IPV4_IFADDR | libc::RTMGRP_IPV6_IFADDR) as u32;
+            // SAFETY: `addr` is a `sockaddr_nl` of the given size
+            let res = unsafe {
+                libc::bind(
+                    fd.as_raw_fd(),
+                    &addr as *const libc::sockaddr_nl as *const libc::sockaddr,
+                    mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+                )
+            };
+            if res < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(Self {
+                fd: AsyncFd::new(fd)?,
+            })
+        }
+
+        /// wait for the next message, its content is not needed
+        pub(super) async fn recv(&self) -> io::Result<()> {
+            let mut buf = [MaybeUninit::<u8>::uninit(); 8192];
+            loop {
+                let mut guard = self.fd.readable().await?;
+                let res = guard.try_io(|fd| {
+                    // SAFETY: `buf` is valid for writes of its length
+                    let len = unsafe {
+                        libc::recv(fd.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len(), 0)
+                    };
+                    match len {
+                        len if len < 0 => Err(io::Error::last_os_error()),
+                        _ => Ok(()),
+                    }
+                });
+                match res {
+                    Ok(Err(e)) if e.raw_os_error() == Some(libc::ENOBUFS) => {
+                        // messages were dropped, which is a change as well
+                        return Ok(());
+                    }
+                    Ok(res) => return res,
+                    Err(_would_block) => continue,
+                }
+            }
+        }
+    }
+}
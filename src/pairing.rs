@@ -0,0 +1,181 @@
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+
+use lan_mouse_proto::{MAX_EVENT_SIZE, ProtoEvent};
+use local_channel::mpsc::{Receiver, Sender, channel};
+use thiserror::Error;
+use tokio::task::{JoinHandle, spawn_local};
+use tokio_util::sync::CancellationToken;
+use webrtc_dtls::crypto::Certificate;
+use webrtc_util::Conn;
+
+use crate::{
+    connect::{self, LanMouseConnectionError},
+    crypto, tls,
+};
+
+/// time during which unauthorized devices may pair with this device
+pub(crate) const PAIRING_WINDOW: Duration = Duration::from_secs(120);
+/// time to wait for the response of the other device
+const PAIRING_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Error)]
+pub(crate) enum PairingError {
+    #[error(transparent)]
+    Connection(#[from] LanMouseConnectionError),
+    #[error(transparent)]
+    Webrtc(#[from] webrtc_util::Error),
+    #[error("no certificate received")]
+    NoCertificate,
+    #[error("no response - is the other device ready for pairing?")]
+    Timeout,
+}
+
+/// initiates pairing with other devices: both devices exchange their
+/// certificates through the DTLS handshake and derive a short code from them
+/// and a pair of nonces, which the user compares on both devices.
+///
+/// The initiator commits to its nonce ([`ProtoEvent::PairCommit`]) before the
+/// responder reveals its own ([`ProtoEvent::PairNonce`]), so a device in the middle
+/// can not choose its nonce or certificate to produce matching codes.
+pub(crate) struct Pairing {
+    cancellation_token: CancellationToken,
+    task: Option<JoinHandle<()>>,
+    request_tx: Sender<SocketAddr>,
+    event_rx: Receiver<PairingEvent>,
+}
+
+pub(crate) enum PairingEvent {
+    /// pairing finished, the code must be compared by the user
+    Code {
+        addr: SocketAddr,
+        fingerprint: String,
+        code: String,
+    },
+    Failed {
+        addr: SocketAddr,
+        error: PairingError,
+    },
+}
+
+struct PairingTask {
+    cert: Certificate,
+    request_rx: Receiver<SocketAddr>,
+    event_tx: Sender<PairingEvent>,
+    cancellation_token: CancellationToken,
+}
+
+impl Pairing {
+    pub(crate) fn new(cert: Certificate) -> Self {
+        let (request_tx, request_rx) = channel();
+        let (event_tx, event_rx) = channel();
+        let cancellation_token = CancellationToken::new();
+        let pairing_task = PairingTask {
+            cert,
+            request_rx,
+            event_tx,
+            cancellation_token: cancellation_token.clone(),
+        };
+        let task = Some(spawn_local(pairing_task.run()));
+        Self {
+            cancellation_token,
+            task,
+            request_tx,
+            event_rx,
+        }
+    }
+
+    /// pair with the device listening at the given address
+    pub(crate) fn pair(&self, addr: SocketAddr) {
+        self.request_tx.send(addr).expect("channel closed");
+    }
+
+    pub(crate) async fn event(&mut self) -> PairingEvent {
+        self.event_rx.recv().await.expect("channel closed")
+    }
+
+    pub(crate) async fn terminate(&mut self) {
+        self.cancellation_token.cancel();
+        self.task.take().expect("task").await.expect("join error");
+    }
+}
+
+impl PairingTask {
+    async fn run(mut self) {
+        let cancellation_token = self.cancellation_token.clone();
+        tokio::select! {
+            _ = self.do_pairing() => {},
+            _ = cancellation_token.cancelled() => {},
+        }
+    }
+
+    async fn do_pairing(&mut self) {
+        while let Some(addr) = self.request_rx.recv().await {
+            let cert = self.cert.clone();
+            let event_tx = self.event_tx.clone();
+            let cancellation_token = self.cancellation_token.clone();
+            spawn_local(async move {
+                tokio::select! {
+                    result = pair(addr, cert) => {
+                        let event = match result {
+                            Ok((fingerprint, code)) => PairingEvent::Code { addr, fingerprint, code },
+                            Err(error) => PairingEvent::Failed { addr, error },
+                        };
+                        event_tx.send(event).expect("channel closed");
+                    }
+                    _ = cancellation_token.cancelled() => {},
+                }
+            });
+        }
+    }
+}
+
+/// returns the fingerprint of the other device and the pairing code
+async fn pair(addr: SocketAddr, cert: Certificate) -> Result<(String, String), PairingError> {
+    log::info!("pairing with {addr} ...");
+    let (conn, _) = connect::connect(addr, cert.clone(), Default::default())
+        .await
+        .map_err(|(_, e)| e)?;
+    let result = exchange_nonces(&conn, &cert).await;
+    let _ = conn.close().await;
+    result
+}
+
+async fn exchange_nonces(
+    conn: &Arc<dyn Conn + Send + Sync>,
+    cert: &Certificate,
+) -> Result<(String, String), PairingError> {
+    let certs = tls::peer_certificates(conn).await;
+    let peer_cert = certs.first().ok_or(PairingError::NoCertificate)?;
+    let fingerprint = crypto::generate_fingerprint(peer_cert);
+
+    let nonce: u128 = rand::random();
+    send(
+        conn,
+        ProtoEvent::PairCommit(crypto::pairing_commitment(nonce)),
+    )
+    .await?;
+    let peer_nonce = tokio::time::timeout(PAIRING_TIMEOUT, recv_nonce(conn))
+        .await
+        .map_err(|_| PairingError::Timeout)??;
+    send(conn, ProtoEvent::PairNonce(nonce)).await?;
+
+    let own_fingerprint = crypto::certificate_fingerprint(cert);
+    let code = crypto::pairing_code(&own_fingerprint, &fingerprint, nonce, peer_nonce);
+    Ok((fingerprint, code))
+}
+
+async fn send(conn: &Arc<dyn Conn + Send + Sync>, event: ProtoEvent) -> Result<(), PairingError> {
+    let (buf, len): ([u8; MAX_EVENT_SIZE], usize) = event.into();
+    conn.send(&buf[..len]).await?;
+    Ok(())
+}
+
+async fn recv_nonce(conn: &Arc<dyn Conn + Send + Sync>) -> Result<u128, PairingError> {
+    let mut buf = [0u8; MAX_EVENT_SIZE];
+    loop {
+        conn.recv(&mut buf).await?;
+        if let Ok(ProtoEvent::PairNonce(nonce)) = buf.try_into() {
+            return Ok(nonce);
+        }
+    }
+}
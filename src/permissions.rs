@@ -0,0 +1,291 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, RwLock},
+};
+
+use input_event::{Event, KeyboardEvent};
+
+use crate::{
+    config::InputPermissions,
+    keymap::{modifier_mask, normalize},
+};
+
+/// [`InputPermissions`] of connecting devices by certificate fingerprint.
+///
+/// Authorized fingerprints without configured permissions may send any input.
+/// Any other device (e.g. one trusted through the certificate authority)
+/// only gets the permissions granted to the certificate authority.
+pub(crate) struct DevicePermissions {
+    /// configured permissions of authorized fingerprints
+    configured: HashMap<String, InputPermissions>,
+    /// authorized fingerprints, including those authorized at runtime
+    authorized: Arc<RwLock<HashMap<String, String>>>,
+    /// permissions of devices that are not authorized individually
+    others: InputPermissions,
+}
+
+impl DevicePermissions {
+    pub(crate) fn new(
+        configured: HashMap<String, InputPermissions>,
+        authorized: Arc<RwLock<HashMap<String, String>>>,
+        others: Option<InputPermissions>,
+    ) -> Self {
+        Self {
+            configured,
+            authorized,
+            others: others.unwrap_or_else(InputPermissions::none),
+        }
+    }
+
+    pub(crate) fn get(&self, fingerprint: &str) -> InputPermissions {
+        if let Some(permissions) = self.configured.get(fingerprint) {
+            return permissions.clone();
+        }
+        match self
+            .authorized
+            .read()
+            .expect("lock")
+            .contains_key(fingerprint)
+        {
+            true => InputPermissions::default(),
+            false => self.others.clone(),
+        }
+    }
+
+    /// the permissions of a device follow it to its new key
+    pub(crate) fn key_transition(&mut self, previous: &str, fingerprint: String) {
+        if let Some(permissions) = self.configured.remove(previous) {
+            self.configured.insert(fingerprint, permissions);
+        }
+    }
+}
+
+/// Enforces the [`InputPermissions`] of a connected device.
+///
+/// Pressed keys and depressed modifiers are tracked to detect denied key
+/// combinations. If the press of a key is dropped, its release is dropped
+/// as well. A modifier state completing a denied combination is dropped, too.
+pub(crate) struct InputFilter {
+    pointer: bool,
+    keyboard: bool,
+    /// denied key combinations (left and right modifiers are not distinguished)
+    deny_keys: Vec<Vec<u32>>,
+    /// keys currently held down by the device
+    pressed: HashSet<u32>,
+    /// modifiers reported as depressed by the device
+    modifiers: u32,
+    /// held keys whose press event was dropped
+    suppressed: HashSet<u32>,
+}
+
+impl InputFilter {
+    pub(crate) fn new(permissions: &InputPermissions) -> Self {
+        let deny_keys = permissions
+            .deny_keys
+            .iter()
+            .map(|combo| combo.iter().map(|&k| normalize(k as u32)).collect())
+            .collect();
+        Self {
+            pointer: permissions.pointer,
+            keyboard: permissions.keyboard,
+            deny_keys,
+            pressed: Default::default(),
+            modifiers: 0,
+            suppressed: Default::default(),
+        }
+    }
+
+    /// whether the event may be emulated
+    pub(crate) fn allow(&mut self, event: &Event) -> bool {
+        match *event {
+            Event::Pointer(_) => self.pointer,
+            Event::Keyboard(_) if !self.keyboard => false,
+            Event::Keyboard(KeyboardEvent::Key { key, state, .. }) => self.allow_key(key, state),
+            Event::Keyboard(KeyboardEvent::Modifiers { depressed, .. }) => {
+                self.allow_modifiers(depressed)
+            }
+        }
+    }
+
+    fn allow_modifiers(&mut self, depressed: u32) -> bool {
+        self.modifiers = depressed;
+        let denied = self.deny_keys.iter().any(|combo| self.completes(combo));
+        if denied {
+            log::warn!("dropping modifiers {depressed:#x}: denied key combination");
+        }
+        !denied
+    }
+
+    /// all keys of the combination are held,
+    /// either as pressed keys or as depressed modifiers
+    fn completes(&self, combo: &[u32]) -> bool {
+        combo.iter().all(|&k| {
+            let mask = modifier_mask(k);
+            self.pressed.contains(&k) || (mask != 0 && self.modifiers & mask != 0)
+        })
+    }
+
+    fn allow_key(&mut self, key: u32, state: u8) -> bool {
+        if state == 0 {
+            self.pressed.remove(&normalize(key));
+            return !self.suppressed.remove(&key);
+        }
+        self.pressed.insert(normalize(key));
+        let denied = self
+            .deny_keys
+            .iter()
+            .any(|combo| combo.contains(&normalize(key)) && self.completes(combo));
+        if denied {
+            log::warn!("dropping key {key}: denied key combination");
+            self.suppressed.insert(key);
+        }
+        !denied
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use input_event::{PointerEvent, scancode::Linux};
+
+    use super::*;
+
+    fn key(key: Linux, state: u8) -> Event {
+        Event::Keyboard(KeyboardEvent::Key {
+            time: 0,
+            key: key as u32,
+            state,
+        })
+    }
+
+    fn modifiers(keys: &[Linux]) -> Event {
+        Event::Keyboard(KeyboardEvent::Modifiers {
+            depressed: keys.iter().fold(0, |m, &k| m | modifier_mask(k as u32)),
+            latched: 0,
+            locked: 0,
+            group: 0,
+        })
+    }
+
+    fn motion() -> Event {
+        Event::Pointer(PointerEvent::Motion {
+            time: 0,
+            dx: 1.,
+            dy: 1.,
+        })
+    }
+
+    /// Ctrl+Alt+Delete denied
+    fn deny_cad() -> InputFilter {
+        InputFilter::new(&InputPermissions {
+            deny_keys: vec![vec![
+                Linux::KeyLeftCtrl,
+                Linux::KeyLeftAlt,
+                Linux::KeyDelete,
+            ]],
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn unrestricted_allows_everything() {
+        let mut filter = InputFilter::new(&InputPermissions::default());
+        assert!(filter.allow(&motion()));
+        assert!(filter.allow(&key(Linux::KeyA, 1)));
+        assert!(filter.allow(&modifiers(&[Linux::KeyLeftCtrl])));
+    }
+
+    #[test]
+    fn pointer_only() {
+        let mut filter = InputFilter::new(&InputPermissions {
+            keyboard: false,
+            ..Default::default()
+        });
+        assert!(filter.allow(&motion()));
+        assert!(!filter.allow(&key(Linux::KeyA, 1)));
+        assert!(!filter.allow(&key(Linux::KeyA, 0)));
+        assert!(!filter.allow(&modifiers(&[Linux::KeyLeftShift])));
+    }
+
+    #[test]
+    fn keyboard_only() {
+        let mut filter = InputFilter::new(&InputPermissions {
+            pointer: false,
+            ..Default::default()
+        });
+        assert!(!filter.allow(&motion()));
+        assert!(filter.allow(&key(Linux::KeyA, 1)));
+    }
+
+    #[test]
+    fn denied_combination_and_its_release_are_dropped() {
+        let mut filter = deny_cad();
+        assert!(filter.allow(&key(Linux::KeyLeftCtrl, 1)));
+        assert!(filter.allow(&key(Linux::KeyRightalt, 1)));
+        assert!(!filter.allow(&key(Linux::KeyDelete, 1)));
+        assert!(!filter.allow(&key(Linux::KeyDelete, 0)));
+        assert!(filter.allow(&key(Linux::KeyRightalt, 0)));
+        // the combination is no longer complete
+        assert!(filter.allow(&key(Linux::KeyDelete, 1)));
+        assert!(filter.allow(&key(Linux::KeyDelete, 0)));
+    }
+
+    #[test]
+    fn denied_combination_through_modifier_state() {
+        let mut filter = deny_cad();
+        assert!(filter.allow(&modifiers(&[Linux::KeyLeftCtrl, Linux::KeyLeftAlt])));
+        assert!(!filter.allow(&key(Linux::KeyDelete, 1)));
+        assert!(!filter.allow(&key(Linux::KeyDelete, 0)));
+        assert!(filter.allow(&modifiers(&[])));
+        assert!(filter.allow(&key(Linux::KeyDelete, 1)));
+    }
+
+    #[test]
+    fn modifier_state_completing_denied_combination_is_dropped() {
+        let mut filter = deny_cad();
+        assert!(filter.allow(&key(Linux::KeyDelete, 1)));
+        assert!(filter.allow(&modifiers(&[Linux::KeyLeftCtrl])));
+        assert!(!filter.allow(&modifiers(&[Linux::KeyLeftCtrl, Linux::KeyLeftAlt])));
+        assert!(filter.allow(&key(Linux::KeyDelete, 0)));
+        assert!(filter.allow(&modifiers(&[Linux::KeyLeftCtrl, Linux::KeyLeftAlt])));
+    }
+
+    #[test]
+    fn devices_that_are_not_authorized_are_denied_by_default() {
+        let guest = InputPermissions {
+            keyboard: false,
+            ..Default::default()
+        };
+        let configured = HashMap::from([("fp-guest".to_owned(), guest.clone())]);
+        let authorized = Arc::new(RwLock::new(HashMap::from([
+            ("fp-guest".to_owned(), "guest".to_owned()),
+            ("fp-full".to_owned(), "full".to_owned()),
+        ])));
+        let mut permissions = DevicePermissions::new(configured, authorized.clone(), None);
+        assert_eq!(permissions.get("fp-guest"), guest);
+        assert_eq!(permissions.get("fp-full"), InputPermissions::default());
+        assert_eq!(permissions.get("fp-other"), InputPermissions::none());
+        // authorized at runtime
+        authorized
+            .write()
+            .expect("lock")
+            .insert("fp-other".to_owned(), "other".to_owned());
+        assert_eq!(permissions.get("fp-other"), InputPermissions::default());
+        // configured permissions follow a key transition
+        permissions.key_transition("fp-guest", "fp-rotated".to_owned());
+        assert_eq!(permissions.get("fp-rotated"), guest);
+    }
+
+    #[test]
+    fn devices_that_are_not_authorized_get_the_granted_permissions() {
+        let granted = InputPermissions {
+            keyboard: false,
+            ..Default::default()
+        };
+        let permissions = DevicePermissions::new(
+            Default::default(),
+            Default::default(),
+            Some(granted.clone()),
+        );
+        assert_eq!(permissions.get("fp-ca"), granted);
+    }
+}
@@ -0,0 +1,42 @@
+use input_event::{Event, PointerEvent};
+use lan_mouse_ipc::PointerConfig;
+
+/// apply the per client pointer settings to an event
+pub(crate) fn apply(config: &PointerConfig, event: Event) -> Event {
+    match event {
+        Event::Pointer(e) => Event::Pointer(apply_pointer(config, e)),
+        e => e,
+    }
+}
+
+fn apply_pointer(config: &PointerConfig, event: PointerEvent) -> PointerEvent {
+    let scroll_factor = if config.natural_scrolling {
+        -config.scroll_speed
+    } else {
+        config.scroll_speed
+    };
+    match event {
+        PointerEvent::Motion { time, dx, dy } => {
+            let gain = config
+                .acceleration
+                .map(|a| a.gain(dx.hypot(dy)))
+                .unwrap_or(1.0);
+            let factor = config.sensitivity * gain;
+            PointerEvent::Motion {
+                time,
+                dx: dx * factor,
+                dy: dy * factor,
+            }
+        }
+        PointerEvent::Axis { time, axis, value } => PointerEvent::Axis {
+            time,
+            axis,
+            value: value * scroll_factor,
+        },
+        PointerEvent::AxisDiscrete120 { axis, value } => PointerEvent::AxisDiscrete120 {
+            axis,
+            value: (value as f64 * scroll_factor).round() as i32,
+        },
+        e @ PointerEvent::Button { .. } => e,
+    }
+}
@@ -0,0 +1,290 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    net::IpAddr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use lan_mouse_ipc::InboundLimit;
+
+use crate::config::LimitsConfig;
+
+/// handshakes and rejected attempts are counted within this window
+const WINDOW: Duration = Duration::from_secs(60);
+
+/// enforces the [`LimitsConfig`] for inbound connections.
+///
+/// Handshakes are admitted per source ip address before they start,
+/// so banned or throttled hosts can not make this device do any cryptography.
+/// Limits that were hit are queued until [`RateLimiter::take_exceeded`] is called.
+pub(crate) struct RateLimiter {
+    limits: LimitsConfig,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    handshakes: HashMap<IpAddr, Window>,
+    rejections: HashMap<IpAddr, Window>,
+    bans: HashMap<IpAddr, Instant>,
+    /// currently open incoming connections
+    connections: usize,
+    /// the limit of incoming connections was reported
+    at_capacity: bool,
+    exceeded: VecDeque<(IpAddr, InboundLimit)>,
+}
+
+/// number of events since the start of a window
+struct Window {
+    start: Instant,
+    count: u32,
+}
+
+impl Window {
+    fn new(now: Instant) -> Self {
+        Self {
+            start: now,
+            count: 0,
+        }
+    }
+
+    fn expired(&self, now: Instant) -> bool {
+        now.duration_since(self.start) >= WINDOW
+    }
+
+    /// count an event, returns the number of events in the current window
+    fn hit(&mut self, now: Instant) -> u32 {
+        if self.expired(now) {
+            *self = Self::new(now);
+        }
+        self.count += 1;
+        self.count
+    }
+}
+
+impl RateLimiter {
+    pub(crate) fn new(limits: LimitsConfig) -> Self {
+        Self {
+            limits,
+            state: Default::default(),
+        }
+    }
+
+    /// whether a handshake from `ip` may start. An admitted handshake occupies
+    /// a connection slot until it fails or its connection is closed.
+    pub(crate) fn allow_handshake(&self, ip: IpAddr) -> bool {
+        let now = Instant::now();
+        let mut state = self.state.lock().expect("lock");
+        state.bans.retain(|_, until| now < *until);
+        if state.bans.contains_key(&ip) {
+            log::debug!("dropping handshake from {ip}: banned");
+            return false;
+        }
+        let max_connections = self.limits.max_connections;
+        if max_connections > 0 && state.connections >= max_connections {
+            if !state.at_capacity {
+                log::warn!("refusing {ip}: {max_connections} incoming connections open");
+                state.at_capacity = true;
+                state.exceeded.push_back((ip, InboundLimit::MaxConnections));
+            }
+            return false;
+        }
+        let rate = self.limits.handshake_rate;
+        if rate > 0 {
+            state.handshakes.retain(|_, w| !w.expired(now));
+            let count = state
+                .handshakes
+                .entry(ip)
+                .or_insert_with(|| Window::new(now))
+                .hit(now);
+            if count > rate {
+                // reported once per window
+                if count == rate + 1 {
+                    log::warn!("throttling {ip}: more than {rate} handshakes per minute");
+                    state.exceeded.push_back((ip, InboundLimit::HandshakeRate));
+                }
+                return false;
+            }
+        }
+        state.connections += 1;
+        true
+    }
+
+    /// a handshake from `ip` was rejected: the device is not authorized
+    pub(crate) fn rejected(&self, ip: IpAddr) {
+        let now = Instant::now();
+        let mut state = self.state.lock().expect("lock");
+        let ban_after = self.limits.ban_after;
+        if ban_after == 0 {
+            return;
+        }
+        state.rejections.retain(|_, w| !w.expired(now));
+        let count = state
+            .rejections
+            .entry(ip)
+            .or_insert_with(|| Window::new(now))
+            .hit(now);
+        if count >= ban_after {
+            let duration = self.limits.ban_duration;
+            log::warn!("banning {ip} for {duration:?}: {count} rejected attempts");
+            state.rejections.remove(&ip);
+            state.bans.insert(ip, now + duration);
+            state.exceeded.push_back((ip, InboundLimit::Banned));
+        }
+    }
+
+    /// lift all bans, e.g. because a device was authorized
+    pub(crate) fn lift_bans(&self) {
+        let mut state = self.state.lock().expect("lock");
+        state.bans.clear();
+        state.rejections.clear();
+    }
+
+    /// an admitted handshake failed, its connection slot is free again
+    pub(crate) fn handshake_failed(&self) {
+        self.release_slot();
+    }
+
+    /// a connection closed, its connection slot is free again
+    pub(crate) fn disconnected(&self) {
+        self.release_slot();
+    }
+
+    fn release_slot(&self) {
+        let mut state = self.state.lock().expect("lock");
+        state.connections = state.connections.saturating_sub(1);
+        state.at_capacity = false;
+    }
+
+    /// events per second a connection may send (0: unlimited)
+    pub(crate) fn max_event_rate(&self) -> u32 {
+        self.limits.max_event_rate
+    }
+
+    /// limits hit since the last call
+    pub(crate) fn take_exceeded(&self) -> Vec<(IpAddr, InboundLimit)> {
+        self.state
+            .lock()
+            .expect("lock")
+            .exceeded
+            .drain(..)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IP: IpAddr = IpAddr::V4(std::net::Ipv4Addr::new(192, 168, 1, 2));
+    const OTHER: IpAddr = IpAddr::V4(std::net::Ipv4Addr::new(192, 168, 1, 3));
+
+    #[test]
+    fn window_counts_until_expired() {
+        let start = Instant::now();
+        let mut window = Window::new(start);
+        assert_eq!(window.hit(start), 1);
+        assert_eq!(window.hit(start + WINDOW / 2), 2);
+        assert!(!window.expired(start + WINDOW / 2));
+        assert!(window.expired(start + WINDOW));
+        // a new window starts
+        assert_eq!(window.hit(start + WINDOW), 1);
+        assert_eq!(window.start, start + WINDOW);
+    }
+
+    #[test]
+    fn handshakes_are_throttled_per_ip() {
+        let limiter = RateLimiter::new(LimitsConfig {
+            handshake_rate: 3,
+            ..Default::default()
+        });
+        for _ in 0..3 {
+            assert!(limiter.allow_handshake(IP));
+        }
+        assert!(!limiter.allow_handshake(IP));
+        assert!(!limiter.allow_handshake(IP));
+        assert!(limiter.allow_handshake(OTHER));
+        // reported once per window
+        assert_eq!(
+            limiter.take_exceeded(),
+            vec![(IP, InboundLimit::HandshakeRate)]
+        );
+        assert!(limiter.take_exceeded().is_empty());
+    }
+
+    #[test]
+    fn rejected_hosts_are_banned() {
+        let limiter = RateLimiter::new(LimitsConfig {
+            ban_after: 2,
+            ..Default::default()
+        });
+        limiter.rejected(IP);
+        assert!(limiter.allow_handshake(IP));
+        limiter.rejected(IP);
+        assert!(!limiter.allow_handshake(IP));
+        assert!(limiter.allow_handshake(OTHER));
+        assert_eq!(limiter.take_exceeded(), vec![(IP, InboundLimit::Banned)]);
+        limiter.lift_bans();
+        assert!(limiter.allow_handshake(IP));
+    }
+
+    #[test]
+    fn bans_expire() {
+        let limiter = RateLimiter::new(LimitsConfig {
+            ban_after: 1,
+            ban_duration: Duration::ZERO,
+            ..Default::default()
+        });
+        limiter.rejected(IP);
+        assert!(limiter.allow_handshake(IP));
+    }
+
+    #[test]
+    fn connections_are_limited() {
+        let limiter = RateLimiter::new(LimitsConfig {
+            max_connections: 1,
+            ..Default::default()
+        });
+        assert!(limiter.allow_handshake(IP));
+        // the connection is established
+        assert!(!limiter.allow_handshake(OTHER));
+        assert!(!limiter.allow_handshake(OTHER));
+        assert_eq!(
+            limiter.take_exceeded(),
+            vec![(OTHER, InboundLimit::MaxConnections)]
+        );
+        limiter.disconnected();
+        assert!(limiter.allow_handshake(OTHER));
+    }
+
+    #[test]
+    fn handshakes_in_progress_occupy_a_connection() {
+        let limiter = RateLimiter::new(LimitsConfig {
+            max_connections: 2,
+            ..Default::default()
+        });
+        // concurrent handshakes, none of them finished yet
+        assert!(limiter.allow_handshake(IP));
+        assert!(limiter.allow_handshake(OTHER));
+        assert!(!limiter.allow_handshake(OTHER));
+        limiter.handshake_failed();
+        assert!(limiter.allow_handshake(OTHER));
+        assert!(!limiter.allow_handshake(IP));
+    }
+
+    #[test]
+    fn zero_disables_limits() {
+        let limiter = RateLimiter::new(LimitsConfig {
+            handshake_rate: 0,
+            ban_after: 0,
+            max_event_rate: 0,
+            max_connections: 0,
+            ..Default::default()
+        });
+        for _ in 0..1000 {
+            limiter.rejected(IP);
+            assert!(limiter.allow_handshake(IP));
+        }
+        assert!(limiter.take_exceeded().is_empty());
+    }
+}
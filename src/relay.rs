@@ -0,0 +1,583 @@
+use std::{
+    collections::HashMap,
+    io,
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr},
+    time::{Duration, Instant},
+};
+
+use clap::Args;
+use local_channel::mpsc::Sender;
+use thiserror::Error;
+use tokio::net::{UdpSocket, lookup_host};
+
+/// default port of the relay
+pub const DEFAULT_RELAY_PORT: u16 = 4243;
+
+/// registrations are renewed in this interval, which also keeps NAT mappings open
+const REGISTER_INTERVAL: Duration = Duration::from_secs(20);
+/// devices that did not renew their registration are forgotten
+const REGISTRATION_TIMEOUT: Duration = Duration::from_secs(60);
+/// time for a device to accept a session
+const PENDING_TIMEOUT: Duration = Duration::from_secs(10);
+/// sessions without traffic are closed
+const SESSION_TIMEOUT: Duration = Duration::from_secs(60);
+/// time to wait for the relay to answer a connection request
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(5);
+/// time to wait before registering again after an error
+const RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+const MAX_MESSAGE_SIZE: usize = 256;
+
+#[derive(Debug, Error)]
+pub enum RelayError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("could not resolve relay `{0}`")]
+    Resolve(String),
+    #[error("connecting via a relay requires the fingerprint of the client")]
+    NoFingerprint,
+    #[error("the client is not registered at the relay")]
+    UnknownPeer,
+    #[error("no response from the relay")]
+    Timeout,
+}
+
+#[derive(Args, Clone, Debug, Eq, PartialEq)]
+pub struct RelayArgs {
+    /// port to listen on
+    #[arg(long, short, default_value_t = DEFAULT_RELAY_PORT)]
+    port: u16,
+}
+
+/// messages exchanged with the relay.
+///
+/// DTLS records start with a content type of at least 20,
+/// so control messages are distinguished from them by their first byte.
+#[derive(Debug, PartialEq)]
+enum Message {
+    /// register the sender under the fingerprint of its certificate
+    Register(String),
+    /// the registration was renewed
+    Registered,
+    /// the fingerprint is registered from another address,
+    /// which has to expire before it can be registered again
+    Taken,
+    /// open a session to the device registered with the given fingerprint
+    Connect(String),
+    /// no device is registered with the requested fingerprint
+    Unknown,
+    /// the device at `addr` opened a session,
+    /// which is accepted by sending [`Message::Accept`] from a new socket
+    Incoming { session: u64, addr: SocketAddr },
+    /// accept a session
+    Accept(u64),
+    /// the session was accepted by the device at the given address,
+    /// datagrams are forwarded from now on
+    Ready(SocketAddr),
+}
+
+impl Message {
+    fn encode(&self) -> Vec<u8> {
+        match self {
+            Message::Register(fingerprint) => [&[1], fingerprint.as_bytes()].concat(),
+            Message::Registered => vec![2],
+            Message::Connect(fingerprint) => [&[3], fingerprint.as_bytes()].concat(),
+            Message::Unknown => vec![4],
+            Message::Incoming { session, addr } => [
+                &[5][..],
+                &session.to_be_bytes(),
+                addr.to_string().as_bytes(),
+            ]
+            .concat(),
+            Message::Accept(session) => [&[6][..], &session.to_be_bytes()].concat(),
+            Message::Ready(addr) => [&[7], addr.to_string().as_bytes()].concat(),
+            Message::Taken => vec![8],
+        }
+    }
+
+    fn decode(buf: &[u8]) -> Option<Self> {
+        let (&kind, payload) = buf.split_first()?;
+        let text = || std::str::from_utf8(payload).ok();
+        let session = || {
+            payload
+                .get(..8)
+                .map(|b| u64::from_be_bytes(b.try_into().expect("8 bytes")))
+        };
+        match kind {
+            1 => Some(Message::Register(text()?.to_owned())),
+            2 => Some(Message::Registered),
+            3 => Some(Message::Connect(text()?.to_owned())),
+            4 => Some(Message::Unknown),
+            5 => Some(Message::Incoming {
+                session: session()?,
+                addr: std::str::from_utf8(&payload[8..]).ok()?.parse().ok()?,
+            }),
+            6 => Some(Message::Accept(session()?)),
+            7 => Some(Message::Ready(text()?.parse().ok()?)),
+            8 => Some(Message::Taken),
+            _ => None,
+        }
+    }
+}
+
+/// whether the datagram is a DTLS record (content types 20 - 25)
+fn is_dtls(packet: &[u8]) -> bool {
+    matches!(packet.first(), Some(20..=25))
+}
+
+/// run the relay: devices register under the fingerprint of their certificate,
+/// other devices open sessions to them by fingerprint.
+/// Datagrams of a session are forwarded as they are, the relay can not decrypt them.
+pub async fn run(args: RelayArgs) -> Result<(), RelayError> {
+    let addr = SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), args.port);
+    let socket = UdpSocket::bind(addr).await?;
+    log::info!("relay listening on {addr}");
+    tokio::select! {
+        _ = serve(socket) => {}
+        _ = tokio::signal::ctrl_c() => {}
+    }
+    Ok(())
+}
+
+/// relay the datagrams received on `socket`
+async fn serve(socket: UdpSocket) {
+    let mut relay = Relay::default();
+    let mut expire = tokio::time::interval(PENDING_TIMEOUT);
+    let mut buf = vec![0u8; u16::MAX as usize];
+    loop {
+        tokio::select! {
+            r = socket.recv_from(&mut buf) => {
+                let (len, src) = match r {
+                    Ok(r) => r,
+                    Err(e) => {
+                        log::warn!("recv: {e}");
+                        continue;
+                    }
+                };
+                for (packet, dst) in relay.handle(&buf[..len], src) {
+                    if let Err(e) = socket.send_to(&packet, dst).await {
+                        log::warn!("send to {dst}: {e}");
+                    }
+                }
+            }
+            _ = expire.tick() => relay.expire(),
+        }
+    }
+}
+
+/// an address and when it was last seen
+struct Peer {
+    addr: SocketAddr,
+    seen: Instant,
+}
+
+impl Peer {
+    fn new(addr: SocketAddr) -> Self {
+        Self {
+            addr,
+            seen: Instant::now(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Relay {
+    /// registered devices by fingerprint
+    devices: HashMap<String, Peer>,
+    /// sessions waiting to be accepted, by the device that opened them
+    pending: HashMap<u64, Peer>,
+    /// forwarding table of established sessions (in both directions)
+    sessions: HashMap<SocketAddr, Peer>,
+}
+
+impl Relay {
+    /// handle a datagram from `src`, returns the datagrams to send
+    fn handle(&mut self, packet: &[u8], src: SocketAddr) -> Vec<(Vec<u8>, SocketAddr)> {
+        if is_dtls(packet) {
+            return match self.sessions.get_mut(&src) {
+                Some(peer) => {
+                    peer.seen = Instant::now();
+                    vec![(packet.to_vec(), peer.addr)]
+                }
+                None => vec![],
+            };
+        }
+        match Message::decode(packet) {
+            Some(Message::Register(fingerprint)) => match self.devices.get(&fingerprint) {
+                // registering does not prove possession of the key,
+                // so a live registration is never taken over
+                Some(device)
+                    if device.addr != src && device.seen.elapsed() < REGISTRATION_TIMEOUT =>
+                {
+                    log::warn!("{src}: {fingerprint} is registered from {}", device.addr);
+                    vec![(Message::Taken.encode(), src)]
+                }
+                device => {
+                    if device.is_none_or(|d| d.addr != src) {
+                        log::info!("{src} registered as {fingerprint}");
+                    }
+                    self.devices.insert(fingerprint, Peer::new(src));
+                    vec![(Message::Registered.encode(), src)]
+                }
+            },
+            Some(Message::Connect(fingerprint)) => {
+                self.close_session(src);
+                match self.devices.get(&fingerprint) {
+                    Some(device) => {
+                        let session = rand::random();
+                        log::info!("{src} opens a session to {fingerprint} ({})", device.addr);
+                        self.pending.insert(session, Peer::new(src));
+                        let incoming = Message::Incoming { session, addr: src };
+                        vec![(incoming.encode(), device.addr)]
+                    }
+                    None => {
+                        log::info!("{src}: {fingerprint} is not registered");
+                        vec![(Message::Unknown.encode(), src)]
+                    }
+                }
+            }
+            Some(Message::Accept(session)) => match self.pending.remove(&session) {
+                Some(peer) => {
+                    self.close_session(src);
+                    self.sessions.insert(peer.addr, Peer::new(src));
+                    self.sessions.insert(src, Peer::new(peer.addr));
+                    vec![(Message::Ready(src).encode(), peer.addr)]
+                }
+                None => vec![],
+            },
+            _ => vec![],
+        }
+    }
+
+    fn close_session(&mut self, addr: SocketAddr) {
+        if let Some(peer) = self.sessions.remove(&addr) {
+            self.sessions.remove(&peer.addr);
+        }
+    }
+
+    fn expire(&mut self) {
+        self.devices
+            .retain(|_, d| d.seen.elapsed() < REGISTRATION_TIMEOUT);
+        self.pending
+            .retain(|_, p| p.seen.elapsed() < PENDING_TIMEOUT);
+        let idle: Vec<SocketAddr> = self
+            .sessions
+            .iter()
+            .filter(|(_, p)| p.seen.elapsed() >= SESSION_TIMEOUT)
+            .map(|(&a, _)| a)
+            .collect();
+        for addr in idle {
+            self.close_session(addr);
+        }
+    }
+}
+
+/// resolve the address of a relay, given as `host:port` or `host`
+async fn resolve(relay: &str) -> Result<SocketAddr, RelayError> {
+    let addr = match lookup_host(relay).await {
+        Ok(mut addrs) => addrs.next(),
+        Err(_) => lookup_host((relay, DEFAULT_RELAY_PORT)).await?.next(),
+    };
+    addr.ok_or_else(|| RelayError::Resolve(relay.to_owned()))
+}
+
+/// a new UDP socket connected to the relay
+async fn relay_socket(relay: SocketAddr) -> io::Result<UdpSocket> {
+    let local: SocketAddr = match relay {
+        SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+        SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+    };
+    let socket = UdpSocket::bind(local).await?;
+    socket.connect(relay).await?;
+    Ok(socket)
+}
+
+/// open a session through the relay to the device with the given fingerprint.
+/// Returns a socket connected to the relay and the address of the device as seen by the relay.
+pub(crate) async fn open(
+    relay: &str,
+    fingerprint: &str,
+) -> Result<(UdpSocket, SocketAddr), RelayError> {
+    let socket = relay_socket(resolve(relay).await?).await?;
+    socket
+        .send(&Message::Connect(fingerprint.to_owned()).encode())
+        .await?;
+    let mut buf = [0u8; MAX_MESSAGE_SIZE];
+    let ready = async {
+        loop {
+            let len = socket.recv(&mut buf).await?;
+            match Message::decode(&buf[..len]) {
+                Some(Message::Ready(addr)) => return Ok(addr),
+                Some(Message::Unknown) => return Err(RelayError::UnknownPeer),
+                _ => {}
+            }
+        }
+    };
+    let addr = tokio::time::timeout(RESPONSE_TIMEOUT, ready)
+        .await
+        .map_err(|_| RelayError::Timeout)??;
+    Ok((socket, addr))
+}
+
+/// keep this device registered at the relay under the fingerprint of its certificate.
+/// Sessions opened by other devices are accepted and sent to `sessions`
+/// together with the address of the other device.
+pub(crate) async fn register(
+    relay: String,
+    fingerprint: String,
+    sessions: Sender<(UdpSocket, SocketAddr)>,
+) {
+    loop {
+        if let Err(e) = registration(&relay, &fingerprint, &sessions).await {
+            log::warn!("relay {relay}: {e}");
+        }
+        tokio::time::sleep(RETRY_INTERVAL).await;
+    }
+}
+
+async fn registration(
+    relay: &str,
+    fingerprint: &str,
+    sessions: &Sender<(UdpSocket, SocketAddr)>,
+) -> Result<(), RelayError> {
+    let relay_addr = resolve(relay).await?;
+    let socket = relay_socket(relay_addr).await?;
+    let register = Message::Register(fingerprint.to_owned()).encode();
+    let mut renew = tokio::time::interval(REGISTER_INTERVAL);
+    let mut registered = false;
+    let mut buf = [0u8; MAX_MESSAGE_SIZE];
+    loop {
+        tokio::select! {
+            _ = renew.tick() => {
+                socket.send(&register).await?;
+            }
+            len = socket.recv(&mut buf) => match Message::decode(&buf[..len?]) {
+                Some(Message::Registered) if !registered => {
+                    log::info!("registered at relay {relay} ({relay_addr})");
+                    registered = true;
+                }
+                Some(Message::Taken) => {
+                    log::warn!("relay {relay}: {fingerprint} is registered from another address");
+                    registered = false;
+                }
+                Some(Message::Incoming { session, addr }) => {
+                    log::info!("{addr} opened a session via relay {relay}");
+                    let session_socket = relay_socket(relay_addr).await?;
+                    session_socket.send(&Message::Accept(session).encode()).await?;
+                    // the listener may have been terminated in the meantime
+                    let _ = sessions.send((session_socket, addr));
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use local_channel::mpsc::channel;
+    use tokio::task::{LocalSet, spawn_local};
+
+    use super::*;
+
+    fn addr(port: u16) -> SocketAddr {
+        SocketAddr::new(Ipv4Addr::LOCALHOST.into(), port)
+    }
+
+    /// a single datagram sent to `dst`, decoded
+    fn single(out: Vec<(Vec<u8>, SocketAddr)>) -> (Message, SocketAddr) {
+        assert_eq!(out.len(), 1, "expected a single datagram");
+        let (packet, dst) = out.into_iter().next().expect("datagram");
+        (Message::decode(&packet).expect("message"), dst)
+    }
+
+    /// registers `device` as `fp` and opens and accepts a session from `opener`,
+    /// the device accepts from `accepting`
+    fn session(relay: &mut Relay, device: SocketAddr, opener: SocketAddr, accepting: SocketAddr) {
+        relay.handle(&Message::Register("fp".into()).encode(), device);
+        let (incoming, _) = single(relay.handle(&Message::Connect("fp".into()).encode(), opener));
+        let Message::Incoming { session, .. } = incoming else {
+            panic!("expected incoming session, got {incoming:?}");
+        };
+        relay.handle(&Message::Accept(session).encode(), accepting);
+    }
+
+    #[test]
+    fn messages_round_trip() {
+        let messages = [
+            Message::Register("ab:cd".into()),
+            Message::Registered,
+            Message::Taken,
+            Message::Connect("ab:cd".into()),
+            Message::Unknown,
+            Message::Incoming {
+                session: u64::MAX - 1,
+                addr: "[::1]:4242".parse().unwrap(),
+            },
+            Message::Accept(7),
+            Message::Ready(addr(4242)),
+        ];
+        for message in messages {
+            let encoded = message.encode();
+            assert!(!is_dtls(&encoded), "{message:?} is mistaken for DTLS");
+            assert_eq!(Message::decode(&encoded), Some(message));
+        }
+    }
+
+    #[test]
+    fn invalid_messages() {
+        assert_eq!(Message::decode(&[]), None);
+        assert_eq!(Message::decode(&[0]), None);
+        assert_eq!(Message::decode(&[6, 1, 2]), None);
+        assert_eq!(Message::decode(&[7, b'x']), None);
+        assert_eq!(Message::decode(&[3, 0xff]), None);
+        assert!(is_dtls(&[22, 0xfe, 0xfd]));
+    }
+
+    #[test]
+    fn register_and_renew() {
+        let mut relay = Relay::default();
+        let out = relay.handle(&Message::Register("fp".into()).encode(), addr(1));
+        assert_eq!(single(out), (Message::Registered, addr(1)));
+        // renewing from the same address
+        let out = relay.handle(&Message::Register("fp".into()).encode(), addr(1));
+        assert_eq!(single(out), (Message::Registered, addr(1)));
+        assert_eq!(relay.devices["fp"].addr, addr(1));
+    }
+
+    #[test]
+    fn live_registration_is_not_replaced() {
+        let mut relay = Relay::default();
+        relay.handle(&Message::Register("fp".into()).encode(), addr(1));
+        let out = relay.handle(&Message::Register("fp".into()).encode(), addr(2));
+        assert_eq!(single(out), (Message::Taken, addr(2)));
+        assert_eq!(relay.devices["fp"].addr, addr(1));
+    }
+
+    #[test]
+    fn expired_registration_is_replaced() {
+        let mut relay = Relay::default();
+        relay.handle(&Message::Register("fp".into()).encode(), addr(1));
+        let device = relay.devices.get_mut("fp").expect("registered");
+        device.seen -= REGISTRATION_TIMEOUT;
+        let out = relay.handle(&Message::Register("fp".into()).encode(), addr(2));
+        assert_eq!(single(out), (Message::Registered, addr(2)));
+        assert_eq!(relay.devices["fp"].addr, addr(2));
+    }
+
+    #[test]
+    fn connect_to_unknown_device() {
+        let mut relay = Relay::default();
+        let out = relay.handle(&Message::Connect("fp".into()).encode(), addr(1));
+        assert_eq!(single(out), (Message::Unknown, addr(1)));
+        assert!(relay.pending.is_empty());
+    }
+
+    #[test]
+    fn connect_and_accept() {
+        let mut relay = Relay::default();
+        relay.handle(&Message::Register("fp".into()).encode(), addr(1));
+        let out = relay.handle(&Message::Connect("fp".into()).encode(), addr(2));
+        let (incoming, dst) = single(out);
+        assert_eq!(dst, addr(1));
+        let Message::Incoming {
+            session,
+            addr: opener,
+        } = incoming
+        else {
+            panic!("expected incoming session, got {incoming:?}");
+        };
+        assert_eq!(opener, addr(2));
+        // unknown sessions are ignored
+        assert!(
+            relay
+                .handle(&Message::Accept(session ^ 1).encode(), addr(3))
+                .is_empty()
+        );
+        let out = relay.handle(&Message::Accept(session).encode(), addr(3));
+        assert_eq!(single(out), (Message::Ready(addr(3)), addr(2)));
+        // a session is accepted only once
+        assert!(
+            relay
+                .handle(&Message::Accept(session).encode(), addr(4))
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn forwarding() {
+        let mut relay = Relay::default();
+        session(&mut relay, addr(1), addr(2), addr(3));
+        let record = [22, 0xfe, 0xfd, 1, 2, 3];
+        assert_eq!(
+            relay.handle(&record, addr(2)),
+            vec![(record.to_vec(), addr(3))]
+        );
+        assert_eq!(
+            relay.handle(&record, addr(3)),
+            vec![(record.to_vec(), addr(2))]
+        );
+        // records from outside of a session are dropped
+        assert!(relay.handle(&record, addr(1)).is_empty());
+        assert!(relay.handle(&record, addr(4)).is_empty());
+    }
+
+    #[test]
+    fn new_session_closes_previous() {
+        let mut relay = Relay::default();
+        session(&mut relay, addr(1), addr(2), addr(3));
+        // the opener connects again
+        relay.handle(&Message::Connect("fp".into()).encode(), addr(2));
+        let record = [23, 0xfe, 0xfd];
+        assert!(relay.handle(&record, addr(2)).is_empty());
+        assert!(relay.handle(&record, addr(3)).is_empty());
+    }
+
+    #[test]
+    fn idle_sessions_expire() {
+        let mut relay = Relay::default();
+        session(&mut relay, addr(1), addr(2), addr(3));
+        for peer in relay.sessions.values_mut() {
+            peer.seen -= SESSION_TIMEOUT;
+        }
+        relay.expire();
+        assert!(relay.sessions.is_empty());
+        assert!(relay.devices.contains_key("fp"));
+    }
+
+    #[tokio::test]
+    async fn loopback_round_trip() {
+        LocalSet::new()
+            .run_until(async {
+                let socket = UdpSocket::bind(addr(0)).await.unwrap();
+                let relay = socket.local_addr().unwrap().to_string();
+                spawn_local(serve(socket));
+
+                let (tx, mut rx) = channel();
+                spawn_local(register(relay.clone(), "fp-b".into(), tx));
+
+                // wait for the registration
+                let (a, b_addr) = loop {
+                    match open(&relay, "fp-b").await {
+                        Ok(session) => break session,
+                        Err(RelayError::UnknownPeer) => {
+                            tokio::time::sleep(Duration::from_millis(10)).await
+                        }
+                        Err(e) => panic!("{e}"),
+                    }
+                };
+                let (b, a_addr) = rx.recv().await.expect("session");
+                assert_eq!(a_addr, a.local_addr().unwrap());
+                assert_eq!(b_addr, b.local_addr().unwrap());
+
+                let mut buf = [0u8; 16];
+                a.send(&[22, 1, 2, 3]).await.unwrap();
+                let len = b.recv(&mut buf).await.unwrap();
+                assert_eq!(&buf[..len], &[22, 1, 2, 3]);
+                b.send(&[23, 4, 5]).await.unwrap();
+                let len = a.recv(&mut buf).await.unwrap();
+                assert_eq!(&buf[..len], &[23, 4, 5]);
+            })
+            .await;
+    }
+}
@@ -0,0 +1,431 @@
+use local_channel::mpsc::{Receiver, Sender, channel};
+use tokio::task::{JoinHandle, spawn_local};
+use tokio_util::sync::CancellationToken;
+
+/// watches the screen lock state of this device and
+/// locks / unlocks the screen on request.
+///
+/// On Linux the state is observed on the D-Bus session bus
+/// (`org.freedesktop.ScreenSaver`) and through the `LockedHint`
+/// of the logind session on the system bus.
+/// Locking and unlocking is requested through logind.
+/// The bus addresses are taken from `DBUS_SESSION_BUS_ADDRESS`
+/// and `DBUS_SYSTEM_BUS_ADDRESS`, if set.
+pub(crate) struct ScreenLock {
+    cancellation_token: CancellationToken,
+    task: Option<JoinHandle<()>>,
+    request_tx: Sender<bool>,
+    event_rx: Receiver<bool>,
+}
+
+struct ScreenLockTask {
+    request_rx: Receiver<bool>,
+    event_tx: Sender<bool>,
+    cancellation_token: CancellationToken,
+}
+
+impl ScreenLock {
+    pub(crate) fn new() -> Self {
+        let (request_tx, request_rx) = channel();
+        let (event_tx, event_rx) = channel();
+        let cancellation_token = CancellationToken::new();
+        let screen_lock_task = ScreenLockTask {
+            request_rx,
+            event_tx,
+            cancellation_token: cancellation_token.clone(),
+        };
+        let task = Some(spawn_local(screen_lock_task.run()));
+        Self {
+            cancellation_token,
+            task,
+            request_tx,
+            event_rx,
+        }
+    }
+
+    /// lock (true) or unlock (false) the screen of this device
+    pub(crate) fn set_locked(&self, locked: bool) {
+        self.request_tx.send(locked).expect("channel closed");
+    }
+
+    /// the screen of this device was locked (true) or unlocked (false)
+    pub(crate) async fn event(&mut self) -> bool {
+        self.event_rx.recv().await.expect("channel closed")
+    }
+
+    pub(crate) async fn terminate(&mut self) {
+        self.cancellation_token.cancel();
+        self.task.take().expect("task").await.expect("join error");
+    }
+}
+
+impl ScreenLockTask {
+    async fn run(mut self) {
+        let cancellation_token = self.cancellation_token.clone();
+        tokio::select! {
+            _ = self.do_screen_lock() => {},
+            _ = cancellation_token.cancelled() => {},
+        }
+    }
+
+    #[cfg(not(all(unix, not(target_os = "macos"))))]
+    async fn do_screen_lock(&mut self) {
+        log::warn!("screen lock synchronization is not supported on this platform");
+        while let Some(locked) = self.request_rx.recv().await {
+            log::warn!(
+                "can not {} the screen",
+                if locked { "lock" } else { "unlock" }
+            );
+        }
+    }
+
+    #[cfg(all(unix, not(target_os = "macos")))]
+    async fn do_screen_lock(&mut self) {
+        use futures::StreamExt;
+
+        let session_bus = zbus::Connection::session()
+            .await
+            .inspect_err(|e| log::warn!("screen lock: session bus unavailable: {e}"))
+            .ok();
+        let system_bus = zbus::Connection::system()
+            .await
+            .inspect_err(|e| log::warn!("screen lock: system bus unavailable: {e}"))
+            .ok();
+
+        let mut screensaver = match &session_bus {
+            Some(bus) => dbus::screensaver_stream(bus)
+                .await
+                .inspect_err(|e| log::warn!("screen lock: can not watch screensaver: {e}"))
+                .ok(),
+            None => None,
+        };
+        let mut session = match &system_bus {
+            Some(bus) => dbus::logind_session_stream(bus)
+                .await
+                .inspect_err(|e| log::warn!("screen lock: can not watch logind session: {e}"))
+                .ok(),
+            None => None,
+        };
+
+        loop {
+            tokio::select! {
+                msg = async { screensaver.as_mut()?.next().await }, if screensaver.is_some() => {
+                    match msg.map(|m| dbus::screensaver_active(&m?)) {
+                        Some(Ok(locked)) => self.event_tx.send(locked).expect("channel closed"),
+                        Some(Err(e)) => log::warn!("screen lock: invalid screensaver signal: {e}"),
+                        None => screensaver = None,
+                    }
+                }
+                msg = async { session.as_mut()?.next().await }, if session.is_some() => {
+                    match msg.map(|m| dbus::session_locked_hint(&m?)) {
+                        Some(Ok(Some(locked))) => self.event_tx.send(locked).expect("channel closed"),
+                        Some(Ok(None)) => {}
+                        Some(Err(e)) => log::warn!("screen lock: invalid logind signal: {e}"),
+                        None => session = None,
+                    }
+                }
+                request = self.request_rx.recv() => {
+                    let Some(locked) = request else { break };
+                    match &system_bus {
+                        Some(bus) => {
+                            if let Err(e) = dbus::set_session_locked(bus, locked).await {
+                                log::warn!("screen lock: logind request failed: {e}");
+                            }
+                        }
+                        None => log::warn!("screen lock: can not reach logind"),
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+mod dbus {
+    use std::collections::HashMap;
+
+    use zbus::{
+        Connection, MatchRule, Message, MessageStream,
+        message::Type,
+        zvariant::{OwnedObjectPath, OwnedValue},
+    };
+
+    const LOGIND: &str = "org.freedesktop.login1";
+    const LOGIND_PATH: &str = "/org/freedesktop/login1";
+    const LOGIND_MANAGER: &str = "org.freedesktop.login1.Manager";
+    const LOGIND_SESSION: &str = "org.freedesktop.login1.Session";
+    const SCREENSAVER: &str = "org.freedesktop.ScreenSaver";
+
+    /// stream of `ActiveChanged` signals of the screensaver
+    pub(super) async fn screensaver_stream(bus: &Connection) -> zbus::Result<MessageStream> {
+        let rule = MatchRule::builder()
+            .msg_type(Type::Signal)
+            .interface(SCREENSAVER)?
+            .member("ActiveChanged")?
+            .build();
+        MessageStream::for_match_rule(rule, bus, None).await
+    }
+
+    pub(super) fn screensaver_active(msg: &Message) -> zbus::Result<bool> {
+        msg.body().deserialize::<bool>()
+    }
+
+    /// stream of property changes of the logind session this process belongs to
+    pub(super) async fn logind_session_stream(bus: &Connection) -> zbus::Result<MessageStream> {
+        let session: OwnedObjectPath = bus
+            .call_method(
+                Some(LOGIND),
+                LOGIND_PATH,
+                Some(LOGIND_MANAGER),
+                "GetSession",
+                &("auto",),
+            )
+            .await?
+            .body()
+            .deserialize()?;
+        log::debug!("screen lock: watching logind session {}", session.as_str());
+        let rule = MatchRule::builder()
+            .msg_type(Type::Signal)
+            .sender(LOGIND)?
+            .path(session)?
+            .interface("org.freedesktop.DBus.Properties")?
+            .member("PropertiesChanged")?
+            .arg(0, LOGIND_SESSION)?
+            .build();
+        MessageStream::for_match_rule(rule, bus, None).await
+    }
+
+    /// the `LockedHint` of the session, if it changed
+    pub(super) fn session_locked_hint(msg: &Message) -> zbus::Result<Option<bool>> {
+        let (_, changed, _): (String, HashMap<String, OwnedValue>, Vec<String>) =
+            msg.body().deserialize()?;
+        match changed.get("LockedHint") {
+            Some(v) => Ok(Some(bool::try_from(v)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// ask logind to lock or unlock the current session.
+    /// Whether unlocking has an effect depends on the screen locker.
+    pub(super) async fn set_session_locked(bus: &Connection, locked: bool) -> zbus::Result<()> {
+        let method = if locked { "Lock" } else { "Unlock" };
+        bus.call_method(
+            Some(LOGIND),
+            format!("{LOGIND_PATH}/session/auto"),
+            Some(LOGIND_SESSION),
+            method,
+            &(),
+        )
+        .await?;
+        Ok(())
+    }
+}
+
+/// the D-Bus helpers tested against a stand-in logind and screensaver
+/// on a private bus (requires `dbus-daemon`)
+#[cfg(all(test, unix, not(target_os = "macos")))]
+mod tests {
+    use std::{
+        collections::HashMap,
+        io::{BufRead, BufReader},
+        process::{Child, Command, Stdio},
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use futures::StreamExt;
+    use zbus::{
+        Connection, interface,
+        zvariant::{OwnedObjectPath, Value},
+    };
+
+    use super::dbus;
+
+    const SESSION: &str = "/org/freedesktop/login1/session/_1";
+
+    /// a private bus daemon, terminated when dropped
+    struct PrivateBus {
+        daemon: Child,
+        address: String,
+    }
+
+    impl PrivateBus {
+        fn start() -> Option<Self> {
+            let mut daemon = Command::new("dbus-daemon")
+                .args(["--session", "--nofork", "--print-address"])
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null())
+                .spawn()
+                .ok()?;
+            let mut address = String::new();
+            BufReader::new(daemon.stdout.as_mut()?)
+                .read_line(&mut address)
+                .ok()?;
+            let address = address.trim().to_owned();
+            Some(Self { daemon, address })
+        }
+
+        async fn connect(&self) -> Connection {
+            zbus::connection::Builder::address(self.address.as_str())
+                .expect("bus address")
+                .build()
+                .await
+                .expect("connect to private bus")
+        }
+    }
+
+    impl Drop for PrivateBus {
+        fn drop(&mut self) {
+            let _ = self.daemon.kill();
+            let _ = self.daemon.wait();
+        }
+    }
+
+    struct Manager;
+
+    #[interface(name = "org.freedesktop.login1.Manager")]
+    impl Manager {
+        fn get_session(&self, _id: &str) -> OwnedObjectPath {
+            OwnedObjectPath::try_from(SESSION).expect("object path")
+        }
+    }
+
+    /// records the lock requests of the session
+    struct Session {
+        requests: Arc<Mutex<Vec<&'static str>>>,
+    }
+
+    #[interface(name = "org.freedesktop.login1.Session")]
+    impl Session {
+        fn lock(&self) {
+            self.requests.lock().expect("lock").push("Lock");
+        }
+
+        fn unlock(&self) {
+            self.requests.lock().expect("lock").push("Unlock");
+        }
+    }
+
+    /// stand-in logind on the given bus
+    async fn logind(bus: &PrivateBus) -> (Connection, Arc<Mutex<Vec<&'static str>>>) {
+        let requests = Arc::new(Mutex::new(vec![]));
+        let conn = bus.connect().await;
+        let server = conn.object_server();
+        server
+            .at("/org/freedesktop/login1", Manager)
+            .await
+            .expect("serve manager");
+        server
+            .at(
+                "/org/freedesktop/login1/session/auto",
+                Session {
+                    requests: requests.clone(),
+                },
+            )
+            .await
+            .expect("serve session");
+        conn.request_name("org.freedesktop.login1")
+            .await
+            .expect("request name");
+        (conn, requests)
+    }
+
+    async fn next(stream: &mut zbus::MessageStream) -> zbus::Message {
+        tokio::time::timeout(Duration::from_secs(5), stream.next())
+            .await
+            .expect("timeout")
+            .expect("stream ended")
+            .expect("message")
+    }
+
+    #[tokio::test]
+    async fn lock_and_unlock_session() {
+        let Some(bus) = PrivateBus::start() else {
+            return eprintln!("dbus-daemon not available, skipping");
+        };
+        let (_logind, requests) = logind(&bus).await;
+        let conn = bus.connect().await;
+        dbus::set_session_locked(&conn, true).await.expect("lock");
+        dbus::set_session_locked(&conn, false)
+            .await
+            .expect("unlock");
+        assert_eq!(*requests.lock().expect("lock"), ["Lock", "Unlock"]);
+    }
+
+    #[tokio::test]
+    async fn session_locked_hint() {
+        let Some(bus) = PrivateBus::start() else {
+            return eprintln!("dbus-daemon not available, skipping");
+        };
+        let (logind, _) = logind(&bus).await;
+        let conn = bus.connect().await;
+        let mut stream = dbus::logind_session_stream(&conn)
+            .await
+            .expect("watch session");
+
+        let changed = HashMap::from([("LockedHint", Value::from(true))]);
+        let body = (
+            "org.freedesktop.login1.Session",
+            changed,
+            Vec::<&str>::new(),
+        );
+        logind
+            .emit_signal(
+                None::<()>,
+                SESSION,
+                "org.freedesktop.DBus.Properties",
+                "PropertiesChanged",
+                &body,
+            )
+            .await
+            .expect("emit");
+        let msg = next(&mut stream).await;
+        assert_eq!(dbus::session_locked_hint(&msg).expect("hint"), Some(true));
+
+        // other properties do not change the lock state
+        let changed = HashMap::from([("IdleHint", Value::from(true))]);
+        let body = (
+            "org.freedesktop.login1.Session",
+            changed,
+            Vec::<&str>::new(),
+        );
+        logind
+            .emit_signal(
+                None::<()>,
+                SESSION,
+                "org.freedesktop.DBus.Properties",
+                "PropertiesChanged",
+                &body,
+            )
+            .await
+            .expect("emit");
+        let msg = next(&mut stream).await;
+        assert_eq!(dbus::session_locked_hint(&msg).expect("hint"), None);
+    }
+
+    #[tokio::test]
+    async fn screensaver_active_changed() {
+        let Some(bus) = PrivateBus::start() else {
+            return eprintln!("dbus-daemon not available, skipping");
+        };
+        let screensaver = bus.connect().await;
+        let conn = bus.connect().await;
+        let mut stream = dbus::screensaver_stream(&conn)
+            .await
+            .expect("watch screensaver");
+        for active in [true, false] {
+            screensaver
+                .emit_signal(
+                    None::<()>,
+                    "/org/freedesktop/ScreenSaver",
+                    "org.freedesktop.ScreenSaver",
+                    "ActiveChanged",
+                    &active,
+                )
+                .await
+                .expect("emit");
+            let msg = next(&mut stream).await;
+            assert_eq!(dbus::screensaver_active(&msg).expect("active"), active);
+        }
+    }
+}
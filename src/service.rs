@@ -1,28 +1,47 @@
 use crate::{
+    audit::AuditLog,
+    ca::{CaError, CertificateAuthority},
     capture::{Capture, CaptureType, ICaptureEvent},
     client::ClientManager,
-    config::Config,
-    connect::LanMouseConnection,
+    config::{self, Config},
+    connect::{ConnectionEvent, LanMouseConnection},
     crypto,
+    discovery::{Discovery, DiscoveryEvent},
     dns::{DnsEvent, DnsResolver},
     emulation::{Emulation, EmulationEvent},
     listen::{LanMouseListener, ListenerCreationError},
+    network::NetworkMonitor,
+    pairing::{PAIRING_WINDOW, Pairing, PairingEvent},
+    permissions::DevicePermissions,
+    rate_limit::RateLimiter,
+    screen_lock::ScreenLock,
+    wol,
 };
-use futures::StreamExt;
+use futures::{StreamExt, future};
 use hickory_resolver::ResolveError;
 use lan_mouse_ipc::{
-    AsyncFrontendListener, ClientConfig, ClientHandle, ClientState, FrontendEvent, FrontendRequest,
-    IpcError, IpcListenerCreationError, Position, Status,
+    AsyncFrontendListener, AuditEvent, AuditQuery, ClientConfig, ClientHandle, ClientState,
+    DiscoveredPeer, FrontendEvent, FrontendRequest, IpcError, IpcListenerCreationError,
+    PointerConfig, Position, ReleaseReason, Status,
 };
+use local_channel::mpsc::{Receiver, channel};
 use log;
 use std::{
     collections::{HashMap, HashSet, VecDeque},
     io,
     net::{IpAddr, SocketAddr},
+    path::PathBuf,
+    rc::Rc,
     sync::{Arc, RwLock},
+    time::{Duration, Instant, SystemTime},
 };
 use thiserror::Error;
-use tokio::{process::Command, signal, sync::Notify};
+use tokio::{process::Command, signal, sync::Notify, time::Interval};
+
+/// frontends are warned when the certificate expires within this many days
+const CERT_EXPIRY_WARNING_DAYS: i64 = 30;
+/// interval in which the expiry of the certificate is checked
+const CERT_EXPIRY_CHECK_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
 
 #[derive(Debug, Error)]
 pub enum ServiceError {
@@ -36,6 +55,8 @@ pub enum ServiceError {
     ListenError(#[from] ListenerCreationError),
     #[error("failed to load certificate: `{0}`")]
     Certificate(#[from] crypto::Error),
+    #[error("failed to load certificate authority: `{0}`")]
+    CertificateAuthority(#[from] CaError),
 }
 
 pub struct Service {
@@ -45,16 +66,52 @@ pub struct Service {
     emulation: Emulation,
     /// dns resolver
     resolver: DnsResolver,
+    /// changes of the network addresses of this device
+    network: NetworkMonitor,
+    /// tells outgoing connections to retry failed clients right away
+    network_changed: Rc<Notify>,
+    /// screen lock synchronization (if enabled)
+    screen_lock: Option<ScreenLock>,
+    /// last known screen lock state
+    screen_locked: bool,
+    /// peers may unlock the screen of this device
+    sync_screen_unlock: bool,
+    /// mDNS advertisement and discovery (if enabled)
+    discovery: Option<Discovery>,
+    /// peers found via mDNS by certificate fingerprint
+    discovered_peers: HashMap<String, DiscoveredPeer>,
+    /// pairing with other devices
+    pairing: Pairing,
+    /// paired devices waiting for confirmation (fingerprint -> description)
+    pending_pairings: HashMap<String, String>,
     /// frontend listener
     frontend_listener: AsyncFrontendListener,
     /// authorized public key sha256 fingerprints
     authorized_keys: Arc<RwLock<HashMap<String, String>>>,
+    /// authorizations that are revoked automatically
+    key_expiry: HashMap<String, KeyExpiry>,
+    /// audit log of connections and authorization changes (if enabled)
+    audit: Option<AuditLog>,
+    /// limits for inbound connections
+    rate_limiter: Arc<RateLimiter>,
     /// (outgoing) client information
     client_manager: ClientManager,
+    /// path of the config file
+    config_path: PathBuf,
+    /// clients loaded from the config file (handle -> index in the file)
+    config_clients: HashMap<ClientHandle, usize>,
+    /// certificates pinned or rejected by outgoing connections
+    conn_events: Receiver<ConnectionEvent>,
+    /// changed certificate fingerprints already reported to the frontend
+    rejected_fingerprints: HashMap<ClientHandle, String>,
     /// current port
     port: u16,
     /// the public key fingerprint for (D)TLS
     public_key_fingerprint: String,
+    /// expiry of the certificate of this device
+    cert_expiry: Option<SystemTime>,
+    /// periodic check of the certificate expiry
+    cert_expiry_check: Interval,
     /// notify for pending frontend events
     frontend_event_pending: Notify,
     /// frontend events queued for sending
@@ -63,13 +120,22 @@ pub struct Service {
     capture_status: Status,
     /// status of input emulation (enabled / disabled)
     emulation_status: Status,
-    /// keep track of registered connections to avoid duplicate barriers
-    incoming_conns: HashSet<SocketAddr>,
+    /// addresses of the incoming connections of each device by its certificate
+    /// fingerprint, in the order they were established
+    incoming_conns: HashMap<String, Vec<SocketAddr>>,
     /// map from capture handle to connection info
     incoming_conn_info: HashMap<ClientHandle, Incoming>,
     next_trigger_handle: u64,
 }
 
+/// end of a temporary authorization
+enum KeyExpiry {
+    /// revoked at the given time
+    At(Instant),
+    /// revoked when one of the connections of the device closes
+    Session(HashSet<SocketAddr>),
+}
+
 #[derive(Debug)]
 struct Incoming {
     fingerprint: String,
@@ -80,13 +146,26 @@ struct Incoming {
 impl Service {
     pub async fn new(config: Config) -> Result<Self, ServiceError> {
         let client_manager = ClientManager::default();
-        for client in config.clients() {
+        let mut config_clients = HashMap::new();
+        // relays this device registers at, to be reachable by its clients
+        let mut relays = Vec::new();
+        for (index, client) in config.clients().into_iter().enumerate() {
+            if let Some(relay) = client.relay.as_ref().filter(|r| !relays.contains(*r)) {
+                relays.push(relay.clone());
+            }
             let config = ClientConfig {
                 hostname: client.hostname,
                 fix_ips: client.ips.into_iter().collect(),
                 port: client.port,
                 pos: client.pos,
                 cmd: client.enter_hook,
+                pointer: client.pointer,
+                key_remap: client.key_remap,
+                edge: client.edge,
+                mac_address: client.mac_address,
+                fingerprint: client.fingerprint,
+                transport: client.transport,
+                relay: client.relay,
             };
             let state = ClientState {
                 active: client.active,
@@ -96,29 +175,79 @@ impl Service {
             let handle = client_manager.add_client();
             client_manager.set_config(handle, config);
             client_manager.set_state(handle, state);
+            config_clients.insert(handle, index);
         }
 
         // load certificate
         let cert = crypto::load_or_generate_key_and_cert(config.cert_path())?;
         let public_key_fingerprint = crypto::certificate_fingerprint(&cert);
+        let cert_expiry = crypto::certificate_expiry(&cert)
+            .inspect_err(|e| log::warn!("can not determine certificate expiry: {e}"))
+            .ok();
 
         // create frontend communication adapter, exit if already running
         let frontend_listener = AsyncFrontendListener::new().await?;
 
         let authorized_keys = Arc::new(RwLock::new(config.authorized_fingerprints()));
+        let ca = match config.certificate_authority() {
+            Some(ca) => Some(Arc::new(CertificateAuthority::load(&ca)?)),
+            None => None,
+        };
+        let rate_limiter = Arc::new(RateLimiter::new(config.limits()));
         // listener + connection
-        let listener =
-            LanMouseListener::new(config.port(), cert.clone(), authorized_keys.clone()).await?;
-        let conn = LanMouseConnection::new(cert.clone(), client_manager.clone());
+        let listener = LanMouseListener::new(
+            config.port(),
+            cert.clone(),
+            authorized_keys.clone(),
+            ca.clone(),
+            rate_limiter.clone(),
+            relays,
+        )
+        .await?;
+        let (conn_event_tx, conn_events) = channel();
+        let network_changed: Rc<Notify> = Default::default();
+        let conn = LanMouseConnection::new(
+            cert.clone(),
+            ca,
+            client_manager.clone(),
+            conn_event_tx,
+            network_changed.clone(),
+        );
+        let pairing = Pairing::new(cert.clone());
 
         // input capture + emulation
         let capture_backend = config.capture_backend().map(|b| b.into());
-        let capture = Capture::new(capture_backend, conn, config.release_bind());
+        let capture = Capture::new(
+            capture_backend,
+            conn,
+            client_manager.clone(),
+            config.release_bind(),
+            config.bindings(),
+            config.idle_timeout(),
+            config.idle_center_cursor(),
+        );
         let emulation_backend = config.emulation_backend().map(|b| b.into());
-        let emulation = Emulation::new(emulation_backend, listener);
+        let permissions = DevicePermissions::new(
+            config.input_permissions(),
+            authorized_keys.clone(),
+            config.certificate_authority().map(|ca| ca.permissions),
+        );
+        let emulation = Emulation::new(emulation_backend, listener, permissions);
 
         // create dns resolver
         let resolver = DnsResolver::new()?;
+        let network = NetworkMonitor::new();
+
+        let screen_lock = config.sync_screen_lock().then(ScreenLock::new);
+
+        let discovery = match config.discovery() {
+            true => Discovery::new(config.port(), public_key_fingerprint.clone())
+                .inspect_err(|e| log::warn!("mdns discovery unavailable: {e}"))
+                .ok(),
+            false => None,
+        };
+
+        let audit = config.audit().map(AuditLog::new);
 
         let port = config.port();
         let service = Self {
@@ -126,9 +255,27 @@ impl Service {
             emulation,
             frontend_listener,
             resolver,
+            network,
+            network_changed,
+            screen_lock,
+            screen_locked: false,
+            sync_screen_unlock: config.sync_screen_unlock(),
+            discovery,
+            discovered_peers: Default::default(),
+            pairing,
+            pending_pairings: Default::default(),
             authorized_keys,
+            key_expiry: Default::default(),
+            audit,
+            rate_limiter,
             public_key_fingerprint,
+            cert_expiry,
+            cert_expiry_check: tokio::time::interval(CERT_EXPIRY_CHECK_INTERVAL),
             client_manager,
+            config_path: config.config_path().to_owned(),
+            config_clients,
+            conn_events,
+            rejected_fingerprints: Default::default(),
             frontend_event_pending: Default::default(),
             port,
             pending_frontend_events: Default::default(),
@@ -155,12 +302,20 @@ impl Service {
         }
 
         loop {
+            let next_key_expiry = self.next_key_expiry();
             tokio::select! {
                 request = self.frontend_listener.next() => self.handle_frontend_request(request),
                 _ = self.frontend_event_pending.notified() => self.handle_frontend_pending().await,
                 event = self.emulation.event() => self.handle_emulation_event(event),
                 event = self.capture.event() => self.handle_capture_event(event),
                 event = self.resolver.event() => self.handle_resolver_event(event),
+                _ = self.network.changed() => self.handle_network_change(),
+                locked = screen_lock_event(&mut self.screen_lock) => self.handle_local_screen_lock(locked),
+                event = discovery_event(&mut self.discovery) => self.handle_discovery_event(event),
+                event = self.pairing.event() => self.handle_pairing_event(event),
+                _ = key_expiry(next_key_expiry) => self.revoke_expired_keys(),
+                event = self.conn_events.recv() => self.handle_connection_event(event.expect("channel closed")),
+                _ = self.cert_expiry_check.tick() => self.check_cert_expiry(),
                 r = signal::ctrl_c() => break r.expect("failed to wait for CTRL+C"),
             }
         }
@@ -172,6 +327,18 @@ impl Service {
         self.emulation.terminate().await;
         log::debug!("terminating dns resolver ...");
         self.resolver.terminate().await;
+        log::debug!("terminating network monitor ...");
+        self.network.terminate().await;
+        if let Some(screen_lock) = &mut self.screen_lock {
+            log::debug!("terminating screen lock watcher ...");
+            screen_lock.terminate().await;
+        }
+        log::debug!("terminating pairing ...");
+        self.pairing.terminate().await;
+        if let Some(discovery) = &mut self.discovery {
+            log::debug!("terminating mdns discovery ...");
+            discovery.terminate().await;
+        }
 
         Ok(())
     }
@@ -184,11 +351,16 @@ impl Service {
         match request {
             FrontendRequest::Activate(handle, active) => self.set_client_active(handle, active),
             FrontendRequest::AuthorizeKey(desc, fp) => self.add_authorized_key(desc, fp),
+            FrontendRequest::AuthorizeKeyTemporarily(desc, fp, duration) => {
+                self.add_temporary_key(desc, fp, duration)
+            }
+            FrontendRequest::AuthorizeKeyForSession(desc, fp) => self.add_session_key(desc, fp),
             FrontendRequest::ChangePort(port) => self.change_port(port),
             FrontendRequest::Create => self.add_client(),
             FrontendRequest::Delete(handle) => self.remove_client(handle),
             FrontendRequest::EnableCapture => self.capture.reenable(),
             FrontendRequest::EnableEmulation => self.emulation.reenable(),
+            FrontendRequest::Enter(handle) => self.capture.enter(handle),
             FrontendRequest::Enumerate() => self.enumerate(),
             FrontendRequest::UpdateFixIps(handle, fix_ips) => self.update_fix_ips(handle, fix_ips),
             FrontendRequest::UpdateHostname(handle, host) => self.update_hostname(handle, host),
@@ -196,10 +368,25 @@ impl Service {
             FrontendRequest::UpdatePosition(handle, pos) => self.update_pos(handle, pos),
             FrontendRequest::ResolveDns(handle) => self.resolve(handle),
             FrontendRequest::Sync => self.sync_frontend(),
+            FrontendRequest::ToggleLock => self.capture.toggle_lock(),
+            FrontendRequest::Release => self.capture.release(ReleaseReason::Requested),
             FrontendRequest::RemoveAuthorizedKey(key) => self.remove_authorized_key(key),
+            FrontendRequest::Wake(handle) => self.wake(handle),
+            FrontendRequest::EnumerateDiscovered => self.enumerate_discovered(),
+            FrontendRequest::AddDiscovered(fingerprint) => self.add_discovered(fingerprint),
+            FrontendRequest::StartPairing => self.start_pairing(),
+            FrontendRequest::Pair(addr) => self.pairing.pair(addr),
+            FrontendRequest::ConfirmPairing(fingerprint) => self.confirm_pairing(fingerprint),
+            FrontendRequest::AcceptFingerprint(handle, fingerprint) => {
+                self.accept_fingerprint(handle, fingerprint)
+            }
             FrontendRequest::UpdateEnterHook(handle, enter_hook) => {
                 self.update_enter_hook(handle, enter_hook)
             }
+            FrontendRequest::UpdatePointerConfig(handle, pointer) => {
+                self.update_pointer_config(handle, pointer)
+            }
+            FrontendRequest::QueryAudit(query) => self.query_audit(query),
         }
     }
 
@@ -211,7 +398,17 @@ impl Service {
 
     fn handle_emulation_event(&mut self, event: EmulationEvent) {
         match event {
+            EmulationEvent::PairingConnected { addr, fingerprint } => {
+                self.audit(AuditEvent::Accepted {
+                    addr,
+                    fingerprint,
+                    pairing: true,
+                });
+            }
             EmulationEvent::ConnectionAttempt { fingerprint } => {
+                self.audit(AuditEvent::Rejected {
+                    fingerprint: fingerprint.clone(),
+                });
                 self.notify_frontend(FrontendEvent::ConnectionAttempt { fingerprint });
             }
             EmulationEvent::Entered {
@@ -219,8 +416,19 @@ impl Service {
                 pos,
                 fingerprint,
             } => {
-                // check if already registered
-                if !self.incoming_conns.contains(&addr) {
+                // check if already registered, the device may have
+                // reconnected from a different address in the meantime
+                if !self
+                    .incoming_conn_info
+                    .values()
+                    .any(|i| i.fingerprint == fingerprint)
+                {
+                    self.audit(AuditEvent::Entered {
+                        addr,
+                        fingerprint: fingerprint.clone(),
+                    });
+                    // a different device may have used this address before
+                    self.remove_incoming(addr);
                     self.add_incoming(addr, pos, fingerprint.clone());
                     self.notify_frontend(FrontendEvent::DeviceEntered {
                         fingerprint,
@@ -232,13 +440,49 @@ impl Service {
                 }
             }
             EmulationEvent::Disconnected { addr } => {
+                self.audit(AuditEvent::Disconnected { addr });
+                self.end_key_session(addr);
+                self.incoming_closed(addr);
                 if let Some(addr) = self.remove_incoming(addr) {
                     self.notify_frontend(FrontendEvent::IncomingDisconnected(addr));
                 }
             }
+            EmulationEvent::KeyTransition {
+                previous,
+                fingerprint,
+            } => {
+                self.audit(AuditEvent::KeyTransition {
+                    previous: previous.clone(),
+                    fingerprint: fingerprint.clone(),
+                });
+                // a temporary authorization stays temporary
+                if let Some(expiry) = self.key_expiry.remove(&previous) {
+                    self.key_expiry.insert(fingerprint, expiry);
+                } else if let Err(e) =
+                    config::save_key_transition(&self.config_path, &previous, &fingerprint)
+                {
+                    log::warn!("{:?}: failed to save key transition: {e}", self.config_path);
+                }
+                let keys = self.authorized_keys.read().expect("lock").clone();
+                self.notify_frontend(FrontendEvent::AuthorizedUpdated(keys));
+            }
+            EmulationEvent::Revoked { addr } => {
+                self.audit(AuditEvent::Disconnected { addr });
+                // notify even if the device never entered this device
+                self.incoming_closed(addr);
+                self.remove_incoming(addr);
+                self.notify_frontend(FrontendEvent::IncomingDisconnected(addr));
+            }
+            EmulationEvent::LimitExceeded { ip, limit } => {
+                self.audit(AuditEvent::LimitExceeded { ip, limit });
+                self.notify_frontend(FrontendEvent::LimitExceeded { ip, limit });
+            }
             EmulationEvent::PortChanged(port) => match port {
                 Ok(port) => {
                     self.port = port;
+                    if let Some(discovery) = &self.discovery {
+                        discovery.change_port(port);
+                    }
                     self.notify_frontend(FrontendEvent::PortChanged(port, None));
                 }
                 Err(e) => self
@@ -252,10 +496,41 @@ impl Service {
                 self.emulation_status = Status::Enabled;
                 self.notify_frontend(FrontendEvent::EmulationStatus(self.emulation_status));
             }
-            EmulationEvent::ReleaseNotify => self.capture.release(),
+            EmulationEvent::ReleaseNotify => self.capture.release(ReleaseReason::DeviceEntered),
             EmulationEvent::Connected { addr, fingerprint } => {
+                self.audit(AuditEvent::Accepted {
+                    addr,
+                    fingerprint: fingerprint.clone(),
+                    pairing: false,
+                });
+                if let Some(KeyExpiry::Session(addrs)) = self.key_expiry.get_mut(&fingerprint) {
+                    addrs.insert(addr);
+                }
+                let addrs = self.incoming_conns.entry(fingerprint.clone()).or_default();
+                if !addrs.contains(&addr) {
+                    addrs.push(addr);
+                }
+                self.link_incoming(&fingerprint);
                 self.notify_frontend(FrontendEvent::DeviceConnected { addr, fingerprint });
             }
+            EmulationEvent::ScreenLock {
+                locked,
+                fingerprint,
+                restricted,
+            } => {
+                // temporary or restricted authorizations do not grant unlocking
+                let may_unlock = !restricted
+                    && fingerprint.is_some_and(|fp| {
+                        self.authorized_keys.read().expect("lock").contains_key(&fp)
+                            && !self.key_expiry.contains_key(&fp)
+                    });
+                self.handle_remote_screen_lock(locked, may_unlock)
+            }
+            EmulationEvent::PairingCode {
+                addr,
+                fingerprint,
+                code,
+            } => self.paired(addr, fingerprint, code),
         }
     }
 
@@ -280,7 +555,285 @@ impl Service {
                 log::info!("entering client {handle} ...");
                 self.spawn_hook_command(handle);
             }
+            ICaptureEvent::Released(handle, reason) => {
+                self.notify_frontend(FrontendEvent::CaptureReleased(handle, reason));
+            }
+            ICaptureEvent::EnterFailed(handle, reason) => {
+                self.notify_frontend(FrontendEvent::Error(format!(
+                    "can not enter client {handle}: {reason}"
+                )));
+            }
+            ICaptureEvent::ToggleEmulation => match self.emulation_status {
+                Status::Enabled => self.emulation.disable(),
+                Status::Disabled => self.emulation.reenable(),
+            },
+            // clients only receive input from this device => they can not unlock it
+            ICaptureEvent::ScreenLock(locked) => self.handle_remote_screen_lock(locked, false),
+        }
+    }
+
+    fn handle_connection_event(&mut self, event: ConnectionEvent) {
+        match event {
+            ConnectionEvent::Connected {
+                handle,
+                addr,
+                fingerprint,
+            } => self.audit(AuditEvent::Connected {
+                handle,
+                addr,
+                fingerprint,
+            }),
+            ConnectionEvent::FingerprintPinned(handle, fingerprint) => {
+                self.save_fingerprint(handle, &fingerprint);
+                self.link_incoming(&fingerprint);
+                self.broadcast_client(handle);
+            }
+            ConnectionEvent::StateChanged(handle) => self.broadcast_client(handle),
+            ConnectionEvent::FingerprintMismatch {
+                handle,
+                expected,
+                received,
+            } => {
+                // connecting is retried in the background,
+                // so each changed certificate is reported only once
+                if self.rejected_fingerprints.get(&handle) == Some(&received) {
+                    return;
+                }
+                log::warn!(
+                    "client {handle}: certificate changed (expected {expected}, received {received}), refusing to connect"
+                );
+                self.rejected_fingerprints.insert(handle, received.clone());
+                self.audit(AuditEvent::FingerprintMismatch {
+                    handle,
+                    expected: expected.clone(),
+                    received: received.clone(),
+                });
+                self.notify_frontend(FrontendEvent::FingerprintMismatch {
+                    handle,
+                    expected,
+                    received,
+                });
+            }
+        }
+    }
+
+    /// the user approved a (changed) certificate of the client
+    fn accept_fingerprint(&mut self, handle: ClientHandle, fingerprint: String) {
+        if self.client_manager.get_state(handle).is_none() {
+            return self.notify_frontend(FrontendEvent::NoSuchClient(handle));
+        }
+        log::info!("client {handle}: pinning certificate {fingerprint}");
+        self.rejected_fingerprints.remove(&handle);
+        self.client_manager
+            .set_fingerprint(handle, Some(fingerprint.clone()));
+        self.save_fingerprint(handle, &fingerprint);
+        self.link_incoming(&fingerprint);
+        self.broadcast_client(handle);
+    }
+
+    /// persist the pinned fingerprint of a client.
+    /// Clients that are not in the config file yet are added to it,
+    /// so that the pin is not lost on restart.
+    fn save_fingerprint(&mut self, handle: ClientHandle, fingerprint: &str) {
+        let Some(&index) = self.config_clients.get(&handle) else {
+            let Some((client, _)) = self.client_manager.get_state(handle) else {
+                return;
+            };
+            match config::save_client(&self.config_path, &client) {
+                Ok(index) => {
+                    log::info!("client {handle}: saved to {:?}", self.config_path);
+                    self.config_clients.insert(handle, index);
+                }
+                Err(e) => log::warn!("{:?}: failed to save client: {e}", self.config_path),
+            }
+            return;
+        };
+        if let Err(e) = config::save_client_fingerprint(&self.config_path, index, fingerprint) {
+            log::warn!("{:?}: failed to pin fingerprint: {e}", self.config_path);
+        }
+    }
+
+    /// the screen of this device was locked or unlocked => notify all peers
+    fn handle_local_screen_lock(&mut self, locked: bool) {
+        // logind and the screensaver both report the same change and
+        // a lock requested by a peer is reported back as well
+        if locked == self.screen_locked {
+            return;
+        }
+        log::info!("screen {}", if locked { "locked" } else { "unlocked" });
+        self.screen_locked = locked;
+        self.capture.send_screen_lock(locked);
+        self.emulation.send_screen_lock(locked);
+    }
+
+    /// a peer locked or unlocked its screen => do the same here.
+    /// Unlocking requires `sync_screen_unlock` and a peer that `may_unlock`.
+    fn handle_remote_screen_lock(&mut self, locked: bool, may_unlock: bool) {
+        let Some(screen_lock) = &self.screen_lock else {
+            log::debug!("ignoring remote screen lock: synchronization disabled");
+            return;
+        };
+        if locked == self.screen_locked {
+            return;
+        }
+        let may_unlock = self.sync_screen_unlock && may_unlock;
+        if !locked && !may_unlock {
+            log::info!("ignoring screen unlock requested by peer: not permitted");
+            return;
+        }
+        log::info!(
+            "{} screen: requested by peer",
+            if locked { "locking" } else { "unlocking" }
+        );
+        self.screen_locked = locked;
+        screen_lock.set_locked(locked);
+    }
+
+    fn wake(&self, handle: ClientHandle) {
+        let Some(mac) = self.client_manager.get_mac_address(handle) else {
+            return log::warn!("can not wake client {handle}: no mac address configured");
+        };
+        tokio::task::spawn_local(async move {
+            if let Err(e) = wol::wake(mac).await {
+                log::warn!("failed to send wake-on-lan packet: {e}");
+            }
+        });
+    }
+
+    fn handle_pairing_event(&mut self, event: PairingEvent) {
+        match event {
+            PairingEvent::Code {
+                addr,
+                fingerprint,
+                code,
+            } => self.paired(addr, fingerprint, code),
+            PairingEvent::Failed { addr, error } => {
+                log::warn!("pairing with {addr} failed: {error}");
+                self.notify_frontend(FrontendEvent::Error(format!(
+                    "pairing with {addr} failed: {error}"
+                )));
+            }
+        }
+    }
+
+    fn start_pairing(&mut self) {
+        log::info!("accepting pairing requests for {PAIRING_WINDOW:?}");
+        // the other device may have been banned while it was not authorized
+        self.rate_limiter.lift_bans();
+        self.emulation.allow_pairing(PAIRING_WINDOW);
+    }
+
+    /// pairing finished on either side => let the user compare the codes
+    fn paired(&mut self, addr: SocketAddr, fingerprint: String, code: String) {
+        log::info!("paired with {addr}, code: {code}");
+        let description = self
+            .discovered_peers
+            .get(&fingerprint)
+            .map(|p| p.name.clone())
+            .unwrap_or_else(|| addr.ip().to_string());
+        self.pending_pairings
+            .insert(fingerprint.clone(), description);
+        self.notify_frontend(FrontendEvent::PairingCode { fingerprint, code });
+    }
+
+    fn confirm_pairing(&mut self, fingerprint: String) {
+        let Some(description) = self.pending_pairings.remove(&fingerprint) else {
+            return log::warn!("no pending pairing for {fingerprint}");
+        };
+        self.add_authorized_key(description, fingerprint);
+    }
+
+    fn handle_discovery_event(&mut self, event: DiscoveryEvent) {
+        let DiscoveryEvent::PeerDiscovered {
+            name,
+            fingerprint,
+            addr,
+        } = event;
+        let peer = DiscoveredPeer {
+            name,
+            fingerprint: fingerprint.clone(),
+            ip: addr.ip(),
+            port: addr.port(),
+            pos: self.unused_position(),
+        };
+        self.discovered_peers
+            .insert(fingerprint.clone(), peer.clone());
+        // the address of a known device is tried when connecting to it
+        if let Some(handle) = self.client_manager.get_client(&fingerprint) {
+            self.client_manager.add_seen_ip(handle, peer.ip);
+            self.broadcast_client(handle);
+        }
+        if !self.is_configured(&peer) {
+            self.notify_frontend(FrontendEvent::PeerDiscovered(peer));
+        }
+    }
+
+    /// whether a discovered peer is already set up as a client.
+    /// Clients without a pinned certificate are recognized by their address.
+    fn is_configured(&self, peer: &DiscoveredPeer) -> bool {
+        self.client_manager.get_client(&peer.fingerprint).is_some()
+            || self
+                .client_manager
+                .get_client_states()
+                .iter()
+                .any(|(_, c, s)| {
+                    c.fingerprint.is_none() && c.port == peer.port && s.ips.contains(&peer.ip)
+                })
+    }
+
+    /// first position not occupied by a client
+    fn unused_position(&self) -> Position {
+        let used = self
+            .client_manager
+            .get_client_states()
+            .into_iter()
+            .map(|(_, c, _)| c.pos)
+            .collect::<HashSet<_>>();
+        [
+            Position::Right,
+            Position::Left,
+            Position::Top,
+            Position::Bottom,
+        ]
+        .into_iter()
+        .find(|p| !used.contains(p))
+        .unwrap_or_default()
+    }
+
+    fn enumerate_discovered(&mut self) {
+        let peers = self
+            .discovered_peers
+            .values()
+            .filter(|p| !self.is_configured(p))
+            .map(|p| DiscoveredPeer {
+                pos: self.unused_position(),
+                ..p.clone()
+            })
+            .collect::<Vec<_>>();
+        for peer in peers {
+            self.notify_frontend(FrontendEvent::PeerDiscovered(peer));
+        }
+    }
+
+    fn add_discovered(&mut self, fingerprint: String) {
+        let Some(peer) = self.discovered_peers.get(&fingerprint).cloned() else {
+            return log::warn!("no discovered peer with fingerprint {fingerprint}");
+        };
+        let pos = self.unused_position();
+        let handle = self.client_manager.add_client();
+        log::info!("added discovered peer {} as client {handle}", peer.name);
+        self.client_manager.set_fix_ips(handle, vec![peer.ip]);
+        self.client_manager.set_port(handle, peer.port);
+        self.client_manager.set_pos(handle, pos);
+        // the advertised certificate identifies the device
+        self.client_manager
+            .set_fingerprint(handle, Some(fingerprint.clone()));
+        self.link_incoming(&fingerprint);
+        if self.client_manager.set_hostname(handle, Some(peer.name)) {
+            self.resolve(handle);
         }
+        let (c, s) = self.client_manager.get_state(handle).expect("client");
+        self.notify_frontend(FrontendEvent::Created(handle, c, s));
     }
 
     fn handle_resolver_event(&mut self, event: DnsEvent) {
@@ -303,11 +856,19 @@ impl Service {
     }
 
     fn resolve(&self, handle: ClientHandle) {
-        if let Some(hostname) = self.client_manager.get_hostname(handle) {
-            self.resolver.resolve(handle, hostname);
+        match self.client_manager.get_hostname(handle) {
+            Some(hostname) => self.resolver.resolve(handle, hostname),
+            None => self.resolver.forget(handle),
         }
     }
 
+    /// addresses of clients may have changed as well
+    /// and clients unreachable before may be reachable now
+    fn handle_network_change(&mut self) {
+        self.resolver.refresh();
+        self.network_changed.notify_one();
+    }
+
     fn sync_frontend(&mut self) {
         self.enumerate();
         self.notify_frontend(FrontendEvent::EmulationStatus(self.emulation_status));
@@ -318,6 +879,28 @@ impl Service {
         ));
         let keys = self.authorized_keys.read().expect("lock").clone();
         self.notify_frontend(FrontendEvent::AuthorizedUpdated(keys));
+        self.enumerate_discovered();
+        if let Some(days_left) = self.cert_expiring() {
+            self.notify_frontend(FrontendEvent::CertificateExpiring { days_left });
+        }
+    }
+
+    /// days until the certificate of this device expires, if it expires soon
+    fn cert_expiring(&self) -> Option<i64> {
+        let days_left = crypto::days_until(self.cert_expiry?);
+        (days_left < CERT_EXPIRY_WARNING_DAYS).then_some(days_left)
+    }
+
+    fn check_cert_expiry(&mut self) {
+        let Some(days_left) = self.cert_expiring() else {
+            return;
+        };
+        match days_left {
+            d if d < 0 => log::warn!("the certificate of this device has expired"),
+            d => log::warn!("the certificate of this device expires in {d} days"),
+        }
+        log::warn!("use `lan-mouse cert rotate` (or renew it with your CA) to replace it");
+        self.notify_frontend(FrontendEvent::CertificateExpiring { days_left });
     }
 
     const ENTER_HANDLE_BEGIN: u64 = u64::MAX / 2 + 1;
@@ -326,7 +909,6 @@ impl Service {
         let handle = Self::ENTER_HANDLE_BEGIN + self.next_trigger_handle;
         self.next_trigger_handle += 1;
         self.capture.create(handle, pos, CaptureType::EnterOnly);
-        self.incoming_conns.insert(addr);
         self.incoming_conn_info.insert(
             handle,
             Incoming {
@@ -340,23 +922,14 @@ impl Service {
     fn update_incoming(&mut self, addr: SocketAddr, pos: Position, fingerprint: String) {
         let incoming = self
             .incoming_conn_info
-            .iter_mut()
-            .find(|(_, i)| i.addr == addr)
-            .map(|(_, i)| i)
+            .values()
+            .find(|i| i.fingerprint == fingerprint)
             .expect("no such client");
-        let mut changed = false;
-        if incoming.fingerprint != fingerprint {
-            incoming.fingerprint = fingerprint.clone();
-            changed = true;
-        }
-        if incoming.pos != pos {
-            incoming.pos = pos;
-            changed = true;
-        }
-        if changed {
-            self.remove_incoming(addr);
+        let previous = incoming.addr;
+        if incoming.addr != addr || incoming.pos != pos {
+            self.remove_incoming(previous);
             self.add_incoming(addr, pos, fingerprint.clone());
-            self.notify_frontend(FrontendEvent::IncomingDisconnected(addr));
+            self.notify_frontend(FrontendEvent::IncomingDisconnected(previous));
             self.notify_frontend(FrontendEvent::DeviceEntered {
                 fingerprint,
                 addr,
@@ -372,29 +945,181 @@ impl Service {
             .find(|(_, incoming)| incoming.addr == addr)
             .map(|(k, _)| *k)?;
         self.capture.destroy(handle);
-        self.incoming_conns.remove(&addr);
         self.incoming_conn_info
             .remove(&handle)
             .map(|incoming| incoming.addr)
     }
 
+    /// an incoming connection was closed. If the device is connected
+    /// from another address as well, it is still connected.
+    fn incoming_closed(&mut self, addr: SocketAddr) {
+        let Some((fingerprint, addrs)) = self
+            .incoming_conns
+            .iter_mut()
+            .find(|(_, addrs)| addrs.contains(&addr))
+        else {
+            return;
+        };
+        addrs.retain(|a| *a != addr);
+        let fingerprint = fingerprint.clone();
+        if addrs.is_empty() {
+            self.incoming_conns.remove(&fingerprint);
+        }
+        self.link_incoming(&fingerprint);
+    }
+
+    /// the client with the same certificate as an incoming connection
+    /// is the same device => show both as one
+    fn link_incoming(&mut self, fingerprint: &str) {
+        let Some(handle) = self.client_manager.get_client(fingerprint) else {
+            return;
+        };
+        // the most recent connection is shown
+        let addr = self
+            .incoming_conns
+            .get(fingerprint)
+            .and_then(|addrs| addrs.last())
+            .copied();
+        self.client_manager.set_incoming(handle, addr);
+        self.broadcast_client(handle);
+    }
+
     fn notify_frontend(&mut self, event: FrontendEvent) {
         self.pending_frontend_events.push_back(event);
         self.frontend_event_pending.notify_one();
     }
 
     fn add_authorized_key(&mut self, desc: String, fp: String) {
-        self.authorized_keys.write().expect("lock").insert(fp, desc);
+        self.audit(AuditEvent::Authorized {
+            fingerprint: fp.clone(),
+            description: desc.clone(),
+            expires_after: None,
+            session: false,
+        });
+        if let Err(e) = config::save_authorized_key(&self.config_path, &fp, &desc) {
+            log::warn!("{:?}: failed to save authorized key: {e}", self.config_path);
+        }
+        self.authorize_key(desc, fp);
+    }
+
+    fn add_temporary_key(&mut self, desc: String, fp: String, duration: Duration) {
+        log::info!("authorizing {fp} for {}s", duration.as_secs());
+        self.audit(AuditEvent::Authorized {
+            fingerprint: fp.clone(),
+            description: desc.clone(),
+            expires_after: Some(duration.as_secs()),
+            session: false,
+        });
+        self.authorize_key(desc, fp.clone());
+        self.key_expiry
+            .insert(fp, KeyExpiry::At(Instant::now() + duration));
+    }
+
+    fn add_session_key(&mut self, desc: String, fp: String) {
+        log::info!("authorizing {fp} until it disconnects");
+        self.audit(AuditEvent::Authorized {
+            fingerprint: fp.clone(),
+            description: desc.clone(),
+            expires_after: None,
+            session: true,
+        });
+        self.authorize_key(desc, fp.clone());
+        self.key_expiry
+            .insert(fp, KeyExpiry::Session(Default::default()));
+    }
+
+    fn authorize_key(&mut self, desc: String, fp: String) {
+        // the device may have been banned while it was not authorized
+        self.rate_limiter.lift_bans();
+        self.key_expiry.remove(&fp);
+        self.authorized_keys
+            .write()
+            .expect("lock")
+            .insert(fp.clone(), desc);
+        // connections accepted for pairing may send input from now on
+        self.emulation.authorize(fp);
         let keys = self.authorized_keys.read().expect("lock").clone();
         self.notify_frontend(FrontendEvent::AuthorizedUpdated(keys));
     }
 
+    fn next_key_expiry(&self) -> Option<Instant> {
+        self.key_expiry
+            .values()
+            .filter_map(|e| match e {
+                KeyExpiry::At(t) => Some(*t),
+                KeyExpiry::Session(_) => None,
+            })
+            .min()
+    }
+
+    fn revoke_expired_keys(&mut self) {
+        let now = Instant::now();
+        let expired = self
+            .key_expiry
+            .iter()
+            .filter(|(_, e)| matches!(e, KeyExpiry::At(t) if *t <= now))
+            .map(|(fp, _)| fp.clone())
+            .collect::<Vec<_>>();
+        for fp in expired {
+            log::info!("authorization of {fp} expired");
+            self.remove_authorized_key(fp);
+        }
+    }
+
+    /// revoke the session authorization of the device connected from `addr`
+    fn end_key_session(&mut self, addr: SocketAddr) {
+        let ended = self.key_expiry.iter().find_map(|(fp, e)| match e {
+            KeyExpiry::Session(addrs) if addrs.contains(&addr) => Some(fp.clone()),
+            _ => None,
+        });
+        if let Some(fp) = ended {
+            log::info!("session of {fp} ended");
+            self.remove_authorized_key(fp);
+        }
+    }
+
     fn remove_authorized_key(&mut self, fp: String) {
-        self.authorized_keys.write().expect("lock").remove(&fp);
+        // temporary authorizations are not saved
+        if self.key_expiry.remove(&fp).is_none() {
+            if let Err(e) = config::remove_authorized_key(&self.config_path, &fp) {
+                log::warn!(
+                    "{:?}: failed to remove authorized key: {e}",
+                    self.config_path
+                );
+            }
+        }
+        if self
+            .authorized_keys
+            .write()
+            .expect("lock")
+            .remove(&fp)
+            .is_some()
+        {
+            self.audit(AuditEvent::Deauthorized {
+                fingerprint: fp.clone(),
+            });
+        }
+        // connected devices must not keep sending input
+        self.emulation.revoke(fp);
         let keys = self.authorized_keys.read().expect("lock").clone();
         self.notify_frontend(FrontendEvent::AuthorizedUpdated(keys));
     }
 
+    fn audit(&mut self, event: AuditEvent) {
+        if let Some(audit) = &mut self.audit {
+            audit.record(event);
+        }
+    }
+
+    fn query_audit(&mut self, query: AuditQuery) {
+        let Some(audit) = &self.audit else {
+            return self
+                .notify_frontend(FrontendEvent::Error("the audit log is disabled".to_owned()));
+        };
+        let entries = audit.query(&query);
+        self.notify_frontend(FrontendEvent::AuditLog(entries));
+    }
+
     fn enumerate(&mut self) {
         let clients = self.client_manager.get_client_states();
         self.notify_frontend(FrontendEvent::Enumerate(clients));
@@ -467,6 +1192,10 @@ impl Service {
         {
             self.capture.destroy(handle);
         }
+        // handles are reused for new clients
+        self.resolver.forget(handle);
+        self.config_clients.remove(&handle);
+        self.rejected_fingerprints.remove(&handle);
         self.notify_frontend(FrontendEvent::Deleted(handle));
     }
 
@@ -502,6 +1231,17 @@ impl Service {
         self.broadcast_client(handle);
     }
 
+    fn update_pointer_config(&mut self, handle: ClientHandle, pointer: PointerConfig) {
+        let sanitized = pointer.sanitized();
+        if sanitized != pointer {
+            log::warn!(
+                "client {handle}: invalid pointer settings {pointer:?}, using {sanitized:?}"
+            );
+        }
+        self.client_manager.set_pointer_config(handle, sanitized);
+        self.broadcast_client(handle);
+    }
+
     fn broadcast_client(&mut self, handle: ClientHandle) {
         let event = self
             .client_manager
@@ -537,3 +1277,24 @@ impl Service {
         });
     }
 }
+
+async fn screen_lock_event(screen_lock: &mut Option<ScreenLock>) -> bool {
+    match screen_lock {
+        Some(screen_lock) => screen_lock.event().await,
+        None => future::pending().await,
+    }
+}
+
+async fn key_expiry(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline.into()).await,
+        None => future::pending().await,
+    }
+}
+
+async fn discovery_event(discovery: &mut Option<Discovery>) -> DiscoveryEvent {
+    match discovery {
+        Some(discovery) => discovery.event().await,
+        None => future::pending().await,
+    }
+}
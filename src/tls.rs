@@ -0,0 +1,481 @@
+use std::{
+    any::Any,
+    fmt,
+    io::{self, ErrorKind, Read, Write},
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use rustls::{
+    CertificateError, ClientConfig, ClientConnection, Connection, DigitallySignedStruct,
+    DistinguishedName, ServerConfig, ServerConnection, SignatureScheme, SupportedProtocolVersion,
+    client::{
+        Resumption,
+        danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    },
+    crypto::{self, WebPkiSupportedAlgorithms},
+    pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer, ServerName, UnixTime},
+    server::{
+        NoServerSessionStorage,
+        danger::{ClientCertVerified, ClientCertVerifier},
+    },
+    version,
+};
+use thiserror::Error;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{
+        TcpStream,
+        tcp::{OwnedReadHalf, OwnedWriteHalf},
+    },
+    sync::Mutex as AsyncMutex,
+};
+use tokio_util::sync::CancellationToken;
+use webrtc_dtls::{conn::DTLSConn, crypto::Certificate};
+use webrtc_util::{Conn, Error};
+
+use crate::listen::VerifyPeerCertificateFn;
+
+/// time until the TLS handshake must be completed
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// TLS 1.2 only: as with DTLS 1.2, a client learns during the handshake
+/// whether its certificate was accepted, while TLS 1.3 reports it only
+/// after the client considers the connection established
+const PROTOCOL_VERSIONS: &[&SupportedProtocolVersion] = &[&version::TLS12];
+
+/// size of the length prefix of each frame
+const FRAME_HEADER: usize = size_of::<u16>();
+
+#[derive(Debug, Error)]
+pub(crate) enum TlsError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Tls(#[from] rustls::Error),
+    #[error("TLS handshake timed out")]
+    Timeout,
+}
+
+impl TlsError {
+    /// the certificate of the other device was not accepted
+    pub(crate) fn is_rejection(&self) -> bool {
+        matches!(
+            self,
+            TlsError::Tls(rustls::Error::InvalidCertificate(
+                CertificateError::ApplicationVerificationFailure
+            ))
+        )
+    }
+}
+
+/// TLS over TCP connection, used where UDP is unavailable.
+///
+/// Events are sent as frames with a 16 bit big endian length prefix,
+/// so each `recv` returns exactly one event as with DTLS.
+pub(crate) struct TlsConn {
+    state: Mutex<TlsState>,
+    reader: AsyncMutex<OwnedReadHalf>,
+    /// held while records are sealed and written, to keep them in order
+    writer: AsyncMutex<OwnedWriteHalf>,
+    local_addr: SocketAddr,
+    peer_addr: SocketAddr,
+    peer_certificates: Vec<Vec<u8>>,
+    closed: CancellationToken,
+}
+
+struct TlsState {
+    conn: Connection,
+    /// received plaintext, not yet split into frames
+    received: Vec<u8>,
+}
+
+impl TlsState {
+    fn next_frame(&mut self) -> Option<Vec<u8>> {
+        let header = self.received.get(..FRAME_HEADER)?;
+        let len = u16::from_be_bytes([header[0], header[1]]) as usize;
+        if self.received.len() < FRAME_HEADER + len {
+            return None;
+        }
+        let frame = self.received[FRAME_HEADER..FRAME_HEADER + len].to_vec();
+        self.received.drain(..FRAME_HEADER + len);
+        Some(frame)
+    }
+
+    /// records waiting to be sent
+    fn take_tls(&mut self) -> io::Result<Vec<u8>> {
+        let mut out = Vec::new();
+        while self.conn.wants_write() {
+            self.conn.write_tls(&mut out)?;
+        }
+        Ok(out)
+    }
+
+    /// decrypt received records, returns false if the other device closed the connection
+    fn process(&mut self, mut data: &[u8]) -> Result<bool, TlsError> {
+        while !data.is_empty() {
+            self.conn.read_tls(&mut data)?;
+            self.conn.process_new_packets()?;
+        }
+        let mut buf = [0u8; 4096];
+        loop {
+            match self.conn.reader().read(&mut buf) {
+                Ok(0) => return Ok(false),
+                Ok(n) => self.received.extend_from_slice(&buf[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(true),
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+}
+
+impl TlsConn {
+    async fn new(mut stream: TcpStream, mut conn: Connection) -> Result<Self, TlsError> {
+        tokio::time::timeout(HANDSHAKE_TIMEOUT, handshake(&mut stream, &mut conn))
+            .await
+            .map_err(|_| TlsError::Timeout)??;
+        let local_addr = stream.local_addr()?;
+        let peer_addr = stream.peer_addr()?;
+        let peer_certificates = conn
+            .peer_certificates()
+            .unwrap_or_default()
+            .iter()
+            .map(|c| c.to_vec())
+            .collect();
+        let (reader, writer) = stream.into_split();
+        Ok(Self {
+            state: Mutex::new(TlsState {
+                conn,
+                received: Vec::new(),
+            }),
+            reader: AsyncMutex::new(reader),
+            writer: AsyncMutex::new(writer),
+            local_addr,
+            peer_addr,
+            peer_certificates,
+            closed: CancellationToken::new(),
+        })
+    }
+
+    /// write records queued by the TLS state machine, e.g. alerts or key updates
+    async fn flush(&self) -> io::Result<()> {
+        let mut writer = self.writer.lock().await;
+        let out = self.state.lock().expect("lock").take_tls()?;
+        writer.write_all(&out).await
+    }
+}
+
+#[async_trait]
+impl Conn for TlsConn {
+    async fn connect(&self, _addr: SocketAddr) -> Result<(), Error> {
+        Err(Error::Other("Not applicable".to_owned()))
+    }
+
+    async fn recv(&self, buf: &mut [u8]) -> Result<usize, Error> {
+        let mut reader = self.reader.lock().await;
+        let mut data = [0u8; 4096];
+        loop {
+            let (frame, pending) = {
+                let mut state = self.state.lock().expect("lock");
+                (state.next_frame(), state.conn.wants_write())
+            };
+            if pending {
+                self.flush().await?;
+            }
+            if let Some(frame) = frame {
+                let n = frame.len().min(buf.len());
+                buf[..n].copy_from_slice(&frame[..n]);
+                return Ok(n);
+            }
+            let n = tokio::select! {
+                n = reader.read(&mut data) => n?,
+                _ = self.closed.cancelled() => return Err(Error::ErrUseClosedNetworkConn),
+            };
+            if n == 0 {
+                return Err(io::Error::from(ErrorKind::UnexpectedEof).into());
+            }
+            let open = self
+                .state
+                .lock()
+                .expect("lock")
+                .process(&data[..n])
+                .map_err(Error::from_std)?;
+            if !open {
+                self.closed.cancel();
+            }
+        }
+    }
+
+    async fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr), Error> {
+        let n = self.recv(buf).await?;
+        Ok((n, self.peer_addr))
+    }
+
+    async fn send(&self, buf: &[u8]) -> Result<usize, Error> {
+        if self.closed.is_cancelled() {
+            return Err(Error::ErrUseClosedNetworkConn);
+        }
+        let len = u16::try_from(buf.len()).map_err(|_| Error::Other("frame too large".into()))?;
+        let mut writer = self.writer.lock().await;
+        let out = {
+            let mut state = self.state.lock().expect("lock");
+            let mut plaintext = state.conn.writer();
+            plaintext.write_all(&len.to_be_bytes())?;
+            plaintext.write_all(buf)?;
+            state.take_tls()?
+        };
+        writer.write_all(&out).await?;
+        Ok(buf.len())
+    }
+
+    async fn send_to(&self, _buf: &[u8], _target: SocketAddr) -> Result<usize, Error> {
+        Err(Error::Other("Not applicable".to_owned()))
+    }
+
+    fn local_addr(&self) -> Result<SocketAddr, Error> {
+        Ok(self.local_addr)
+    }
+
+    fn remote_addr(&self) -> Option<SocketAddr> {
+        Some(self.peer_addr)
+    }
+
+    async fn close(&self) -> Result<(), Error> {
+        self.closed.cancel();
+        let mut writer = self.writer.lock().await;
+        let out = {
+            let mut state = self.state.lock().expect("lock");
+            state.conn.send_close_notify();
+            state.take_tls()?
+        };
+        let _ = writer.write_all(&out).await;
+        let _ = writer.shutdown().await;
+        Ok(())
+    }
+
+    fn as_any(&self) -> &(dyn Any + Send + Sync) {
+        self
+    }
+}
+
+async fn handshake(stream: &mut TcpStream, conn: &mut Connection) -> Result<(), TlsError> {
+    let mut buf = [0u8; 4096];
+    loop {
+        while conn.wants_write() {
+            let mut out = Vec::new();
+            conn.write_tls(&mut out)?;
+            stream.write_all(&out).await?;
+        }
+        if !conn.is_handshaking() {
+            return Ok(());
+        }
+        let n = stream.read(&mut buf).await?;
+        if n == 0 {
+            return Err(io::Error::from(ErrorKind::UnexpectedEof).into());
+        }
+        let mut data = &buf[..n];
+        while !data.is_empty() {
+            conn.read_tls(&mut data)?;
+            if let Err(e) = conn.process_new_packets() {
+                // let the other device know why the handshake failed
+                let mut alert = Vec::new();
+                let _ = conn.write_tls(&mut alert);
+                let _ = stream.write_all(&alert).await;
+                return Err(e.into());
+            }
+        }
+    }
+}
+
+/// connect to `addr` over TCP and perform the TLS handshake
+pub(crate) async fn connect(
+    addr: SocketAddr,
+    config: Arc<ClientConfig>,
+) -> Result<TlsConn, TlsError> {
+    let stream = tokio::time::timeout(HANDSHAKE_TIMEOUT, TcpStream::connect(addr))
+        .await
+        .map_err(|_| TlsError::Timeout)??;
+    stream.set_nodelay(true)?;
+    let name = ServerName::try_from("ignored").expect("valid server name");
+    let conn = ClientConnection::new(config, name)?;
+    TlsConn::new(stream, conn.into()).await
+}
+
+/// perform the TLS handshake of an incoming TCP connection
+pub(crate) async fn accept(
+    stream: TcpStream,
+    config: Arc<ServerConfig>,
+) -> Result<TlsConn, TlsError> {
+    stream.set_nodelay(true)?;
+    let conn = ServerConnection::new(config)?;
+    TlsConn::new(stream, conn.into()).await
+}
+
+/// TLS configuration of the listening side. Client certificates are required
+/// and checked with the same function as DTLS client certificates.
+pub(crate) fn server_config(
+    cert: &Certificate,
+    verify: VerifyPeerCertificateFn,
+) -> Result<Arc<ServerConfig>, rustls::Error> {
+    let provider = Arc::new(crypto::ring::default_provider());
+    let verifier = PeerVerifier {
+        verify: Some(verify),
+        algorithms: provider.signature_verification_algorithms,
+    };
+    let (chain, key) = certificate_and_key(cert);
+    let mut config = ServerConfig::builder_with_provider(provider)
+        .with_protocol_versions(PROTOCOL_VERSIONS)?
+        .with_client_cert_verifier(Arc::new(verifier))
+        .with_single_cert(chain, key)?;
+    // authorization is checked on every handshake
+    config.session_storage = Arc::new(NoServerSessionStorage {});
+    config.send_tls13_tickets = 0;
+    Ok(Arc::new(config))
+}
+
+/// TLS configuration of the connecting side.
+/// Certificates are self-signed, so instead of a certificate store
+/// the server certificate is checked by `verify`, if given.
+pub(crate) fn client_config(
+    cert: &Certificate,
+    verify: Option<VerifyPeerCertificateFn>,
+) -> Result<Arc<ClientConfig>, rustls::Error> {
+    let provider = Arc::new(crypto::ring::default_provider());
+    let verifier = PeerVerifier {
+        verify,
+        algorithms: provider.signature_verification_algorithms,
+    };
+    let (chain, key) = certificate_and_key(cert);
+    let mut config = ClientConfig::builder_with_provider(provider)
+        .with_protocol_versions(PROTOCOL_VERSIONS)?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(verifier))
+        .with_client_auth_cert(chain, key)?;
+    config.resumption = Resumption::disabled();
+    Ok(Arc::new(config))
+}
+
+fn certificate_and_key(
+    cert: &Certificate,
+) -> (Vec<CertificateDer<'static>>, PrivateKeyDer<'static>) {
+    let key = PrivatePkcs8KeyDer::from(cert.private_key.serialized_der.clone());
+    (cert.certificate.clone(), PrivateKeyDer::Pkcs8(key))
+}
+
+/// certificate chain presented by the other device of a DTLS or TLS connection
+pub(crate) async fn peer_certificates(conn: &Arc<dyn Conn + Send + Sync>) -> Vec<Vec<u8>> {
+    if let Some(tls_conn) = conn.as_any().downcast_ref::<TlsConn>() {
+        return tls_conn.peer_certificates.clone();
+    }
+    let dtls_conn: &DTLSConn = conn.as_any().downcast_ref().expect("dtls conn");
+    dtls_conn.connection_state().await.peer_certificates
+}
+
+/// checks certificates of the other device with a [`VerifyPeerCertificateFn`]
+/// and handshake signatures with the supported algorithms
+struct PeerVerifier {
+    verify: Option<VerifyPeerCertificateFn>,
+    algorithms: WebPkiSupportedAlgorithms,
+}
+
+impl PeerVerifier {
+    fn verify_chain(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+    ) -> Result<(), rustls::Error> {
+        let Some(verify) = &self.verify else {
+            return Ok(());
+        };
+        let certs: Vec<Vec<u8>> = std::iter::once(end_entity)
+            .chain(intermediates)
+            .map(|c| c.to_vec())
+            .collect();
+        verify(&certs, &[]).map_err(|e| {
+            log::debug!("certificate verification failed: {e}");
+            rustls::Error::InvalidCertificate(CertificateError::ApplicationVerificationFailure)
+        })
+    }
+}
+
+impl fmt::Debug for PeerVerifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PeerVerifier").finish_non_exhaustive()
+    }
+}
+
+impl ServerCertVerifier for PeerVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        self.verify_chain(end_entity, intermediates)?;
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        crypto::verify_tls12_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        crypto::verify_tls13_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.algorithms.supported_schemes()
+    }
+}
+
+impl ClientCertVerifier for PeerVerifier {
+    fn root_hint_subjects(&self) -> &[DistinguishedName] {
+        &[]
+    }
+
+    fn verify_client_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        _now: UnixTime,
+    ) -> Result<ClientCertVerified, rustls::Error> {
+        self.verify_chain(end_entity, intermediates)?;
+        Ok(ClientCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        crypto::verify_tls12_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        crypto::verify_tls13_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.algorithms.supported_schemes()
+    }
+}
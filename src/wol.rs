@@ -0,0 +1,31 @@
+use std::{
+    io,
+    net::{Ipv4Addr, SocketAddr},
+};
+
+use lan_mouse_ipc::MacAddress;
+use tokio::net::UdpSocket;
+
+/// port commonly used for Wake-on-LAN ("discard")
+const WOL_PORT: u16 = 9;
+
+/// a magic packet consists of 6 bytes of 0xff
+/// followed by 16 repetitions of the target's mac address
+fn magic_packet(mac: MacAddress) -> [u8; 102] {
+    let mut packet = [0xff; 102];
+    for chunk in packet[6..].chunks_exact_mut(6) {
+        chunk.copy_from_slice(&mac.0);
+    }
+    packet
+}
+
+/// broadcast a Wake-on-LAN magic packet for the given mac address
+pub(crate) async fn wake(mac: MacAddress) -> io::Result<()> {
+    let socket = UdpSocket::bind(SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0))).await?;
+    socket.set_broadcast(true)?;
+    socket
+        .send_to(&magic_packet(mac), (Ipv4Addr::BROADCAST, WOL_PORT))
+        .await?;
+    log::info!("sent wake-on-lan packet to {mac}");
+    Ok(())
+}